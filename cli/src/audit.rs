@@ -0,0 +1,194 @@
+use extract::{AccessibilityAudit, AuditIssue, AuditIssueKind, ImageBounds};
+
+use crate::info::format_points;
+use crate::json::{json_array, json_object, json_optional_string, json_string};
+
+pub fn format_audit_human(audit: &AccessibilityAudit) -> String {
+    let mut lines = vec![
+        format!("Tagged: {}", if audit.tagged { "yes" } else { "no" }),
+        format!("Images: {}", audit.image_count),
+        format!("Figures: {}", audit.figure_count),
+        format!("Issues: {}", audit.issues.len()),
+    ];
+    for issue in &audit.issues {
+        lines.push(format!("  {}", describe_issue(issue)));
+    }
+    lines.join("\n")
+}
+
+fn describe_issue(issue: &AuditIssue) -> String {
+    let mut text = match issue.page_index {
+        Some(page_index) => format!("page {}", page_index + 1),
+        None => "unknown page".to_string(),
+    };
+    // ラベルが物理ページ番号と同じなら省く
+    if let (Some(page_index), Some(label)) = (issue.page_index, &issue.page_label)
+        && *label != (page_index + 1).to_string()
+    {
+        text.push_str(&format!(" (label {label})"));
+    }
+    text.push_str(": ");
+    text.push_str(match issue.kind {
+        AuditIssueKind::MissingAltText => "missing alt text",
+        AuditIssueKind::UntaggedImage => "untagged image",
+        AuditIssueKind::DecorativeNotArtifact => "decorative image not marked as artifact",
+    });
+    if let Some(image_index) = issue.image_index {
+        text.push_str(&format!(", image {}", image_index + 1));
+    }
+    if let Some(object) = issue.object {
+        text.push_str(&format!(" ({object})"));
+    }
+    if let Some(bounds) = issue.bounds {
+        text.push_str(&format!(
+            " at {}, {} - {}, {} pt",
+            format_points(bounds.x0),
+            format_points(bounds.y0),
+            format_points(bounds.x1),
+            format_points(bounds.y1)
+        ));
+    }
+    if let Some(role) = &issue.role {
+        text.push_str(&format!(", element {role}"));
+        if let Some(element) = issue.element {
+            text.push_str(&format!(" ({element})"));
+        }
+    }
+    text
+}
+
+pub fn format_audit_json(audit: &AccessibilityAudit) -> String {
+    let issues = audit.issues.iter().map(|issue| {
+        json_object([
+            ("kind", json_string(issue_kind_code(issue.kind))),
+            (
+                "page",
+                issue.page_index.map_or_else(
+                    || "null".to_string(),
+                    |page_index| (page_index + 1).to_string(),
+                ),
+            ),
+            (
+                "pageLabel",
+                json_optional_string(issue.page_label.as_deref()),
+            ),
+            (
+                "image",
+                issue.image_index.map_or_else(
+                    || "null".to_string(),
+                    |image_index| (image_index + 1).to_string(),
+                ),
+            ),
+            (
+                "bounds",
+                issue
+                    .bounds
+                    .map_or_else(|| "null".to_string(), |bounds| bounds_json(&bounds)),
+            ),
+            (
+                "object",
+                json_optional_string(issue.object.map(|object| object.to_string()).as_deref()),
+            ),
+            (
+                "element",
+                json_optional_string(issue.element.map(|element| element.to_string()).as_deref()),
+            ),
+            ("role", json_optional_string(issue.role.as_deref())),
+        ])
+    });
+    json_object([
+        ("tagged", audit.tagged.to_string()),
+        ("imageCount", audit.image_count.to_string()),
+        ("figureCount", audit.figure_count.to_string()),
+        ("issues", json_array(issues)),
+    ])
+}
+
+fn issue_kind_code(kind: AuditIssueKind) -> &'static str {
+    match kind {
+        AuditIssueKind::MissingAltText => "missing_alt_text",
+        AuditIssueKind::UntaggedImage => "untagged_image",
+        AuditIssueKind::DecorativeNotArtifact => "decorative_not_artifact",
+    }
+}
+
+fn bounds_json(bounds: &ImageBounds) -> String {
+    json_object([
+        ("x0", format_points(bounds.x0)),
+        ("y0", format_points(bounds.y0)),
+        ("x1", format_points(bounds.x1)),
+        ("y1", format_points(bounds.y1)),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{describe_issue, format_audit_json};
+    use extract::{AccessibilityAudit, AuditIssue, AuditIssueKind, ImageBounds, ObjectRef};
+
+    fn untagged_image() -> AuditIssue {
+        AuditIssue {
+            kind: AuditIssueKind::UntaggedImage,
+            page_index: Some(1),
+            page_label: Some("ii".to_string()),
+            image_index: Some(0),
+            bounds: Some(ImageBounds {
+                x0: 10.0,
+                y0: 20.5,
+                x1: 110.0,
+                y1: 70.5,
+            }),
+            object: Some(ObjectRef {
+                number: 8,
+                generation: 0,
+            }),
+            element: None,
+            role: None,
+        }
+    }
+
+    #[test]
+    fn describes_issues_with_page_and_placement() {
+        assert_eq!(
+            describe_issue(&untagged_image()),
+            "page 2 (label ii): untagged image, image 1 (8 0 R) at 10, 20.5 - 110, 70.5 pt"
+        );
+
+        let figure = AuditIssue {
+            kind: AuditIssueKind::MissingAltText,
+            page_index: None,
+            page_label: None,
+            image_index: None,
+            bounds: None,
+            object: None,
+            element: Some(ObjectRef {
+                number: 14,
+                generation: 0,
+            }),
+            role: Some("Figure".to_string()),
+        };
+        assert_eq!(
+            describe_issue(&figure),
+            "unknown page: missing alt text, element Figure (14 0 R)"
+        );
+    }
+
+    #[test]
+    fn formats_json_report() {
+        let audit = AccessibilityAudit {
+            tagged: false,
+            image_count: 1,
+            figure_count: 0,
+            issues: vec![untagged_image()],
+            warnings: Vec::new(),
+        };
+        assert_eq!(
+            format_audit_json(&audit),
+            concat!(
+                r#"{"tagged":false,"imageCount":1,"figureCount":0,"issues":[{"kind":"untagged_image","#,
+                r#""page":2,"pageLabel":"ii","image":1,"bounds":{"x0":10,"y0":20.5,"x1":110,"y1":70.5},"#,
+                r#""object":"8 0 R","element":null,"role":null}]}"#
+            )
+        );
+    }
+}
@@ -1,14 +1,348 @@
-pub fn build_extracted_image_file_name(page_index: usize, image_index: usize) -> String {
-    format!("page-{}-image-{}.png", page_index + 1, image_index + 1)
+use extract::{ExtractedImage, ObjectRef, UnusedImageKind};
+
+// ファイル名のページ部分。ラベルを使う場合もファイル名に使えない文字は"_"にする
+pub fn page_name(page_index: usize, page_label: &str, use_page_labels: bool) -> String {
+    if use_page_labels && !page_label.is_empty() {
+        page_label
+            .chars()
+            .map(|c| {
+                if c.is_alphanumeric() || matches!(c, '-' | '_' | '.') {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect()
+    } else {
+        (page_index + 1).to_string()
+    }
+}
+
+// キャプションから作るファイル名の部分の最大文字数
+const MAX_CAPTION_NAME_CHARS: usize = 48;
+
+// キャプションを小文字にして、文字と数字以外を"-"にまとめる（日本語などはそのまま残す）
+pub fn caption_name(caption: &str) -> String {
+    let mut name = String::new();
+    for c in caption.chars().flat_map(char::to_lowercase) {
+        if name.chars().count() >= MAX_CAPTION_NAME_CHARS {
+            break;
+        }
+        if c.is_alphanumeric() {
+            name.push(c);
+        } else if !name.is_empty() && !name.ends_with('-') {
+            name.push('-');
+        }
+    }
+    name.trim_end_matches('-').to_string()
+}
+
+// 取り出した画像のファイル名に使う部分
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ImageNameParts<'a> {
+    pub page_index: usize,
+    pub page_label: &'a str,
+    pub from_pattern: bool,
+    pub from_type3_glyph: bool,
+    pub glyph_unicode: Option<char>,
+    pub annotation: Option<&'a str>,
+    pub caption: Option<&'a str>,
+}
+
+impl<'a> From<&'a ExtractedImage> for ImageNameParts<'a> {
+    fn from(extracted: &'a ExtractedImage) -> Self {
+        ImageNameParts {
+            page_index: extracted.page_index,
+            page_label: &extracted.page_label,
+            from_pattern: extracted.from_pattern,
+            from_type3_glyph: extracted.from_type3_glyph,
+            glyph_unicode: extracted.glyph_unicode,
+            annotation: extracted.annotation.as_deref(),
+            caption: extracted.caption.as_deref(),
+        }
+    }
+}
+
+pub fn build_extracted_image_file_name(
+    parts: ImageNameParts<'_>,
+    image_index: usize,
+    use_page_labels: bool,
+    use_captions: bool,
+) -> String {
+    let mut name = format!(
+        "page-{}-image-{}",
+        page_name(parts.page_index, parts.page_label, use_page_labels),
+        image_index + 1
+    );
+    if parts.from_pattern {
+        name.push_str("-pattern");
+    }
+    if parts.from_type3_glyph {
+        name.push_str("-glyph");
+        if let Some(unicode) = parts.glyph_unicode {
+            name.push_str(&format!("-u{:04x}", u32::from(unicode)));
+        }
+    }
+    // 注釈の画像は種類を付けて区別する（ファイル名に使えない文字は落とす）
+    if let Some(annotation) = parts.annotation {
+        let subtype: String = annotation
+            .chars()
+            .filter(char::is_ascii_alphanumeric)
+            .map(|c| c.to_ascii_lowercase())
+            .collect();
+        if subtype.is_empty() {
+            name.push_str("-annotation");
+        } else {
+            name.push('-');
+            name.push_str(&subtype);
+        }
+    }
+    if use_captions && let Some(caption) = parts.caption {
+        let caption = caption_name(caption);
+        if !caption.is_empty() {
+            name.push('-');
+            name.push_str(&caption);
+        }
+    }
+    name.push_str(".png");
+    name
+}
+
+pub fn build_rendered_page_file_name(page_index: usize) -> String {
+    format!("page-{}.png", page_index + 1)
+}
+
+// 図ごとに書き出すときは、ページ内で上から何番目の図かを付ける
+pub fn build_svg_file_name(page_index: usize, figure_index: Option<usize>) -> String {
+    match figure_index {
+        Some(figure_index) => format!("page-{}-figure-{}.svg", page_index + 1, figure_index + 1),
+        None => format!("page-{}.svg", page_index + 1),
+    }
+}
+
+// 添付のパス（"claims.pdf/photo.jpg"、"report.pdf#page3"）の区切りを"-"にし、
+// ファイル名に使えない文字は"_"にする
+pub fn attachment_name(source_path: &str) -> String {
+    source_path
+        .chars()
+        .map(|c| match c {
+            '/' | '#' => '-',
+            c if c.is_alphanumeric() || matches!(c, '-' | '_' | '.') => c,
+            _ => '_',
+        })
+        .collect()
+}
+
+// 画像ファイルの添付は元の拡張子を形式に合わせて付け直す
+pub fn build_attachment_file_name(
+    source_path: &str,
+    duplicate_index: usize,
+    extension: &str,
+) -> String {
+    let file_name_start = source_path.rfind('/').map_or(0, |i| i + 1);
+    let stem = match source_path[file_name_start..].rfind('.') {
+        Some(i) if i > 0 => &source_path[..file_name_start + i],
+        _ => source_path,
+    };
+    let name = attachment_name(stem);
+    if duplicate_index == 0 {
+        format!("attachment-{name}.{extension}")
+    } else {
+        format!("attachment-{name}-{}.{extension}", duplicate_index + 1)
+    }
+}
+
+pub fn build_attachment_image_file_name(source_path: &str, image_index: usize) -> String {
+    format!(
+        "attachment-{}-image-{}.png",
+        attachment_name(source_path),
+        image_index + 1
+    )
+}
+
+// 同じラベルのページが続くときは2つ目から番号を付ける
+pub fn build_thumbnail_file_name(page_name: &str, duplicate_index: usize) -> String {
+    if duplicate_index == 0 {
+        format!("page-{page_name}-thumbnail.png")
+    } else {
+        format!("page-{page_name}-thumbnail-{}.png", duplicate_index + 1)
+    }
+}
+
+// 描かれない画像はページに属さないので、オブジェクト番号で名前を付ける
+pub fn build_unused_image_file_name(object: ObjectRef, kind: UnusedImageKind) -> String {
+    let prefix = match kind {
+        UnusedImageKind::Unpainted => "unused",
+        UnusedImageKind::Orphaned => "orphaned",
+    };
+    format!("{prefix}-{}-{}.png", object.number, object.generation)
 }
 
 #[cfg(test)]
 mod tests {
-    use super::build_extracted_image_file_name;
+    use super::{
+        ImageNameParts, build_attachment_file_name, build_attachment_image_file_name,
+        build_extracted_image_file_name, build_rendered_page_file_name, build_svg_file_name,
+        build_thumbnail_file_name, build_unused_image_file_name, caption_name, page_name,
+    };
+    use extract::{ObjectRef, UnusedImageKind};
+
+    fn extracted(page_index: usize) -> ImageNameParts<'static> {
+        ImageNameParts {
+            page_index,
+            ..Default::default()
+        }
+    }
 
     #[test]
     fn builds_file_name_with_one_based_indexes() {
-        assert_eq!(build_extracted_image_file_name(0, 0), "page-1-image-1.png");
-        assert_eq!(build_extracted_image_file_name(2, 4), "page-3-image-5.png");
+        assert_eq!(
+            build_extracted_image_file_name(extracted(0), 0, false, false),
+            "page-1-image-1.png"
+        );
+        assert_eq!(
+            build_extracted_image_file_name(extracted(2), 4, false, false),
+            "page-3-image-5.png"
+        );
+    }
+
+    #[test]
+    fn appends_annotation_subtype() {
+        let mut stamp = extracted(0);
+        stamp.annotation = Some("Stamp");
+        assert_eq!(
+            build_extracted_image_file_name(stamp, 1, false, false),
+            "page-1-image-2-stamp.png"
+        );
+
+        stamp.annotation = Some("");
+        assert_eq!(
+            build_extracted_image_file_name(stamp, 2, false, false),
+            "page-1-image-3-annotation.png"
+        );
+    }
+
+    #[test]
+    fn marks_pattern_images() {
+        let mut image = extracted(1);
+        image.from_pattern = true;
+        assert_eq!(
+            build_extracted_image_file_name(image, 0, false, false),
+            "page-2-image-1-pattern.png"
+        );
+
+        image.annotation = Some("Stamp");
+        assert_eq!(
+            build_extracted_image_file_name(image, 1, false, false),
+            "page-2-image-2-pattern-stamp.png"
+        );
+    }
+
+    #[test]
+    fn marks_type3_glyph_images() {
+        let mut glyph = extracted(0);
+        glyph.from_type3_glyph = true;
+        assert_eq!(
+            build_extracted_image_file_name(glyph, 0, false, false),
+            "page-1-image-1-glyph.png"
+        );
+
+        glyph.glyph_unicode = Some('A');
+        assert_eq!(
+            build_extracted_image_file_name(glyph, 1, false, false),
+            "page-1-image-2-glyph-u0041.png"
+        );
+    }
+
+    #[test]
+    fn uses_page_labels_when_requested() {
+        let mut image = extracted(2);
+        image.page_label = "iv";
+        assert_eq!(
+            build_extracted_image_file_name(image, 0, true, false),
+            "page-iv-image-1.png"
+        );
+        assert_eq!(
+            build_extracted_image_file_name(image, 0, false, false),
+            "page-3-image-1.png"
+        );
+
+        assert_eq!(page_name(2, "A/1 (draft)", true), "A_1__draft_");
+        assert_eq!(page_name(2, "", true), "3");
+    }
+
+    #[test]
+    fn appends_caption_when_requested() {
+        let mut image = extracted(0);
+        image.caption = Some("Figure 2: Sales by region (2024)");
+        assert_eq!(
+            build_extracted_image_file_name(image, 0, false, true),
+            "page-1-image-1-figure-2-sales-by-region-2024.png"
+        );
+        assert_eq!(
+            build_extracted_image_file_name(image, 0, false, false),
+            "page-1-image-1.png"
+        );
+
+        assert_eq!(caption_name("図3 売上の推移"), "図3-売上の推移");
+        assert_eq!(caption_name("Fig. 1 — "), "fig-1");
+        assert_eq!(caption_name(&"a".repeat(100)).len(), 48);
+    }
+
+    #[test]
+    fn builds_rendered_page_file_name() {
+        assert_eq!(build_rendered_page_file_name(0), "page-1.png");
+        assert_eq!(build_rendered_page_file_name(11), "page-12.png");
+    }
+
+    #[test]
+    fn builds_svg_file_names_for_pages_and_figures() {
+        assert_eq!(build_svg_file_name(0, None), "page-1.svg");
+        assert_eq!(build_svg_file_name(2, Some(1)), "page-3-figure-2.svg");
+    }
+
+    #[test]
+    fn numbers_thumbnails_of_pages_with_the_same_label() {
+        assert_eq!(build_thumbnail_file_name("3", 0), "page-3-thumbnail.png");
+        assert_eq!(
+            build_thumbnail_file_name("iv", 1),
+            "page-iv-thumbnail-2.png"
+        );
+    }
+
+    #[test]
+    fn builds_attachment_file_names_from_source_paths() {
+        assert_eq!(
+            build_attachment_file_name("IMG 0001.JPG", 0, "jpg"),
+            "attachment-IMG_0001.jpg"
+        );
+        assert_eq!(
+            build_attachment_file_name("claims.pdf/photo.jpeg", 1, "jpg"),
+            "attachment-claims.pdf-photo-2.jpg"
+        );
+        assert_eq!(
+            build_attachment_file_name("scan", 0, "tif"),
+            "attachment-scan.tif"
+        );
+        assert_eq!(
+            build_attachment_image_file_name("report.pdf#page3", 0),
+            "attachment-report.pdf-page3-image-1.png"
+        );
+    }
+
+    #[test]
+    fn names_unused_images_after_their_objects() {
+        let object = ObjectRef {
+            number: 12,
+            generation: 0,
+        };
+        assert_eq!(
+            build_unused_image_file_name(object, UnusedImageKind::Unpainted),
+            "unused-12-0.png"
+        );
+        assert_eq!(
+            build_unused_image_file_name(object, UnusedImageKind::Orphaned),
+            "orphaned-12-0.png"
+        );
     }
 }
@@ -0,0 +1,145 @@
+use extract::DocumentInfo;
+
+use crate::json::{json_array, json_object, json_optional_string, json_string};
+
+pub fn format_info_human(info: &DocumentInfo) -> String {
+    let mut lines = vec![
+        format!("PDF version: {}", info.version),
+        format!("Encrypted: {}", if info.encrypted { "yes" } else { "no" }),
+        format!("Producer: {}", info.producer.as_deref().unwrap_or("-")),
+        format!("Creator: {}", info.creator.as_deref().unwrap_or("-")),
+        format!("Pages: {}", info.pages.len()),
+    ];
+    for (index, page) in info.pages.iter().enumerate() {
+        let images = page
+            .image_count
+            .map_or_else(|| "unknown".to_string(), |count| count.to_string());
+        // ラベルが物理ページ番号と同じなら省く
+        let number = (index + 1).to_string();
+        let label = if page.label == number {
+            String::new()
+        } else {
+            format!(" (label {})", page.label)
+        };
+        lines.push(format!(
+            "  page {number}{label}: {} x {} pt, rotation {}, images {images}",
+            format_points(page.width),
+            format_points(page.height),
+            page.rotation,
+        ));
+    }
+    if !info.layers.is_empty() {
+        lines.push(format!("Layers: {}", info.layers.len()));
+        for layer in &info.layers {
+            let state = if layer.visible_by_default {
+                "on"
+            } else {
+                "off"
+            };
+            lines.push(format!("  {} ({state})", layer.name));
+        }
+    }
+    lines.join("\n")
+}
+
+pub fn format_info_json(info: &DocumentInfo) -> String {
+    let pages = info.pages.iter().enumerate().map(|(index, page)| {
+        json_object([
+            ("page", (index + 1).to_string()),
+            ("label", json_string(&page.label)),
+            ("width", format_points(page.width)),
+            ("height", format_points(page.height)),
+            ("rotation", page.rotation.to_string()),
+            (
+                "imageCount",
+                page.image_count
+                    .map_or_else(|| "null".to_string(), |count| count.to_string()),
+            ),
+        ])
+    });
+    let layers = info.layers.iter().map(|layer| {
+        json_object([
+            ("name", json_string(&layer.name)),
+            ("visibleByDefault", layer.visible_by_default.to_string()),
+        ])
+    });
+    json_object([
+        ("version", json_string(&info.version)),
+        ("encrypted", info.encrypted.to_string()),
+        ("producer", json_optional_string(info.producer.as_deref())),
+        ("creator", json_optional_string(info.creator.as_deref())),
+        ("pageCount", info.pages.len().to_string()),
+        ("pages", json_array(pages)),
+        ("layers", json_array(layers)),
+    ])
+}
+
+// 612.0は612、595.276は595.28のように短く出す
+pub fn format_points(value: f32) -> String {
+    let rounded = (f64::from(value) * 100.0).round() / 100.0;
+    if rounded.is_finite() {
+        format!("{rounded}")
+    } else {
+        "0".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_info_human, format_info_json, format_points};
+    use extract::{DocumentInfo, PageInfo};
+
+    fn info() -> DocumentInfo {
+        DocumentInfo {
+            version: "1.7".to_string(),
+            encrypted: false,
+            producer: Some("Scanner \"X\"".to_string()),
+            creator: None,
+            pages: vec![
+                PageInfo {
+                    label: "1".to_string(),
+                    width: 612.0,
+                    height: 792.0,
+                    rotation: 0,
+                    image_count: Some(2),
+                },
+                PageInfo {
+                    label: "A-1".to_string(),
+                    width: 595.276,
+                    height: 841.89,
+                    rotation: 90,
+                    image_count: None,
+                },
+            ],
+            layers: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn formats_points_compactly() {
+        assert_eq!(format_points(612.0), "612");
+        assert_eq!(format_points(595.276), "595.28");
+    }
+
+    #[test]
+    fn formats_human_readable_info() {
+        assert_eq!(
+            format_info_human(&info()),
+            "PDF version: 1.7\n\
+             Encrypted: no\n\
+             Producer: Scanner \"X\"\n\
+             Creator: -\n\
+             Pages: 2\n  \
+             page 1: 612 x 792 pt, rotation 0, images 2\n  \
+             page 2 (label A-1): 595.28 x 841.89 pt, rotation 90, images unknown"
+        );
+    }
+
+    #[test]
+    fn formats_json_info() {
+        assert_eq!(
+            format_info_json(&info()),
+            r#"{"version":"1.7","encrypted":false,"producer":"Scanner \"X\"","creator":null,"pageCount":2,"pages":[{"page":1,"label":"1","width":612,"height":792,"rotation":0,"imageCount":2},{"page":2,"label":"A-1","width":595.28,"height":841.89,"rotation":90,"imageCount":null}],"layers":[]}"#
+        );
+    }
+}
@@ -0,0 +1,61 @@
+// serdeを入れるほどではないので、出力に必要な分だけ手で組み立てる
+
+pub fn json_string(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if u32::from(c) < 0x20 => out.push_str(&format!("\\u{:04x}", u32::from(c))),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+pub fn json_optional_string(value: Option<&str>) -> String {
+    value.map_or_else(|| "null".to_string(), json_string)
+}
+
+pub fn json_array(items: impl IntoIterator<Item = String>) -> String {
+    format!("[{}]", items.into_iter().collect::<Vec<_>>().join(","))
+}
+
+// 値はすでにJSONとして組み立て済みの文字列
+pub fn json_object<'k>(fields: impl IntoIterator<Item = (&'k str, String)>) -> String {
+    let fields: Vec<String> = fields
+        .into_iter()
+        .map(|(key, value)| format!("{}:{value}", json_string(key)))
+        .collect();
+    format!("{{{}}}", fields.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{json_array, json_object, json_optional_string, json_string};
+
+    #[test]
+    fn escapes_strings() {
+        assert_eq!(json_string("a\"b\\c"), r#""a\"b\\c""#);
+        assert_eq!(json_string("line\n\u{1}"), r#""line\n\u0001""#);
+        assert_eq!(json_string("地図"), "\"地図\"");
+        assert_eq!(json_optional_string(None), "null");
+    }
+
+    #[test]
+    fn builds_objects_and_arrays() {
+        assert_eq!(
+            json_object([
+                ("name", json_string("x")),
+                ("pages", json_array(["1".to_string(), "2".to_string()])),
+            ]),
+            r#"{"name":"x","pages":[1,2]}"#
+        );
+        assert_eq!(json_array(Vec::new()), "[]");
+    }
+}
@@ -1,52 +1,340 @@
+mod audit;
 mod file_names;
-mod page_ranges;
+mod info;
+mod json;
+mod manifest;
+mod progress;
+mod revisions;
 
 use std::collections::HashMap;
 use std::fmt;
 use std::io;
 use std::path::{Path, PathBuf};
 
-use clap::Parser;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use extract::{
+    AttachmentContent, ExtractError, ExtractImageWarning, ExtractLimits, InvalidBackgroundColor,
+    LayerVisibility, PageRange, PageSelection, PageSelectionError,
+};
 
-use crate::file_names::build_extracted_image_file_name;
-use crate::page_ranges::{ParsePageRangesError, parse_page_ranges_text};
+use crate::audit::{format_audit_human, format_audit_json};
+use crate::file_names::{
+    ImageNameParts, build_attachment_file_name, build_attachment_image_file_name,
+    build_extracted_image_file_name, build_rendered_page_file_name, build_svg_file_name,
+    build_thumbnail_file_name, build_unused_image_file_name, page_name,
+};
+use crate::info::{format_info_human, format_info_json};
+use crate::manifest::{
+    MANIFEST_FILE_NAME, attachment_file_entry_json, format_manifest_json, manifest_entry_json,
+    unused_image_entry_json,
+};
+use crate::progress::ProgressBar;
+use crate::revisions::{format_revisions_human, format_revisions_json};
 
 #[derive(Debug, Parser)]
 #[command(name = "pdf-img-extract")]
 #[command(about = "Extract raster images from a PDF into PNG files")]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    extract: ExtractArgs,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Render whole pages into PNG files
+    Render(RenderArgs),
+    /// Print document information (pages, version, encryption, images per page)
+    Info(InfoArgs),
+    /// Report images without alt text, untagged images and decorative images not marked as artifacts
+    Audit(AuditArgs),
+    /// List the revisions of an incrementally updated PDF and the images added, removed or changed in each
+    Revisions(RevisionsArgs),
+    /// Export pages, or the figures detected on them, as SVG with vector paths, text outlines and embedded images
+    Svg(SvgArgs),
+}
+
+#[derive(Debug, Args)]
+struct ExtractArgs {
     /// PDF file path
-    input_pdf: PathBuf,
+    #[arg(required = true)]
+    input_pdf: Option<PathBuf>,
 
     /// Output directory (default: <input_basename>-images)
     #[arg(short, long)]
     output: Option<PathBuf>,
 
-    /// Page ranges using 1-based indexes, e.g. "1,3-5"
-    #[arg(short = 'p', long = "pages")]
+    /// Pages to use, e.g. "1,3-5", "5-", "last-2-last", "odd", "1-20:3", "!10-12" or "label:iv-x"
+    #[arg(short = 'p', long = "pages", allow_hyphen_values = true)]
     pages: Option<String>,
+
+    /// Use page labels (e.g. "iv", "A-1") instead of page numbers in file names
+    #[arg(long)]
+    label_file_names: bool,
+
+    /// Also write manifest.json describing each saved image (page, object, layers, alt text)
+    #[arg(long)]
+    manifest: bool,
+
+    /// Capture the caption ("Figure", "Fig.", "図") and the text lines just above and below each image
+    #[arg(long)]
+    captions: bool,
+
+    /// How far from an image, in points, to look for captions and nearby text
+    #[arg(long, value_name = "PT", default_value_t = 36.0)]
+    caption_distance: f32,
+
+    /// Append the caption to file names (implies --captions)
+    #[arg(long)]
+    caption_file_names: bool,
+
+    /// Embed the XMP metadata of image XObjects (creator, rights) into the saved PNG files; attached image files (JPEG and others) are written unchanged without it
+    #[arg(long)]
+    embed_xmp: bool,
+
+    /// Save images as they appear on the page (rotated, mirrored and clipped)
+    #[arg(long)]
+    as_displayed: bool,
+
+    /// Turn images to their reading orientation (placement rotation, mirroring and page /Rotate)
+    #[arg(long)]
+    normalize_orientation: bool,
+
+    /// Skip annotation appearances (stamps, signatures, form fields) and only extract images from the page content
+    #[arg(long)]
+    no_annotations: bool,
+
+    /// Also extract bitmaps drawn by Type3 font glyphs, once per glyph
+    #[arg(long)]
+    include_type3_glyphs: bool,
+
+    /// Extract images from every optional content layer, including hidden ones
+    #[arg(long, conflicts_with = "layers")]
+    all_layers: bool,
+
+    /// Only treat the named optional content layer as visible (repeatable)
+    #[arg(long = "layer", value_name = "NAME")]
+    layers: Vec<String>,
+
+    /// Also save image attachments as-is and extract images from attached PDFs (portfolios, /EmbeddedFiles, file attachment annotations)
+    #[arg(long)]
+    include_attachments: bool,
+
+    /// Also extract image XObjects that are never painted, including image streams no page refers to
+    #[arg(long)]
+    include_unused: bool,
+
+    /// Join images split into adjacent strips or tiles of the same resolution back into one image
+    #[arg(long)]
+    stitch_fragments: bool,
+
+    /// Skip inline images (BI ... EI) and only extract image XObjects
+    #[arg(long)]
+    no_inline: bool,
+
+    /// Only save the page thumbnails (/Thumb) embedded in the PDF, without interpreting page content
+    #[arg(long)]
+    thumbnails: bool,
+
+    /// Do not show the progress bar on stderr
+    #[arg(long)]
+    no_progress: bool,
+
+    #[command(flatten)]
+    limits: LimitArgs,
+}
+
+// 信頼できないPDFを扱うための上限。指定しなければライブラリの既定値を使い、0は無制限
+#[derive(Debug, Args)]
+struct LimitArgs {
+    /// Skip images with more pixels than this (0: no limit) [default: 100000000]
+    #[arg(long, value_name = "N")]
+    max_image_pixels: Option<u64>,
+
+    /// Stop decoding images after this many decoded bytes in total (0: no limit) [default: 1073741824]
+    #[arg(long, value_name = "BYTES")]
+    max_decoded_bytes: Option<u64>,
+
+    /// Stop after this many images (0: no limit) [default: 10000]
+    #[arg(long, value_name = "N")]
+    max_images: Option<usize>,
+
+    /// Refuse to process more pages than this (0: no limit) [default: 10000]
+    #[arg(long, value_name = "N")]
+    max_pages: Option<usize>,
+}
+
+impl LimitArgs {
+    fn limits(&self) -> ExtractLimits {
+        fn limit<T: Copy + Default + PartialEq>(value: Option<T>, default: Option<T>) -> Option<T> {
+            match value {
+                Some(value) if value == T::default() => None,
+                Some(value) => Some(value),
+                None => default,
+            }
+        }
+        let defaults = ExtractLimits::default();
+        ExtractLimits {
+            max_image_pixels: limit(self.max_image_pixels, defaults.max_image_pixels),
+            max_total_decoded_bytes: limit(
+                self.max_decoded_bytes,
+                defaults.max_total_decoded_bytes,
+            ),
+            max_images: limit(self.max_images, defaults.max_images),
+            max_pages: limit(self.max_pages, defaults.max_pages),
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+struct RenderArgs {
+    /// PDF file path
+    input_pdf: PathBuf,
+
+    /// Output directory (default: <input_basename>-pages)
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// Pages to use, e.g. "1,3-5", "5-", "last-2-last", "odd", "1-20:3", "!10-12" or "label:iv-x"
+    #[arg(short = 'p', long = "pages", allow_hyphen_values = true)]
+    pages: Option<String>,
+
+    /// Resolution in dots per inch
+    #[arg(long, default_value_t = 150.0)]
+    dpi: f32,
+
+    /// Background color: white, black, transparent, #rgb, #rrggbb or #rrggbbaa
+    #[arg(long, default_value = "white")]
+    background: String,
+
+    /// Do not show the progress bar on stderr
+    #[arg(long)]
+    no_progress: bool,
+
+    #[command(flatten)]
+    limits: LimitArgs,
+}
+
+#[derive(Debug, Args)]
+struct InfoArgs {
+    /// Input PDF path
+    input_pdf: PathBuf,
+
+    /// Output format
+    #[arg(long, value_enum, default_value_t = OutputFormat::Human)]
+    format: OutputFormat,
+}
+
+#[derive(Debug, Args)]
+struct AuditArgs {
+    /// Input PDF path
+    input_pdf: PathBuf,
+
+    /// Pages to use, e.g. "1,3-5", "5-", "last-2-last", "odd", "1-20:3", "!10-12" or "label:iv-x"
+    #[arg(short = 'p', long = "pages", allow_hyphen_values = true)]
+    pages: Option<String>,
+
+    /// Output format
+    #[arg(long, value_enum, default_value_t = OutputFormat::Human)]
+    format: OutputFormat,
+
+    #[command(flatten)]
+    limits: LimitArgs,
+}
+
+#[derive(Debug, Args)]
+struct RevisionsArgs {
+    /// Input PDF path
+    input_pdf: PathBuf,
+
+    /// Also save the images each revision adds or modifies (all of them for the first) into revision-<n> directories under this directory
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// Pages to use, e.g. "1,3-5", "5-", "last-2-last", "odd", "1-20:3", "!10-12" or "label:iv-x"
+    #[arg(short = 'p', long = "pages", allow_hyphen_values = true)]
+    pages: Option<String>,
+
+    /// Output format
+    #[arg(long, value_enum, default_value_t = OutputFormat::Human)]
+    format: OutputFormat,
+
+    #[command(flatten)]
+    limits: LimitArgs,
+}
+
+#[derive(Debug, Args)]
+struct SvgArgs {
+    /// PDF file path
+    input_pdf: PathBuf,
+
+    /// Output directory (default: <input_basename>-svg)
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// Pages to use, e.g. "1,3-5", "5-", "last-2-last", "odd", "1-20:3", "!10-12" or "label:iv-x"
+    #[arg(short = 'p', long = "pages", allow_hyphen_values = true)]
+    pages: Option<String>,
+
+    /// Write one SVG per detected figure (charts, diagrams and their labels) instead of whole pages
+    #[arg(long)]
+    figures: bool,
+
+    #[command(flatten)]
+    limits: LimitArgs,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    Human,
+    Json,
 }
 
 #[derive(Debug)]
 struct RunOutcome {
-    image_count: usize,
-    warning_count: usize,
+    summary: String,
     output_dir: PathBuf,
 }
 
 #[derive(Debug)]
 enum CliError {
-    InvalidRanges(ParsePageRangesError),
+    MissingInput,
+    InvalidRanges(PageSelectionError),
+    InvalidBackground(InvalidBackgroundColor),
     ReadInput { path: PathBuf, source: io::Error },
     CreateOutputDir { path: PathBuf, source: io::Error },
     Extract(extract::ExtractError),
+    Render(extract::ExtractError),
+    Svg(extract::ExtractError),
+    Info(extract::ExtractError),
     SaveImage { path: PathBuf, message: String },
+    WriteManifest { path: PathBuf, source: io::Error },
+    WriteSvg { path: PathBuf, source: io::Error },
+    Audit(extract::ExtractError),
+    // 監査で問題が見つかった（レポートは出力済み）
+    AuditIssues(usize),
+}
+
+impl CliError {
+    // 公開前の検査に使えるよう、問題が見つかっただけのときは失敗と区別する
+    // （2はclapが使い方の誤りに使う）
+    fn exit_code(&self) -> i32 {
+        match self {
+            CliError::AuditIssues(_) => 3,
+            _ => 1,
+        }
+    }
 }
 
 impl fmt::Display for CliError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
+            CliError::MissingInput => write!(f, "missing input PDF path"),
             CliError::InvalidRanges(err) => write!(f, "failed to parse --pages: {err}"),
+            CliError::InvalidBackground(err) => write!(f, "failed to parse --background: {err}"),
             CliError::ReadInput { path, source } => {
                 write!(f, "failed to read input PDF {}: {source}", path.display())
             }
@@ -58,9 +346,20 @@ impl fmt::Display for CliError {
                 )
             }
             CliError::Extract(err) => write!(f, "failed to extract images: {err}"),
+            CliError::Render(err) => write!(f, "failed to render pages: {err}"),
+            CliError::Svg(err) => write!(f, "failed to export SVG: {err}"),
+            CliError::Info(err) => write!(f, "failed to read document information: {err}"),
             CliError::SaveImage { path, message } => {
                 write!(f, "failed to save PNG {}: {message}", path.display())
             }
+            CliError::WriteManifest { path, source } => {
+                write!(f, "failed to write manifest {}: {source}", path.display())
+            }
+            CliError::WriteSvg { path, source } => {
+                write!(f, "failed to write SVG {}: {source}", path.display())
+            }
+            CliError::Audit(err) => write!(f, "failed to audit document: {err}"),
+            CliError::AuditIssues(count) => write!(f, "found {count} accessibility issues"),
         }
     }
 }
@@ -68,85 +367,524 @@ impl fmt::Display for CliError {
 fn main() {
     if let Err(err) = run_cli(Cli::parse()) {
         eprintln!("{err}");
-        std::process::exit(1);
+        std::process::exit(err.exit_code());
     }
 }
 
 fn run_cli(cli: Cli) -> Result<(), CliError> {
-    let outcome = run(cli)?;
+    let outcome = match cli.command {
+        Some(Command::Render(args)) => run_render(args)?,
+        Some(Command::Svg(args)) => run_svg(args)?,
+        Some(Command::Info(args)) => {
+            println!("{}", run_info(args)?);
+            return Ok(());
+        }
+        Some(Command::Audit(args)) => return run_audit(args),
+        Some(Command::Revisions(args)) => return run_revisions(args),
+        None => run(cli.extract)?,
+    };
     let output_dir = normalize_output_dir(&outcome.output_dir);
-    println!(
-        "extracted {} images ({} warnings)",
-        outcome.image_count, outcome.warning_count
-    );
+    println!("{}", outcome.summary);
     println!("output directory: {}", output_dir.display());
     Ok(())
 }
 
-fn run(cli: Cli) -> Result<RunOutcome, CliError> {
-    let page_ranges = cli
-        .pages
-        .as_deref()
-        .map(parse_page_ranges_text)
-        .transpose()
-        .map_err(CliError::InvalidRanges)?
-        .unwrap_or_default();
+fn run(args: ExtractArgs) -> Result<RunOutcome, CliError> {
+    let selection = parse_pages_arg(args.pages.as_deref())?;
 
-    let input_pdf_path = cli.input_pdf;
-    let output_dir = cli
+    let input_pdf_path = args.input_pdf.ok_or(CliError::MissingInput)?;
+    let output_dir = args
         .output
-        .unwrap_or_else(|| default_output_dir(&input_pdf_path));
-
-    let pdf_bytes = std::fs::read(&input_pdf_path).map_err(|source| CliError::ReadInput {
-        path: input_pdf_path.clone(),
-        source,
-    })?;
+        .unwrap_or_else(|| default_output_dir(&input_pdf_path, "images"));
 
-    std::fs::create_dir_all(&output_dir).map_err(|source| CliError::CreateOutputDir {
-        path: output_dir.clone(),
-        source,
-    })?;
+    let pdf_bytes = read_input_pdf(&input_pdf_path)?;
+    let page_ranges = resolve_pages(selection.as_ref(), &pdf_bytes)?;
+    create_output_dir(&output_dir)?;
+    if args.thumbnails {
+        return run_thumbnails(
+            &pdf_bytes,
+            &page_ranges,
+            output_dir,
+            args.label_file_names,
+            args.limits.limits(),
+        );
+    }
 
-    let result = extract::extract_images(&pdf_bytes, &page_ranges).map_err(CliError::Extract)?;
+    let options = extract::ExtractOptions {
+        as_displayed: args.as_displayed,
+        normalize_orientation: args.normalize_orientation,
+        exclude_annotations: args.no_annotations,
+        include_type3_glyphs: args.include_type3_glyphs,
+        layers: layer_visibility(args.all_layers, args.layers),
+        exclude_inline_images: args.no_inline,
+        caption_distance: (args.captions || args.caption_file_names)
+            .then_some(args.caption_distance),
+        include_attachments: args.include_attachments,
+        include_unused: args.include_unused,
+        stitch_fragments: args.stitch_fragments,
+        limits: args.limits.limits(),
+        ..Default::default()
+    };
+    let mut progress_bar = ProgressBar::new(!args.no_progress);
+    let result =
+        extract::extract_images_with_progress(&pdf_bytes, &page_ranges, &options, |progress| {
+            progress_bar.update(&progress)
+        });
+    progress_bar.finish();
+    let result = result.map_err(CliError::Extract)?;
 
-    let mut image_index_by_page: HashMap<usize, usize> = HashMap::new();
+    // 同じラベルのページが複数あっても上書きしないよう、ファイル名のページ部分ごとに番号を振る
+    let mut image_index_by_page: HashMap<String, usize> = HashMap::new();
+    let mut manifest_entries = Vec::new();
 
     for extracted in result.images {
-        let image_index = image_index_by_page.entry(extracted.page_index).or_insert(0);
-        let file_name = build_extracted_image_file_name(extracted.page_index, *image_index);
+        let image_index = image_index_by_page
+            .entry(page_name(
+                extracted.page_index,
+                &extracted.page_label,
+                args.label_file_names,
+            ))
+            .or_insert(0);
+        let file_name = build_extracted_image_file_name(
+            ImageNameParts::from(&extracted),
+            *image_index,
+            args.label_file_names,
+            args.caption_file_names,
+        );
         *image_index += 1;
 
-        let output_path = output_dir.join(file_name);
-        extracted
-            .image
-            .save(&output_path)
-            .map_err(|source| CliError::SaveImage {
-                path: output_path,
-                message: source.to_string(),
-            })?;
+        let xmp = args
+            .embed_xmp
+            .then(|| extracted.xmp.as_ref().map(|xmp| xmp.raw.as_str()))
+            .flatten();
+        save_png(&extracted.image, xmp, &output_dir.join(&file_name))?;
+        if args.manifest {
+            manifest_entries.push(manifest_entry_json(&file_name, &extracted, None));
+        }
+    }
+
+    // 添付の画像はページの画像と名前が重ならないよう"attachment-"で始める
+    let mut attachment_count = 0;
+    let mut index_by_attachment: HashMap<String, usize> = HashMap::new();
+    for attachment in result.attachments {
+        let index = index_by_attachment
+            .entry(attachment.source_path.clone())
+            .or_insert(0);
+        match attachment.content {
+            AttachmentContent::File { bytes, format } => {
+                let file_name =
+                    build_attachment_file_name(&attachment.source_path, *index, format.extension());
+                let path = output_dir.join(&file_name);
+                std::fs::write(&path, bytes).map_err(|source| CliError::SaveImage {
+                    path,
+                    message: source.to_string(),
+                })?;
+                if args.manifest {
+                    manifest_entries.push(attachment_file_entry_json(
+                        &file_name,
+                        &attachment.source_path,
+                        format.extension(),
+                    ));
+                }
+            }
+            AttachmentContent::Extracted(extracted) => {
+                let file_name = build_attachment_image_file_name(&attachment.source_path, *index);
+                let xmp = args
+                    .embed_xmp
+                    .then(|| extracted.xmp.as_ref().map(|xmp| xmp.raw.as_str()))
+                    .flatten();
+                save_png(&extracted.image, xmp, &output_dir.join(&file_name))?;
+                if args.manifest {
+                    manifest_entries.push(manifest_entry_json(
+                        &file_name,
+                        &extracted,
+                        Some(&attachment.source_path),
+                    ));
+                }
+            }
+        }
+        *index += 1;
+        attachment_count += 1;
+    }
+    let unused_count = result.unused_images.len();
+    for unused in &result.unused_images {
+        let file_name = build_unused_image_file_name(unused.object, unused.kind);
+        save_png(&unused.image, None, &output_dir.join(&file_name))?;
+        if args.manifest {
+            manifest_entries.push(unused_image_entry_json(&file_name, unused));
+        }
+    }
+    if args.manifest {
+        write_manifest(
+            &output_dir.join(MANIFEST_FILE_NAME),
+            &format_manifest_json(manifest_entries),
+        )?;
+    }
+
+    let warning_count =
+        result.errors.len() + result.attachment_errors.len() + result.unused_errors.len();
+    print_warnings(result.errors);
+    for warning in result.attachment_errors {
+        eprintln!(
+            "warning: attachment {}: {}",
+            warning.source_path, warning.kind
+        );
+    }
+    for warning in result.unused_errors {
+        eprintln!("warning: unused image {}: {}", warning.object, warning.kind);
+    }
+
+    let image_count = image_index_by_page.values().sum::<usize>();
+    let mut counts = vec![format!("{image_count} images")];
+    if args.include_attachments {
+        counts.push(format!("{attachment_count} attachment images"));
+    }
+    if args.include_unused {
+        counts.push(format!("{unused_count} unused images"));
+    }
+    let last = counts.pop().unwrap_or_default();
+    let counts = if counts.is_empty() {
+        last
+    } else {
+        format!("{} and {last}", counts.join(", "))
+    };
+    Ok(RunOutcome {
+        summary: format!("extracted {counts} ({warning_count} warnings)"),
+        output_dir,
+    })
+}
+
+// 埋め込みのサムネイルはページを描画しないので、全ページの縮小画像を安く得られる
+fn run_thumbnails(
+    pdf_bytes: &[u8],
+    page_ranges: &[PageRange],
+    output_dir: PathBuf,
+    use_page_labels: bool,
+    limits: ExtractLimits,
+) -> Result<RunOutcome, CliError> {
+    let options = extract::ExtractOptions {
+        limits,
+        ..Default::default()
+    };
+    let result =
+        extract::extract_thumbnails(pdf_bytes, page_ranges, &options).map_err(CliError::Extract)?;
+
+    let mut count_by_page: HashMap<String, usize> = HashMap::new();
+    for thumbnail in &result.thumbnails {
+        let page = page_name(thumbnail.page_index, &thumbnail.page_label, use_page_labels);
+        let count = count_by_page.entry(page.clone()).or_insert(0);
+        let file_name = build_thumbnail_file_name(&page, *count);
+        *count += 1;
+        save_png(&thumbnail.image, None, &output_dir.join(file_name))?;
     }
 
     let warning_count = result.errors.len();
-    for warning in result.errors {
-        eprintln!("warning: page {}: {}", warning.page_index + 1, warning.kind);
+    print_warnings(result.errors);
+
+    Ok(RunOutcome {
+        summary: format!(
+            "extracted {} page thumbnails ({} warnings)",
+            result.thumbnails.len(),
+            warning_count
+        ),
+        output_dir,
+    })
+}
+
+fn run_info(args: InfoArgs) -> Result<String, CliError> {
+    let pdf_bytes = read_input_pdf(&args.input_pdf)?;
+    let info = extract::document_info(&pdf_bytes).map_err(CliError::Info)?;
+    Ok(match args.format {
+        OutputFormat::Human => format_info_human(&info),
+        OutputFormat::Json => format_info_json(&info),
+    })
+}
+
+fn run_audit(args: AuditArgs) -> Result<(), CliError> {
+    let selection = parse_pages_arg(args.pages.as_deref())?;
+    let pdf_bytes = read_input_pdf(&args.input_pdf)?;
+    let page_ranges = resolve_pages(selection.as_ref(), &pdf_bytes)?;
+    let audit = extract::audit_accessibility(
+        &pdf_bytes,
+        &page_ranges,
+        &extract::ExtractOptions {
+            limits: args.limits.limits(),
+            ..Default::default()
+        },
+    )
+    .map_err(CliError::Audit)?;
+
+    println!(
+        "{}",
+        match args.format {
+            OutputFormat::Human => format_audit_human(&audit),
+            OutputFormat::Json => format_audit_json(&audit),
+        }
+    );
+    let issue_count = audit.issues.len();
+    print_warnings(audit.warnings);
+    if issue_count > 0 {
+        return Err(CliError::AuditIssues(issue_count));
+    }
+    Ok(())
+}
+
+// ページ範囲とラベルは最新版で解決し、古い版にも同じ範囲を使う
+fn run_revisions(args: RevisionsArgs) -> Result<(), CliError> {
+    let selection = parse_pages_arg(args.pages.as_deref())?;
+    let pdf_bytes = read_input_pdf(&args.input_pdf)?;
+    let page_ranges = resolve_pages(selection.as_ref(), &pdf_bytes)?;
+    let result = extract::extract_revisions(
+        &pdf_bytes,
+        &page_ranges,
+        &extract::ExtractOptions {
+            limits: args.limits.limits(),
+            ..Default::default()
+        },
+    )
+    .map_err(CliError::Extract)?;
+
+    if let Some(output_dir) = &args.output {
+        for revision in &result.revisions {
+            let revision_dir = output_dir.join(format!("revision-{}", revision.revision.index + 1));
+            create_output_dir(&revision_dir)?;
+            let mut image_index_by_page: HashMap<usize, usize> = HashMap::new();
+            for extracted in &revision.images {
+                let image_index = image_index_by_page.entry(extracted.page_index).or_insert(0);
+                let file_name = build_extracted_image_file_name(
+                    ImageNameParts::from(extracted),
+                    *image_index,
+                    false,
+                    false,
+                );
+                *image_index += 1;
+                save_png(&extracted.image, None, &revision_dir.join(file_name))?;
+            }
+        }
+    }
+
+    println!(
+        "{}",
+        match args.format {
+            OutputFormat::Human => format_revisions_human(&result),
+            OutputFormat::Json => format_revisions_json(&result),
+        }
+    );
+    for revision in result.revisions {
+        for warning in revision.errors {
+            eprintln!(
+                "warning: revision {}: {}",
+                revision.revision.index + 1,
+                describe_warning(&warning)
+            );
+        }
+    }
+    Ok(())
+}
+
+fn run_render(args: RenderArgs) -> Result<RunOutcome, CliError> {
+    let selection = parse_pages_arg(args.pages.as_deref())?;
+    let background =
+        extract::parse_background_color(&args.background).map_err(CliError::InvalidBackground)?;
+
+    let output_dir = args
+        .output
+        .unwrap_or_else(|| default_output_dir(&args.input_pdf, "pages"));
+
+    let pdf_bytes = read_input_pdf(&args.input_pdf)?;
+    let page_ranges = resolve_pages(selection.as_ref(), &pdf_bytes)?;
+    create_output_dir(&output_dir)?;
+
+    let options = extract::RenderOptions {
+        dpi: args.dpi,
+        background,
+        limits: args.limits.limits(),
+        ..Default::default()
+    };
+    let mut progress_bar = ProgressBar::new(!args.no_progress);
+    let result =
+        extract::render_pages_with_progress(&pdf_bytes, &page_ranges, &options, |progress| {
+            progress_bar.update(&progress)
+        });
+    progress_bar.finish();
+    let result = result.map_err(CliError::Render)?;
+
+    let page_count = result.pages.len();
+    for rendered in result.pages {
+        let file_name = build_rendered_page_file_name(rendered.page_index);
+        save_png(&rendered.image, None, &output_dir.join(file_name))?;
+    }
+
+    let warning_count = result.errors.len();
+    print_warnings(result.errors);
+
+    Ok(RunOutcome {
+        summary: format!("rendered {page_count} pages ({warning_count} warnings)"),
+        output_dir,
+    })
+}
+
+fn run_svg(args: SvgArgs) -> Result<RunOutcome, CliError> {
+    let selection = parse_pages_arg(args.pages.as_deref())?;
+    let output_dir = args
+        .output
+        .unwrap_or_else(|| default_output_dir(&args.input_pdf, "svg"));
+
+    let pdf_bytes = read_input_pdf(&args.input_pdf)?;
+    let page_ranges = resolve_pages(selection.as_ref(), &pdf_bytes)?;
+    create_output_dir(&output_dir)?;
+
+    let options = extract::SvgOptions {
+        figures: args.figures,
+        limits: args.limits.limits(),
+        ..Default::default()
+    };
+    let result = extract::export_svg(&pdf_bytes, &page_ranges, &options).map_err(CliError::Svg)?;
+
+    let mut figure_index = 0;
+    let mut previous_page = None;
+    // パターン（グラデーション、タイル）やソフトマスク、描画モードはSVGにできないので、
+    // 省いたり無視したりしたことをファイルごとに知らせる
+    let mut svg_warnings = Vec::new();
+    for graphic in &result.graphics {
+        if previous_page != Some(graphic.page_index) {
+            figure_index = 0;
+            previous_page = Some(graphic.page_index);
+        }
+        let file_name =
+            build_svg_file_name(graphic.page_index, args.figures.then_some(figure_index));
+        figure_index += 1;
+        if graphic.skipped_paints > 0 {
+            svg_warnings.push(format!(
+                "{file_name}: skipped {} pattern fills or strokes",
+                graphic.skipped_paints
+            ));
+        }
+        if graphic.ignored_effects > 0 {
+            svg_warnings.push(format!(
+                "{file_name}: ignored soft masks or blend modes on {} drawings",
+                graphic.ignored_effects
+            ));
+        }
+        let path = output_dir.join(file_name);
+        std::fs::write(&path, &graphic.svg)
+            .map_err(|source| CliError::WriteSvg { path, source })?;
+    }
+
+    let warning_count = result.errors.len() + svg_warnings.len();
+    print_warnings(result.errors);
+    for warning in svg_warnings {
+        eprintln!("warning: {warning}");
     }
 
     Ok(RunOutcome {
-        image_count: image_index_by_page.values().sum(),
-        warning_count,
+        summary: format!(
+            "exported {} SVG files ({warning_count} warnings)",
+            result.graphics.len()
+        ),
         output_dir,
     })
 }
 
-fn default_output_dir(input_pdf_path: &Path) -> PathBuf {
+fn layer_visibility(all_layers: bool, layers: Vec<String>) -> LayerVisibility {
+    if all_layers {
+        LayerVisibility::All
+    } else if layers.is_empty() {
+        LayerVisibility::Default
+    } else {
+        LayerVisibility::Only(layers)
+    }
+}
+
+fn parse_pages_arg(pages: Option<&str>) -> Result<Option<PageSelection>, CliError> {
+    pages
+        .map(str::parse)
+        .transpose()
+        .map_err(CliError::InvalidRanges)
+}
+
+// last、odd、ラベルなどは文書のページ数やラベルを読んでから範囲にする
+fn resolve_pages(
+    selection: Option<&PageSelection>,
+    pdf_bytes: &[u8],
+) -> Result<Vec<PageRange>, CliError> {
+    let Some(selection) = selection else {
+        return Ok(Vec::new());
+    };
+    extract::resolve_page_selection(pdf_bytes, selection).map_err(|err| match err {
+        ExtractError::InvalidPageSelection(err) => CliError::InvalidRanges(err),
+        err => CliError::Extract(err),
+    })
+}
+
+fn read_input_pdf(path: &Path) -> Result<Vec<u8>, CliError> {
+    std::fs::read(path).map_err(|source| CliError::ReadInput {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+fn create_output_dir(path: &Path) -> Result<(), CliError> {
+    std::fs::create_dir_all(path).map_err(|source| CliError::CreateOutputDir {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+// XMPはPNGのiTXtチャンクとして埋め込む
+fn save_png(image: &image::DynamicImage, xmp: Option<&str>, path: &Path) -> Result<(), CliError> {
+    let save_error = |message: String| CliError::SaveImage {
+        path: path.to_path_buf(),
+        message,
+    };
+    let Some(xmp) = xmp else {
+        return image
+            .save(path)
+            .map_err(|source| save_error(source.to_string()));
+    };
+    let mut png = io::Cursor::new(Vec::new());
+    image
+        .write_to(&mut png, image::ImageFormat::Png)
+        .map_err(|source| save_error(source.to_string()))?;
+    let png = extract::embed_xmp_in_png(png.get_ref(), xmp)
+        .map_err(|source| save_error(source.to_string()))?;
+    std::fs::write(path, png).map_err(|source| save_error(source.to_string()))
+}
+
+fn write_manifest(path: &Path, contents: &str) -> Result<(), CliError> {
+    std::fs::write(path, contents).map_err(|source| CliError::WriteManifest {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+fn print_warnings(warnings: Vec<ExtractImageWarning>) {
+    for warning in warnings {
+        eprintln!("warning: {}", describe_warning(&warning));
+    }
+}
+
+fn describe_warning(warning: &ExtractImageWarning) -> String {
+    match warning.image_index {
+        Some(image_index) => format!(
+            "page {} image {}: {}",
+            warning.page_index + 1,
+            image_index + 1,
+            warning.kind
+        ),
+        None => format!("page {}: {}", warning.page_index + 1, warning.kind),
+    }
+}
+
+fn default_output_dir(input_pdf_path: &Path, suffix: &str) -> PathBuf {
     let base_name = input_pdf_path
         .file_stem()
         .and_then(|stem| stem.to_str())
         .map(str::trim)
         .filter(|name| !name.is_empty())
-        .unwrap_or("images");
+        .unwrap_or(suffix);
 
-    PathBuf::from(format!("{base_name}-images"))
+    PathBuf::from(format!("{base_name}-{suffix}"))
 }
 
 fn normalize_output_dir(path: &Path) -> PathBuf {
@@ -155,14 +893,137 @@ fn normalize_output_dir(path: &Path) -> PathBuf {
 
 #[cfg(test)]
 mod tests {
-    use super::default_output_dir;
+    use super::{Cli, Command, OutputFormat, default_output_dir, layer_visibility};
+    use clap::Parser;
+    use extract::LayerVisibility;
     use std::path::Path;
 
     #[test]
     fn default_output_uses_input_stem() {
         assert_eq!(
-            default_output_dir(Path::new("sample.pdf")),
+            default_output_dir(Path::new("sample.pdf"), "images"),
             Path::new("sample-images")
         );
+        assert_eq!(
+            default_output_dir(Path::new("sample.pdf"), "pages"),
+            Path::new("sample-pages")
+        );
+    }
+
+    #[test]
+    fn extracts_without_subcommand() {
+        let cli = Cli::parse_from(["pdf-img-extract", "sample.pdf", "-p", "1-2"]);
+        assert!(cli.command.is_none());
+        assert_eq!(
+            cli.extract.input_pdf.as_deref(),
+            Some(Path::new("sample.pdf"))
+        );
+    }
+
+    #[test]
+    fn parses_layer_selection() {
+        let cli = Cli::parse_from([
+            "pdf-img-extract",
+            "map.pdf",
+            "--layer",
+            "Basemap",
+            "--layer",
+            "Roads",
+        ]);
+        assert_eq!(
+            layer_visibility(cli.extract.all_layers, cli.extract.layers),
+            LayerVisibility::Only(vec!["Basemap".to_string(), "Roads".to_string()])
+        );
+
+        let cli = Cli::parse_from(["pdf-img-extract", "map.pdf", "--all-layers"]);
+        assert_eq!(
+            layer_visibility(cli.extract.all_layers, cli.extract.layers),
+            LayerVisibility::All
+        );
+        assert!(
+            Cli::try_parse_from(["pdf-img-extract", "map.pdf", "--all-layers", "--layer", "A"])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn parses_render_subcommand() {
+        let cli = Cli::parse_from(["pdf-img-extract", "render", "sample.pdf", "--dpi", "300"]);
+        let Some(Command::Render(args)) = cli.command else {
+            panic!("expected render subcommand");
+        };
+        assert_eq!(args.input_pdf, Path::new("sample.pdf"));
+        assert_eq!(args.dpi, 300.0);
+        assert_eq!(args.background, "white");
+    }
+
+    #[test]
+    fn parses_info_subcommand() {
+        let cli = Cli::parse_from(["pdf-img-extract", "info", "sample.pdf", "--format", "json"]);
+        let Some(Command::Info(args)) = cli.command else {
+            panic!("expected info subcommand");
+        };
+        assert_eq!(args.input_pdf, Path::new("sample.pdf"));
+        assert_eq!(args.format, OutputFormat::Json);
+    }
+
+    #[test]
+    fn parses_audit_subcommand() {
+        let cli = Cli::parse_from(["pdf-img-extract", "audit", "report.pdf", "-p", "-3"]);
+        let Some(Command::Audit(args)) = cli.command else {
+            panic!("expected audit subcommand");
+        };
+        assert_eq!(args.input_pdf, Path::new("report.pdf"));
+        assert_eq!(args.pages.as_deref(), Some("-3"));
+        assert_eq!(args.format, OutputFormat::Human);
+    }
+
+    #[test]
+    fn parses_revisions_subcommand() {
+        let cli = Cli::parse_from(["pdf-img-extract", "revisions", "signed.pdf", "-o", "out"]);
+        let Some(Command::Revisions(args)) = cli.command else {
+            panic!("expected revisions subcommand");
+        };
+        assert_eq!(args.input_pdf, Path::new("signed.pdf"));
+        assert_eq!(args.output.as_deref(), Some(Path::new("out")));
+        assert_eq!(args.format, OutputFormat::Human);
+    }
+
+    #[test]
+    fn parses_resource_limits() {
+        let cli = Cli::parse_from([
+            "pdf-img-extract",
+            "scan.pdf",
+            "--max-image-pixels",
+            "1000",
+            "--max-pages",
+            "0",
+        ]);
+        let limits = cli.extract.limits.limits();
+        assert_eq!(limits.max_image_pixels, Some(1000));
+        assert_eq!(limits.max_pages, None);
+        assert_eq!(
+            limits.max_images,
+            extract::ExtractLimits::default().max_images
+        );
+    }
+
+    #[test]
+    fn parses_svg_subcommand() {
+        let cli = Cli::parse_from([
+            "pdf-img-extract",
+            "svg",
+            "paper.pdf",
+            "--figures",
+            "-p",
+            "3",
+        ]);
+        let Some(Command::Svg(args)) = cli.command else {
+            panic!("expected svg subcommand");
+        };
+        assert_eq!(args.input_pdf, Path::new("paper.pdf"));
+        assert!(args.figures);
+        assert_eq!(args.pages.as_deref(), Some("3"));
+        assert!(args.output.is_none());
     }
 }
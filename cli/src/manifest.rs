@@ -0,0 +1,213 @@
+use extract::{ExtractedImage, UnusedImage, UnusedImageKind, XmpMetadata};
+
+use crate::json::{json_array, json_object, json_optional_string, json_string};
+
+pub const MANIFEST_FILE_NAME: &str = "manifest.json";
+
+// 保存した画像1枚分の記述。altからtitleまではタグ付きPDFの構造要素がなければnull。
+// attachmentは添付PDFから取り出した画像の添付パス
+pub fn manifest_entry_json(
+    file_name: &str,
+    extracted: &ExtractedImage,
+    attachment: Option<&str>,
+) -> String {
+    let structure = extracted.structure.as_ref();
+    json_object([
+        ("file", json_string(file_name)),
+        ("page", (extracted.page_index + 1).to_string()),
+        ("pageLabel", json_string(&extracted.page_label)),
+        ("width", extracted.image.width().to_string()),
+        ("height", extracted.image.height().to_string()),
+        (
+            "source",
+            json_string(match extracted.source {
+                extract::ImageSource::XObject => "xobject",
+                extract::ImageSource::Inline => "inline",
+            }),
+        ),
+        (
+            "object",
+            json_optional_string(extracted.object.map(|object| object.to_string()).as_deref()),
+        ),
+        (
+            "annotation",
+            json_optional_string(extracted.annotation.as_deref()),
+        ),
+        (
+            "layers",
+            json_array(extracted.layers.iter().map(|layer| json_string(layer))),
+        ),
+        (
+            "role",
+            json_optional_string(structure.map(|structure| structure.role.as_str())),
+        ),
+        (
+            "alt",
+            json_optional_string(structure.and_then(|structure| structure.alt.as_deref())),
+        ),
+        (
+            "actualText",
+            json_optional_string(structure.and_then(|structure| structure.actual_text.as_deref())),
+        ),
+        (
+            "title",
+            json_optional_string(structure.and_then(|structure| structure.title.as_deref())),
+        ),
+        (
+            "caption",
+            json_optional_string(extracted.caption.as_deref()),
+        ),
+        (
+            "textAbove",
+            json_optional_string(extracted.nearby_text.above.as_deref()),
+        ),
+        (
+            "textBelow",
+            json_optional_string(extracted.nearby_text.below.as_deref()),
+        ),
+        (
+            "xmp",
+            extracted
+                .xmp
+                .as_ref()
+                .map_or_else(|| "null".to_string(), xmp_json),
+        ),
+        ("attachment", json_optional_string(attachment)),
+        ("fragments", extracted.fragment_count.to_string()),
+    ])
+}
+
+// 画像ファイルの添付はデコードしないので、パスと形式だけを載せる
+pub fn attachment_file_entry_json(file_name: &str, source_path: &str, format: &str) -> String {
+    json_object([
+        ("file", json_string(file_name)),
+        ("attachment", json_string(source_path)),
+        ("format", json_string(format)),
+    ])
+}
+
+// 描かれない画像はページ上の位置や構造を持たないので、オブジェクトとリソースに持つページだけを載せる
+pub fn unused_image_entry_json(file_name: &str, unused: &UnusedImage) -> String {
+    json_object([
+        ("file", json_string(file_name)),
+        ("object", json_string(&unused.object.to_string())),
+        (
+            "unused",
+            json_string(match unused.kind {
+                UnusedImageKind::Unpainted => "unpainted",
+                UnusedImageKind::Orphaned => "orphaned",
+            }),
+        ),
+        (
+            "pages",
+            json_array(unused.pages.iter().map(|page| (page + 1).to_string())),
+        ),
+        ("width", unused.image.width().to_string()),
+        ("height", unused.image.height().to_string()),
+    ])
+}
+
+// XMPの全文は大きいので、読み取った項目だけを載せる
+fn xmp_json(xmp: &XmpMetadata) -> String {
+    json_object([
+        ("title", json_optional_string(xmp.title.as_deref())),
+        (
+            "creators",
+            json_array(xmp.creators.iter().map(|creator| json_string(creator))),
+        ),
+        ("rights", json_optional_string(xmp.rights.as_deref())),
+        ("source", json_optional_string(xmp.source.as_deref())),
+    ])
+}
+
+pub fn format_manifest_json(entries: Vec<String>) -> String {
+    json_object([("images", json_array(entries))])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{attachment_file_entry_json, manifest_entry_json, unused_image_entry_json};
+    use extract::{
+        ExtractedImage, ImageBounds, ImageSource, NearbyText, ObjectRef, Orientation,
+        StructureElement, UnusedImage, UnusedImageKind, XmpMetadata,
+    };
+    use image::DynamicImage;
+
+    #[test]
+    fn describes_structure_of_tagged_images() {
+        let extracted = ExtractedImage {
+            page_index: 1,
+            page_label: "ii".to_string(),
+            image: DynamicImage::new_rgb8(4, 3),
+            orientation: Orientation::default(),
+            annotation: None,
+            from_pattern: false,
+            from_type3_glyph: false,
+            glyph_unicode: None,
+            layers: Vec::new(),
+            source: ImageSource::XObject,
+            object: Some(ObjectRef {
+                number: 12,
+                generation: 0,
+            }),
+            structure: Some(StructureElement {
+                object: None,
+                role: "Figure".to_string(),
+                alt: Some("Sales by \"region\"".to_string()),
+                actual_text: None,
+                title: None,
+            }),
+            artifact: false,
+            bounds: ImageBounds::default(),
+            caption: Some("Figure 1: Sales".to_string()),
+            nearby_text: NearbyText {
+                above: None,
+                below: Some("Figure 1: Sales".to_string()),
+            },
+            xmp: Some(XmpMetadata {
+                raw: "<x:xmpmeta/>".to_string(),
+                title: None,
+                creators: vec!["Ann Lee".to_string()],
+                rights: Some("CC BY 4.0".to_string()),
+                source: None,
+            }),
+            fragment_count: 1,
+        };
+        assert_eq!(
+            manifest_entry_json("page-2-image-1.png", &extracted, None),
+            concat!(
+                r#"{"file":"page-2-image-1.png","page":2,"pageLabel":"ii","width":4,"height":3,"#,
+                r#""source":"xobject","object":"12 0 R","annotation":null,"layers":[],"#,
+                r#""role":"Figure","alt":"Sales by \"region\"","actualText":null,"title":null,"#,
+                r#""caption":"Figure 1: Sales","textAbove":null,"textBelow":"Figure 1: Sales","#,
+                r#""xmp":{"title":null,"creators":["Ann Lee"],"rights":"CC BY 4.0","source":null},"#,
+                r#""attachment":null,"fragments":1}"#
+            )
+        );
+    }
+
+    #[test]
+    fn describes_image_file_attachments() {
+        assert_eq!(
+            attachment_file_entry_json("attachment-photo.jpg", "claim.pdf/photo.jpg", "jpg"),
+            r#"{"file":"attachment-photo.jpg","attachment":"claim.pdf/photo.jpg","format":"jpg"}"#
+        );
+    }
+
+    #[test]
+    fn describes_unused_images() {
+        let unused = UnusedImage {
+            object: ObjectRef {
+                number: 9,
+                generation: 0,
+            },
+            kind: UnusedImageKind::Unpainted,
+            pages: vec![0, 2],
+            image: DynamicImage::new_rgba8(4, 3),
+        };
+        assert_eq!(
+            unused_image_entry_json("unused-9-0.png", &unused),
+            r#"{"file":"unused-9-0.png","object":"9 0 R","unused":"unpainted","pages":[1,3],"width":4,"height":3}"#
+        );
+    }
+}
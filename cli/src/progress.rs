@@ -0,0 +1,91 @@
+use std::io::{self, IsTerminal, Write};
+
+use extract::ExtractProgress;
+
+const BAR_WIDTH: usize = 30;
+
+pub struct ProgressBar {
+    enabled: bool,
+    drawn: bool,
+}
+
+impl ProgressBar {
+    // リダイレクト先を汚さないよう、stderrが端末のときだけ描画する
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled: enabled && io::stderr().is_terminal(),
+            drawn: false,
+        }
+    }
+
+    pub fn update(&mut self, progress: &ExtractProgress) {
+        if !self.enabled {
+            return;
+        }
+        let mut stderr = io::stderr().lock();
+        let _ = write!(
+            stderr,
+            "\r\x1b[2K{}",
+            format_progress_line(progress, BAR_WIDTH)
+        );
+        let _ = stderr.flush();
+        self.drawn = true;
+    }
+
+    // 警告や結果を出す前にバーの行を消す
+    pub fn finish(&mut self) {
+        if !self.drawn {
+            return;
+        }
+        let mut stderr = io::stderr().lock();
+        let _ = write!(stderr, "\r\x1b[2K");
+        let _ = stderr.flush();
+        self.drawn = false;
+    }
+}
+
+pub fn format_progress_line(progress: &ExtractProgress, width: usize) -> String {
+    let filled = (width * progress.pages_done.min(progress.total_pages))
+        .checked_div(progress.total_pages)
+        .unwrap_or(width);
+    format!(
+        "[{}{}] {}/{} pages, {} images",
+        "#".repeat(filled),
+        "-".repeat(width - filled),
+        progress.pages_done,
+        progress.total_pages,
+        progress.images_found
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::format_progress_line;
+    use extract::ExtractProgress;
+
+    #[test]
+    fn formats_partial_progress() {
+        let progress = ExtractProgress {
+            pages_done: 5,
+            total_pages: 10,
+            images_found: 3,
+        };
+        assert_eq!(
+            format_progress_line(&progress, 10),
+            "[#####-----] 5/10 pages, 3 images"
+        );
+    }
+
+    #[test]
+    fn empty_selection_is_complete() {
+        let progress = ExtractProgress {
+            pages_done: 0,
+            total_pages: 0,
+            images_found: 0,
+        };
+        assert_eq!(
+            format_progress_line(&progress, 4),
+            "[####] 0/0 pages, 0 images"
+        );
+    }
+}
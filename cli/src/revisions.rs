@@ -0,0 +1,136 @@
+use extract::{ExtractRevisionsResult, ImageChange, ImageChangeKind, RevisionImages};
+
+use crate::json::{json_array, json_object, json_optional_string, json_string};
+
+pub fn format_revisions_human(result: &ExtractRevisionsResult) -> String {
+    let mut lines = vec![format!("Revisions: {}", result.revisions.len())];
+    for revision in &result.revisions {
+        lines.push(format!("  {}", describe_revision(revision)));
+        for change in &revision.changes {
+            lines.push(format!("    {}", describe_change(change)));
+        }
+    }
+    lines.join("\n")
+}
+
+fn describe_revision(revision: &RevisionImages) -> String {
+    let mut text = format!(
+        "revision {}: {} bytes, images {}",
+        revision.revision.index + 1,
+        revision.revision.byte_length,
+        revision.image_count
+    );
+    // 最初の版は比べる相手がない
+    if revision.revision.index > 0 {
+        text.push_str(&format!(", changes {}", revision.changes.len()));
+    }
+    text
+}
+
+fn describe_change(change: &ImageChange) -> String {
+    let mut text = change_kind_code(change.kind).to_string();
+    match change.object {
+        Some(object) => text.push_str(&format!(" {object}")),
+        None => text.push_str(" inline image"),
+    }
+    let pages = change
+        .pages
+        .iter()
+        .map(|page| (page + 1).to_string())
+        .collect::<Vec<_>>();
+    match pages.len() {
+        0 => {}
+        1 => text.push_str(&format!(" on page {}", pages[0])),
+        _ => text.push_str(&format!(" on pages {}", pages.join(", "))),
+    }
+    text
+}
+
+pub fn format_revisions_json(result: &ExtractRevisionsResult) -> String {
+    let revisions = result.revisions.iter().map(|revision| {
+        let changes = revision.changes.iter().map(|change| {
+            json_object([
+                ("kind", json_string(change_kind_code(change.kind))),
+                (
+                    "object",
+                    json_optional_string(change.object.map(|object| object.to_string()).as_deref()),
+                ),
+                (
+                    "pages",
+                    json_array(change.pages.iter().map(|page| (page + 1).to_string())),
+                ),
+            ])
+        });
+        json_object([
+            ("revision", (revision.revision.index + 1).to_string()),
+            ("byteLength", revision.revision.byte_length.to_string()),
+            ("imageCount", revision.image_count.to_string()),
+            ("changes", json_array(changes)),
+        ])
+    });
+    json_object([("revisions", json_array(revisions))])
+}
+
+fn change_kind_code(kind: ImageChangeKind) -> &'static str {
+    match kind {
+        ImageChangeKind::Added => "added",
+        ImageChangeKind::Removed => "removed",
+        ImageChangeKind::Modified => "modified",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{describe_change, format_revisions_json};
+    use extract::{
+        ExtractRevisionsResult, ImageChange, ImageChangeKind, ObjectRef, Revision, RevisionImages,
+    };
+
+    fn removed_image() -> ImageChange {
+        ImageChange {
+            kind: ImageChangeKind::Removed,
+            object: Some(ObjectRef {
+                number: 5,
+                generation: 0,
+            }),
+            pages: vec![0, 2],
+        }
+    }
+
+    #[test]
+    fn describes_changes_with_pages() {
+        assert_eq!(
+            describe_change(&removed_image()),
+            "removed 5 0 R on pages 1, 3"
+        );
+        let inline = ImageChange {
+            kind: ImageChangeKind::Added,
+            object: None,
+            pages: vec![1],
+        };
+        assert_eq!(describe_change(&inline), "added inline image on page 2");
+    }
+
+    #[test]
+    fn formats_json_report() {
+        let result = ExtractRevisionsResult {
+            revisions: vec![RevisionImages {
+                revision: Revision {
+                    index: 1,
+                    byte_length: 2048,
+                },
+                image_count: 0,
+                images: Vec::new(),
+                errors: Vec::new(),
+                changes: vec![removed_image()],
+            }],
+        };
+        assert_eq!(
+            format_revisions_json(&result),
+            concat!(
+                r#"{"revisions":[{"revision":2,"byteLength":2048,"imageCount":0,"#,
+                r#""changes":[{"kind":"removed","object":"5 0 R","pages":[1,3]}]}]}"#
+            )
+        );
+    }
+}
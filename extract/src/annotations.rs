@@ -0,0 +1,126 @@
+use hayro_interpret::RectExt;
+use hayro_syntax::object::dict::keys::{ANNOTS, AP, AS, BBOX, F, MATRIX, N, OC, RECT, SUBTYPE};
+use hayro_syntax::object::{Array, Dict, Name, ObjRef, Rect as PdfRect, Stream};
+use hayro_syntax::page::Page;
+use kurbo::{Affine, Rect, Shape};
+
+// 注釈フラグのHidden（表示も印刷もしない）
+const HIDDEN_FLAG: u32 = 1 << 1;
+
+pub(crate) struct Appearance<'a> {
+    // /Subtype（Stamp、Widgetなど）
+    pub(crate) subtype: String,
+    pub(crate) stream: Stream<'a>,
+    // 外観ストリームの座標からページ座標への変換
+    pub(crate) transform: Affine,
+    // 外観ストリームの/BBox（外観ストリームの座標）
+    pub(crate) bbox: Rect,
+    // 注釈の/OC（レイヤ）
+    pub(crate) optional_content: Option<ObjRef>,
+}
+
+// ページの注釈のうち、表示される通常外観（/AP /N）を持つものを列挙する
+pub(crate) fn annotation_appearances<'a>(page: &Page<'a>) -> Vec<Appearance<'a>> {
+    let Some(annotations) = page.raw().get::<Array<'_>>(ANNOTS) else {
+        return Vec::new();
+    };
+
+    annotations
+        .iter::<Dict<'_>>()
+        .filter(|annotation| annotation.get::<u32>(F).unwrap_or(0) & HIDDEN_FLAG == 0)
+        .filter_map(|annotation| {
+            let stream = normal_appearance(&annotation)?;
+            let rect = annotation.get::<PdfRect>(RECT)?.to_kurbo();
+            let dict = stream.dict();
+            let [x0, y0, x1, y1] = dict.get::<[f64; 4]>(BBOX)?;
+            let bbox = Rect::new(x0, y0, x1, y1);
+            let matrix = Affine::new(
+                dict.get::<[f64; 6]>(MATRIX)
+                    .unwrap_or([1.0, 0.0, 0.0, 1.0, 0.0, 0.0]),
+            );
+            let transform = appearance_transform(rect, bbox, matrix)?;
+            let subtype = annotation
+                .get::<Name<'_>>(SUBTYPE)
+                .map(|name| name.as_str().to_string())
+                .unwrap_or_default();
+            Some(Appearance {
+                subtype,
+                stream,
+                transform,
+                bbox,
+                optional_content: annotation.get_ref(OC),
+            })
+        })
+        .collect()
+}
+
+// /Nが状態ごとの辞書（チェックボックスなど）の場合は/ASで選ばれている外観を使う
+fn normal_appearance<'a>(annotation: &Dict<'a>) -> Option<Stream<'a>> {
+    let appearances = annotation.get::<Dict<'_>>(AP)?;
+    if let Some(stream) = appearances.get::<Stream<'_>>(N) {
+        return Some(stream);
+    }
+    let state = annotation.get::<Name<'_>>(AS)?;
+    appearances.get::<Dict<'_>>(N)?.get::<Stream<'_>>(&*state)
+}
+
+// PDF 32000-1 12.5.5: /Matrixで変換した/BBoxの外接矩形を/Rectに合わせる
+fn appearance_transform(rect: Rect, bbox: Rect, matrix: Affine) -> Option<Affine> {
+    let transformed = (matrix * bbox.to_path(0.1)).bounding_box();
+    if transformed.width() <= 0.0 || transformed.height() <= 0.0 {
+        return None;
+    }
+    let fit = Affine::new([
+        rect.width() / transformed.width(),
+        0.0,
+        0.0,
+        rect.height() / transformed.height(),
+        rect.x0 - transformed.x0 * rect.width() / transformed.width(),
+        rect.y0 - transformed.y0 * rect.height() / transformed.height(),
+    ]);
+    Some(fit * matrix)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::appearance_transform;
+    use kurbo::{Affine, Point, Rect};
+
+    #[test]
+    fn appearance_is_fitted_to_the_annotation_rect() {
+        let transform = appearance_transform(
+            Rect::new(100.0, 200.0, 200.0, 250.0),
+            Rect::new(0.0, 0.0, 50.0, 25.0),
+            Affine::IDENTITY,
+        )
+        .unwrap();
+        assert_eq!(transform * Point::new(0.0, 0.0), Point::new(100.0, 200.0));
+        assert_eq!(transform * Point::new(50.0, 25.0), Point::new(200.0, 250.0));
+    }
+
+    #[test]
+    fn rotated_appearance_matrix_is_kept() {
+        // 90度回転した外観も/Rectの範囲に収まる
+        let matrix = Affine::new([0.0, 1.0, -1.0, 0.0, 0.0, 0.0]);
+        let transform = appearance_transform(
+            Rect::new(0.0, 0.0, 20.0, 40.0),
+            Rect::new(0.0, 0.0, 40.0, 20.0),
+            matrix,
+        )
+        .unwrap();
+        assert_eq!(transform * Point::new(0.0, 0.0), Point::new(20.0, 0.0));
+        assert_eq!(transform * Point::new(40.0, 20.0), Point::new(0.0, 40.0));
+    }
+
+    #[test]
+    fn empty_bbox_is_skipped() {
+        assert!(
+            appearance_transform(
+                Rect::new(0.0, 0.0, 10.0, 10.0),
+                Rect::new(0.0, 0.0, 0.0, 10.0),
+                Affine::IDENTITY,
+            )
+            .is_none()
+        );
+    }
+}
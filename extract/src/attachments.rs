@@ -0,0 +1,555 @@
+use std::collections::HashSet;
+
+use hayro_syntax::Pdf;
+use hayro_syntax::object::dict::keys::{
+    ANNOTS, EF, EMBEDDED_FILES, F, FS, KIDS, NAMES, PARAMS, SIZE, SUBTYPE, UF,
+};
+use hayro_syntax::object::{Array, Dict, Name, ObjRef, Stream, String as PdfString};
+use hayro_syntax::xref::XRef;
+use thiserror::Error;
+
+use crate::limits::{StreamDecodeError, decode_stream_within};
+use crate::number_tree::resolve;
+use crate::text::decode_text_string;
+use crate::{
+    ExtractImageWarning, ExtractImageWarningKind, ExtractOptions, ExtractedImage,
+    extract_images_with_options, open_pdf,
+};
+
+// 添付PDFの中の添付をたどる深さの上限（自分自身を添付したPDFでも止まるように）
+pub(crate) const MAX_ATTACHMENT_DEPTH: usize = 4;
+// ツリーが循環していても止まるように深さを制限する
+const MAX_TREE_DEPTH: usize = 32;
+// PDFのヘッダはファイルの先頭1024バイト以内にあればよい
+const PDF_HEADER_SEARCH_BYTES: usize = 1024;
+
+// 添付ファイルから見つかった画像
+#[derive(Debug)]
+pub struct AttachmentImage {
+    // 添付ファイル名を"/"でつないだパス。添付PDFのページの画像には"#page3"のように1始まりのページ番号を付ける
+    pub source_path: String,
+    pub content: AttachmentContent,
+}
+
+#[derive(Debug)]
+pub enum AttachmentContent {
+    // 画像ファイルの添付（デコードせず、バイト列をそのまま返す）
+    File {
+        bytes: Vec<u8>,
+        format: AttachmentFormat,
+    },
+    // 添付PDFのページから取り出した画像
+    Extracted(Box<ExtractedImage>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttachmentFormat {
+    Jpeg,
+    Png,
+    Gif,
+    Tiff,
+    Webp,
+    Bmp,
+}
+
+impl AttachmentFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            AttachmentFormat::Jpeg => "jpg",
+            AttachmentFormat::Png => "png",
+            AttachmentFormat::Gif => "gif",
+            AttachmentFormat::Tiff => "tif",
+            AttachmentFormat::Webp => "webp",
+            AttachmentFormat::Bmp => "bmp",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AttachmentWarning {
+    pub source_path: String,
+    pub kind: AttachmentWarningKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum AttachmentWarningKind {
+    #[error("failed to decode the embedded file stream")]
+    StreamDecodeFailed,
+    #[error("failed to extract images from attached pdf: {message}")]
+    PdfExtractFailed { message: String },
+    #[error("attachments nested deeper than {MAX_ATTACHMENT_DEPTH} levels were skipped")]
+    TooDeep,
+    #[error(transparent)]
+    LimitReached(ExtractImageWarningKind),
+    #[error("{}", describe_image_warning(.0))]
+    Image(ExtractImageWarning),
+}
+
+fn describe_image_warning(warning: &ExtractImageWarning) -> String {
+    match warning.image_index {
+        Some(image_index) => format!(
+            "page {} image {}: {}",
+            warning.page_index + 1,
+            image_index + 1,
+            warning.kind
+        ),
+        None => format!("page {}: {}", warning.page_index + 1, warning.kind),
+    }
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Attachments {
+    pub(crate) images: Vec<AttachmentImage>,
+    pub(crate) warnings: Vec<AttachmentWarning>,
+    // 上限に達して残りを読まなかった
+    pub(crate) limit_reached: bool,
+}
+
+// 添付ファイルの中身と、参照元での名前
+struct EmbeddedFile<'a> {
+    name: String,
+    stream: Stream<'a>,
+}
+
+// /EmbeddedFilesの名前ツリーと、選ばれたページのファイル添付注釈から画像を集める。
+// page_count、image_countとdecoded_bytesは本文で処理した分で、上限はそれと合わせて数える
+pub(crate) fn collect_attachments(
+    pdf: &Pdf,
+    selected_pages: Option<&[bool]>,
+    options: &ExtractOptions,
+    page_count: usize,
+    image_count: usize,
+    decoded_bytes: u64,
+) -> Attachments {
+    let mut collector = Collector {
+        options,
+        page_count,
+        image_count,
+        decoded_bytes,
+        attachments: Attachments::default(),
+    };
+    collector.collect(pdf, selected_pages, "", 0);
+    collector.attachments
+}
+
+struct Collector<'o> {
+    options: &'o ExtractOptions,
+    page_count: usize,
+    image_count: usize,
+    decoded_bytes: u64,
+    attachments: Attachments,
+}
+
+impl Collector<'_> {
+    fn collect(&mut self, pdf: &Pdf, selected_pages: Option<&[bool]>, prefix: &str, depth: usize) {
+        for file in embedded_files(pdf, selected_pages) {
+            if self.attachments.limit_reached || self.options.cancellation.is_cancelled() {
+                return;
+            }
+            let source_path = format!("{prefix}{}", file.name);
+            let bytes = match self.decode(&file.stream) {
+                Ok(bytes) => bytes,
+                Err(StreamDecodeError::Failed) => {
+                    self.warn(&source_path, AttachmentWarningKind::StreamDecodeFailed);
+                    continue;
+                }
+                Err(StreamDecodeError::TooLarge) => {
+                    self.attachments.limit_reached = true;
+                    self.warn(
+                        &source_path,
+                        AttachmentWarningKind::LimitReached(
+                            ExtractImageWarningKind::DecodedBytesLimitReached,
+                        ),
+                    );
+                    return;
+                }
+            };
+            if let Some(format) = detect_image_format(&bytes) {
+                self.push_file(source_path, bytes, format);
+            } else if is_pdf(&bytes) {
+                if depth + 1 >= MAX_ATTACHMENT_DEPTH {
+                    self.warn(&source_path, AttachmentWarningKind::TooDeep);
+                    continue;
+                }
+                self.collect_pdf(&bytes, &source_path, depth + 1);
+            }
+        }
+    }
+
+    // 残りの予算を超える添付は、/Params /Sizeで分かればデコードせず、分からなければ途中で展開をやめる
+    fn decode(&self, stream: &Stream<'_>) -> Result<Vec<u8>, StreamDecodeError> {
+        let remaining = self
+            .options
+            .limits
+            .max_total_decoded_bytes
+            .map_or(u64::MAX, |limit| limit.saturating_sub(self.decoded_bytes));
+        let declared_size = stream
+            .dict()
+            .get::<Dict<'_>>(PARAMS)
+            .and_then(|params| params.get::<i64>(SIZE));
+        if declared_size.is_some_and(|size| u64::try_from(size).is_ok_and(|size| size > remaining))
+        {
+            return Err(StreamDecodeError::TooLarge);
+        }
+        decode_stream_within(stream, remaining)
+    }
+
+    // 添付PDFは全ページから取り出し、さらにその添付もたどる
+    fn collect_pdf(&mut self, bytes: &[u8], source_path: &str, depth: usize) {
+        let pdf = match open_pdf(bytes) {
+            Ok(pdf) => pdf,
+            Err(err) => {
+                self.warn(
+                    source_path,
+                    AttachmentWarningKind::PdfExtractFailed {
+                        message: err.to_string(),
+                    },
+                );
+                return;
+            }
+        };
+        let mut options = self.options.clone();
+        options.include_attachments = false;
+        options.include_unused = false;
+        options.limits.max_pages = options
+            .limits
+            .max_pages
+            .map(|limit| limit.saturating_sub(self.page_count));
+        // 残りのページ数に収まらなければ、ほかの上限と同じく残りの添付も読まない
+        if options.limits.check_page_count(pdf.pages().len()).is_err() {
+            self.attachments.limit_reached = true;
+            self.warn(
+                source_path,
+                AttachmentWarningKind::LimitReached(ExtractImageWarningKind::PageLimitReached),
+            );
+            return;
+        }
+        options.limits.max_images = options
+            .limits
+            .max_images
+            .map(|limit| limit.saturating_sub(self.image_count));
+        options.limits.max_total_decoded_bytes = options
+            .limits
+            .max_total_decoded_bytes
+            .map(|limit| limit.saturating_sub(self.decoded_bytes));
+        let result = match extract_images_with_options(bytes, &[], &options) {
+            Ok(result) => result,
+            Err(err) => {
+                self.warn(
+                    source_path,
+                    AttachmentWarningKind::PdfExtractFailed {
+                        message: err.to_string(),
+                    },
+                );
+                return;
+            }
+        };
+        self.page_count += pdf.pages().len();
+        for image in result.images {
+            self.image_count += 1;
+            self.decoded_bytes = self
+                .decoded_bytes
+                .saturating_add(image.image.as_bytes().len() as u64);
+            self.attachments.images.push(AttachmentImage {
+                source_path: format!("{source_path}#page{}", image.page_index + 1),
+                content: AttachmentContent::Extracted(Box::new(image)),
+            });
+        }
+        for warning in result.errors {
+            if matches!(
+                warning.kind,
+                ExtractImageWarningKind::ImageLimitReached
+                    | ExtractImageWarningKind::DecodedBytesLimitReached
+            ) {
+                self.attachments.limit_reached = true;
+            }
+            self.warn(source_path, AttachmentWarningKind::Image(warning));
+        }
+        if !self.attachments.limit_reached {
+            self.collect(&pdf, None, &format!("{source_path}/"), depth);
+        }
+    }
+
+    fn push_file(&mut self, source_path: String, bytes: Vec<u8>, format: AttachmentFormat) {
+        let limits = &self.options.limits;
+        let decoded_bytes = self.decoded_bytes.saturating_add(bytes.len() as u64);
+        let checked = limits
+            .check_image_count(self.image_count)
+            .and_then(|()| limits.check_decoded_bytes(decoded_bytes));
+        if let Err(kind) = checked {
+            self.attachments.limit_reached = true;
+            self.warn(&source_path, AttachmentWarningKind::LimitReached(kind));
+            return;
+        }
+        self.image_count += 1;
+        self.decoded_bytes = decoded_bytes;
+        self.attachments.images.push(AttachmentImage {
+            source_path,
+            content: AttachmentContent::File { bytes, format },
+        });
+    }
+
+    fn warn(&mut self, source_path: &str, kind: AttachmentWarningKind) {
+        self.attachments.warnings.push(AttachmentWarning {
+            source_path: source_path.to_string(),
+            kind,
+        });
+    }
+}
+
+// 同じファイルが名前ツリーと注釈の両方から参照されていても1回だけ返す
+fn embedded_files<'a>(pdf: &'a Pdf, selected_pages: Option<&[bool]>) -> Vec<EmbeddedFile<'a>> {
+    let xref = pdf.xref();
+    let mut files = Vec::new();
+    let mut seen: HashSet<ObjRef> = HashSet::new();
+    let mut push = |key: Option<String>, file_spec: Dict<'a>| {
+        let Some(ef) = file_spec.get::<Dict<'_>>(EF) else {
+            return;
+        };
+        let Some(stream) = ef.get::<Stream<'_>>(UF).or_else(|| ef.get::<Stream<'_>>(F)) else {
+            return;
+        };
+        if let Some(obj_ref) = ef.get_ref(UF).or_else(|| ef.get_ref(F))
+            && !seen.insert(obj_ref)
+        {
+            return;
+        }
+        let name = file_name(&file_spec)
+            .or(key)
+            .unwrap_or_else(|| format!("attachment-{}", files.len() + 1));
+        files.push(EmbeddedFile { name, stream });
+    };
+
+    if let Some(tree) = xref
+        .get::<Dict<'_>>(xref.root_id())
+        .and_then(|catalog| catalog.get::<Dict<'_>>(NAMES))
+        .and_then(|names| names.get::<Dict<'_>>(EMBEDDED_FILES))
+    {
+        for (key, file_spec) in name_tree_entries(xref, &tree) {
+            push(Some(key), file_spec);
+        }
+    }
+    for (i, page) in pdf.pages().iter().enumerate() {
+        if selected_pages.is_some_and(|selected| !selected[i]) {
+            continue;
+        }
+        let Some(annotations) = page.raw().get::<Array<'_>>(ANNOTS) else {
+            continue;
+        };
+        for annotation in annotations.iter::<Dict<'_>>() {
+            if annotation
+                .get::<Name<'_>>(SUBTYPE)
+                .is_some_and(|subtype| subtype.as_str() == "FileAttachment")
+                && let Some(file_spec) = annotation.get::<Dict<'_>>(FS)
+            {
+                push(None, file_spec);
+            }
+        }
+    }
+    files
+}
+
+// ファイル指定の/UF（Unicode）、/Fの順に名前を読む。パスの区切りは残さない
+fn file_name(file_spec: &Dict<'_>) -> Option<String> {
+    let name = file_spec
+        .get::<PdfString<'_>>(UF)
+        .or_else(|| file_spec.get::<PdfString<'_>>(F))
+        .map(|value| decode_text_string(&value.get()))?;
+    let name = name.rsplit(['/', '\\']).next().unwrap_or_default().trim();
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+// 名前ツリー（/Names、/Kids）のキーと辞書の値をキー順に集める
+fn name_tree_entries<'a>(xref: &'a XRef, root: &Dict<'a>) -> Vec<(String, Dict<'a>)> {
+    let mut entries = Vec::new();
+    collect_name_tree(xref, root, &mut entries, 0);
+    entries
+}
+
+fn collect_name_tree<'a>(
+    xref: &'a XRef,
+    node: &Dict<'a>,
+    entries: &mut Vec<(String, Dict<'a>)>,
+    depth: usize,
+) {
+    if depth > MAX_TREE_DEPTH {
+        return;
+    }
+    if let Some(names) = node.get::<Array<'a>>(NAMES) {
+        let mut items = names.raw_iter();
+        while let (Some(key), Some(value)) = (items.next(), items.next()) {
+            let key = resolve(xref, key)
+                .and_then(|key| PdfString::try_from(key).ok())
+                .map(|key| decode_text_string(&key.get()));
+            let value = resolve(xref, value).and_then(|value| value.into_dict());
+            if let (Some(key), Some(value)) = (key, value) {
+                entries.push((key, value));
+            }
+        }
+    }
+    if let Some(kids) = node.get::<Array<'a>>(KIDS) {
+        for kid in kids.iter::<Dict<'a>>() {
+            collect_name_tree(xref, &kid, entries, depth + 1);
+        }
+    }
+}
+
+// 拡張子や/Subtypeは当てにならないので、先頭のバイトで判定する
+pub(crate) fn detect_image_format(bytes: &[u8]) -> Option<AttachmentFormat> {
+    if bytes.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        Some(AttachmentFormat::Jpeg)
+    } else if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some(AttachmentFormat::Png)
+    } else if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        Some(AttachmentFormat::Gif)
+    } else if bytes.starts_with(b"II*\0") || bytes.starts_with(b"MM\0*") {
+        Some(AttachmentFormat::Tiff)
+    } else if bytes.starts_with(b"RIFF") && bytes.get(8..12) == Some(b"WEBP") {
+        Some(AttachmentFormat::Webp)
+    } else if bytes.starts_with(b"BM") && bytes.len() >= 26 {
+        Some(AttachmentFormat::Bmp)
+    } else {
+        None
+    }
+}
+
+fn is_pdf(bytes: &[u8]) -> bool {
+    bytes[..bytes.len().min(PDF_HEADER_SEARCH_BYTES)]
+        .windows(5)
+        .any(|window| window == b"%PDF-")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        AttachmentContent, AttachmentFormat, AttachmentWarning, AttachmentWarningKind,
+        detect_image_format, is_pdf,
+    };
+    use crate::tests::{pixel_image, single_page_pdf, stream_object};
+    use crate::{
+        ExtractImageWarningKind, ExtractLimits, ExtractOptions, extract_images_with_options,
+    };
+
+    const PNG_HEADER: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+
+    // ページが1枚で、5 0 Rの添付ファイルを/EmbeddedFilesに持つPDF
+    fn pdf_with_attachment(name: &str, params: &str, bytes: &[u8]) -> Vec<u8> {
+        single_page_pdf(
+            &format!("/Names << /EmbeddedFiles << /Names [({name}) 6 0 R] >> >>"),
+            "<< >>",
+            "",
+            &[
+                stream_object(&format!("/Type /EmbeddedFile {params}"), bytes),
+                format!("<< /Type /Filespec /F ({name}) /EF << /F 5 0 R >> >>").into_bytes(),
+            ],
+        )
+    }
+
+    fn options(limits: ExtractLimits) -> ExtractOptions {
+        ExtractOptions {
+            limits,
+            include_attachments: true,
+            ..ExtractOptions::default()
+        }
+    }
+
+    #[test]
+    fn detects_image_formats_from_magic_bytes() {
+        assert_eq!(
+            detect_image_format(&[0xFF, 0xD8, 0xFF, 0xE0, 0, 0x10]),
+            Some(AttachmentFormat::Jpeg)
+        );
+        assert_eq!(
+            detect_image_format(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"),
+            Some(AttachmentFormat::Png)
+        );
+        assert_eq!(
+            detect_image_format(b"RIFF\x24\0\0\0WEBPVP8 "),
+            Some(AttachmentFormat::Webp)
+        );
+        assert_eq!(detect_image_format(b"RIFF\x24\0\0\0WAVEfmt "), None);
+        assert_eq!(detect_image_format(b"%PDF-1.7\n"), None);
+    }
+
+    #[test]
+    fn finds_pdf_header_after_leading_garbage() {
+        assert!(is_pdf(b"%PDF-1.4\n"));
+        assert!(is_pdf(b"\r\n\xef\xbb\xbf%PDF-1.7\n"));
+        assert!(!is_pdf(b"PK\x03\x04"));
+    }
+
+    #[test]
+    fn skips_files_over_the_remaining_budget_before_decoding() {
+        let limits = ExtractLimits {
+            max_total_decoded_bytes: Some(100),
+            ..ExtractLimits::default()
+        };
+        let declared = pdf_with_attachment("a.png", "/Params << /Size 1000 >>", PNG_HEADER);
+        let result = extract_images_with_options(&declared, &[], &options(limits)).unwrap();
+        assert!(result.attachments.is_empty());
+        assert_eq!(
+            result.attachment_errors[0].kind,
+            AttachmentWarningKind::LimitReached(ExtractImageWarningKind::DecodedBytesLimitReached)
+        );
+
+        let undeclared = pdf_with_attachment("a.png", "", PNG_HEADER);
+        let result = extract_images_with_options(&undeclared, &[], &options(limits)).unwrap();
+        assert_eq!(result.attachments.len(), 1);
+        assert!(result.attachment_errors.is_empty());
+    }
+
+    #[test]
+    fn counts_pages_of_attached_pdfs_against_the_page_limit() {
+        let attached = single_page_pdf("", "<< >>", "", &[]);
+        let pdf = pdf_with_attachment("b.pdf", "", &attached);
+        let limits = |max_pages| ExtractLimits {
+            max_pages: Some(max_pages),
+            ..ExtractLimits::default()
+        };
+
+        let result = extract_images_with_options(&pdf, &[], &options(limits(2))).unwrap();
+        assert!(result.attachment_errors.is_empty());
+
+        let result = extract_images_with_options(&pdf, &[], &options(limits(1))).unwrap();
+        assert_eq!(
+            result.attachment_errors,
+            [AttachmentWarning {
+                source_path: "b.pdf".to_string(),
+                kind: AttachmentWarningKind::LimitReached(
+                    ExtractImageWarningKind::PageLimitReached
+                ),
+            }]
+        );
+    }
+
+    #[test]
+    fn extracts_images_of_attached_and_nested_pdfs() {
+        let inner = single_page_pdf(
+            "",
+            "<< /XObject << /Im 5 0 R >> >>",
+            "/Im Do",
+            &[pixel_image([255, 0, 0])],
+        );
+        let source_paths = |pdf: &[u8]| {
+            let result =
+                extract_images_with_options(pdf, &[], &options(ExtractLimits::default())).unwrap();
+            assert!(result.attachment_errors.is_empty());
+            result
+                .attachments
+                .iter()
+                .map(|attachment| match &attachment.content {
+                    AttachmentContent::Extracted(image) => {
+                        assert_eq!(image.image.as_bytes(), [255, 0, 0]);
+                        attachment.source_path.clone()
+                    }
+                    AttachmentContent::File { .. } => panic!("expected an extracted image"),
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let attached = pdf_with_attachment("b.pdf", "", &inner);
+        assert_eq!(source_paths(&attached), ["b.pdf#page1"]);
+        let nested = pdf_with_attachment("a.pdf", "", &attached);
+        assert_eq!(source_paths(&nested), ["a.pdf/b.pdf#page1"]);
+    }
+}
@@ -0,0 +1,235 @@
+use hayro_syntax::object::ObjectIdentifier;
+
+use crate::page_labels::read_page_labels;
+use crate::structure::StructureTree;
+use crate::{
+    ExtractError, ExtractImageWarning, ExtractOptions, ImageBounds, ObjectRef, PageRange,
+    StructureElement, inspect_images, open_pdf, select_pages_within_limit,
+};
+
+#[derive(Debug, Clone)]
+pub struct AccessibilityAudit {
+    // /StructTreeRootがある（タグ付きPDF）か
+    pub tagged: bool,
+    // 調べた画像（/Artifactの中のものも含む）と、構造ツリーのFigure要素の数
+    pub image_count: usize,
+    pub figure_count: usize,
+    // ページ順。ページの分からないFigure要素は最後
+    pub issues: Vec<AuditIssue>,
+    // 解釈に失敗したページや上限を超えた後の画像は調べられていない
+    pub warnings: Vec<ExtractImageWarning>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuditIssue {
+    pub kind: AuditIssueKind,
+    pub page_index: Option<usize>,
+    pub page_label: Option<String>,
+    // ページ内で何番目に取り出された画像か（画像のないFigure要素ならNone）
+    pub image_index: Option<usize>,
+    pub bounds: Option<ImageBounds>,
+    // 画像XObjectの参照
+    pub object: Option<ObjectRef>,
+    // 構造要素の参照と役割
+    pub element: Option<ObjectRef>,
+    pub role: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditIssueKind {
+    // Figure要素、または画像を含む構造要素に/Altも/ActualTextもない
+    MissingAltText,
+    // どの構造要素にも/Artifactにも含まれない画像
+    UntaggedImage,
+    // 空の/Altで装飾と示されているのに/Artifactになっていない画像
+    DecorativeNotArtifact,
+}
+
+pub fn audit_accessibility(
+    pdf_bytes: &[u8],
+    page_ranges: &[PageRange],
+    options: &ExtractOptions,
+) -> Result<AccessibilityAudit, ExtractError> {
+    // 画像はデコードしないので、壊れた画像や大きすぎる画像も調べられる
+    let (images, warnings) = inspect_images(pdf_bytes, page_ranges, options)?;
+    let pdf = open_pdf(pdf_bytes)?;
+    let pages = pdf.pages();
+    let page_labels = read_page_labels(pdf.xref(), pages.len());
+    let (selected_pages, _) = select_pages_within_limit(pages.len(), page_ranges, &options.limits)?;
+    let structure = StructureTree::new(pdf.xref());
+
+    let mut issues = Vec::new();
+    let mut linked_elements = Vec::new();
+    for image in &images {
+        if let Some(kind) = image_issue(image.structure.as_ref(), image.artifact) {
+            issues.push(AuditIssue {
+                kind,
+                page_index: Some(image.page_index),
+                page_label: Some(image.page_label.clone()),
+                image_index: Some(image.image_index),
+                bounds: Some(image.bounds),
+                object: image.object,
+                element: image.structure.as_ref().and_then(|element| element.object),
+                role: image.structure.as_ref().map(|element| element.role.clone()),
+            });
+        }
+        if let Some(element) = image.structure.as_ref().and_then(|element| element.object) {
+            linked_elements.push(element);
+        }
+    }
+
+    // 画像のあるFigure要素は画像ごとに調べたので、画像と結び付かないもの
+    // （ベクターの図や、画像を取り出せなかったもの）だけを見る
+    let figures = structure.figure_elements();
+    for figure in &figures {
+        if has_description(
+            figure.element.alt.as_deref(),
+            figure.element.actual_text.as_deref(),
+        ) || figure
+            .element
+            .object
+            .is_some_and(|object| linked_elements.contains(&object))
+        {
+            continue;
+        }
+        let page_index = figure.page.and_then(|page| {
+            let id = ObjectIdentifier::from(page);
+            pages
+                .iter()
+                .position(|candidate| candidate.raw().obj_id() == Some(id))
+        });
+        let selected = match page_index {
+            Some(page_index) => selected_pages[page_index],
+            None => page_ranges.is_empty(),
+        };
+        if !selected {
+            continue;
+        }
+        issues.push(AuditIssue {
+            kind: AuditIssueKind::MissingAltText,
+            page_index,
+            page_label: page_index.and_then(|page_index| page_labels.get(page_index).cloned()),
+            image_index: None,
+            bounds: None,
+            object: None,
+            element: figure.element.object,
+            role: Some(figure.element.role.clone()),
+        });
+    }
+    issues.sort_by_key(|issue| issue.page_index.unwrap_or(usize::MAX));
+
+    Ok(AccessibilityAudit {
+        tagged: structure.is_tagged(),
+        image_count: images.len(),
+        figure_count: figures.len(),
+        issues,
+        warnings,
+    })
+}
+
+fn image_issue(structure: Option<&StructureElement>, artifact: bool) -> Option<AuditIssueKind> {
+    if artifact {
+        return None;
+    }
+    let Some(element) = structure else {
+        return Some(AuditIssueKind::UntaggedImage);
+    };
+    match element.alt.as_deref() {
+        Some(alt) if alt.trim().is_empty() => Some(AuditIssueKind::DecorativeNotArtifact),
+        alt if !has_description(alt, element.actual_text.as_deref()) => {
+            Some(AuditIssueKind::MissingAltText)
+        }
+        _ => None,
+    }
+}
+
+fn has_description(alt: Option<&str>, actual_text: Option<&str>) -> bool {
+    [alt, actual_text]
+        .into_iter()
+        .flatten()
+        .any(|text| !text.trim().is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AuditIssueKind, audit_accessibility, image_issue};
+    use crate::tests::{single_page_pdf, stream_object};
+    use crate::{ExtractLimits, ExtractOptions, StructureElement};
+
+    fn figure(alt: Option<&str>, actual_text: Option<&str>) -> StructureElement {
+        StructureElement {
+            object: None,
+            role: "Figure".to_string(),
+            alt: alt.map(str::to_string),
+            actual_text: actual_text.map(str::to_string),
+            title: None,
+        }
+    }
+
+    #[test]
+    fn artifacts_and_described_images_pass() {
+        assert_eq!(image_issue(None, true), None);
+        assert_eq!(image_issue(Some(&figure(Some("Map"), None)), false), None);
+        assert_eq!(image_issue(Some(&figure(None, Some("42"))), false), None);
+    }
+
+    #[test]
+    fn reports_untagged_undescribed_and_decorative_images() {
+        assert_eq!(
+            image_issue(None, false),
+            Some(AuditIssueKind::UntaggedImage)
+        );
+        assert_eq!(
+            image_issue(Some(&figure(None, None)), false),
+            Some(AuditIssueKind::MissingAltText)
+        );
+        assert_eq!(
+            image_issue(Some(&figure(Some(" "), None)), false),
+            Some(AuditIssueKind::DecorativeNotArtifact)
+        );
+    }
+
+    #[test]
+    fn checks_images_that_cannot_be_decoded_or_exceed_limits() {
+        let pdf = single_page_pdf(
+            "",
+            "<< /XObject << /Im1 5 0 R /Im2 6 0 R >> >>",
+            "q 10 0 0 10 0 0 cm /Im1 Do Q q 10 0 0 10 20 0 cm /Im2 Do Q",
+            &[
+                stream_object(
+                    "/Type /XObject /Subtype /Image /Width 1 /Height 1 /ColorSpace /DeviceRGB \
+                     /BitsPerComponent 8 /Filter /DCTDecode",
+                    b"not a jpeg",
+                ),
+                stream_object(
+                    "/Type /XObject /Subtype /Image /Width 100 /Height 100 \
+                     /ColorSpace /DeviceRGB /BitsPerComponent 8",
+                    &[0; 30000],
+                ),
+            ],
+        );
+        let options = ExtractOptions {
+            limits: ExtractLimits {
+                max_image_pixels: Some(10),
+                ..ExtractLimits::default()
+            },
+            ..ExtractOptions::default()
+        };
+        let audit = audit_accessibility(&pdf, &[], &options).unwrap();
+
+        assert_eq!(audit.image_count, 2);
+        let issues: Vec<_> = audit
+            .issues
+            .iter()
+            .map(|issue| (issue.kind, issue.image_index))
+            .collect();
+        assert_eq!(
+            issues,
+            [
+                (AuditIssueKind::UntaggedImage, Some(0)),
+                (AuditIssueKind::UntaggedImage, Some(1))
+            ]
+        );
+        assert!(audit.warnings.is_empty());
+    }
+}
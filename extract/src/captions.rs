@@ -0,0 +1,290 @@
+use kurbo::Affine;
+
+use crate::ImageBounds;
+
+// グリフ座標の1emあたりの単位（hayroはグリフを1000単位/emで渡す）
+const UNITS_PER_EM: f64 = 1000.0;
+// キャプションの続きとして読む行数の上限
+const MAX_CAPTION_LINES: usize = 5;
+const CAPTION_PREFIXES: [&str; 3] = ["figure", "fig.", "図"];
+
+// 画像のすぐ上と下にある1行ずつのテキスト
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NearbyText {
+    pub above: Option<String>,
+    pub below: Option<String>,
+}
+
+// ページ上に描かれた1文字（ページ座標）
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct PlacedGlyph {
+    pub(crate) ch: char,
+    // インクの左右端（空白などの形のないグリフは原点の位置）
+    pub(crate) x0: f32,
+    pub(crate) x1: f32,
+    pub(crate) baseline: f32,
+    // フォントサイズ（ページ座標での1emの高さ）
+    pub(crate) size: f32,
+}
+
+impl PlacedGlyph {
+    // 回転・反転したテキストは行にまとめられないので扱わない。
+    // ink_extentはグリフ座標でのインクの左右端（分からなければNone）
+    pub(crate) fn new(ch: char, transform: Affine, ink_extent: Option<(f64, f64)>) -> Option<Self> {
+        let [a, b, c, d, e, f] = transform.as_coeffs();
+        if a <= 0.0 || d <= 0.0 || b.abs() > a * 0.01 || c.abs() > d * 0.01 {
+            return None;
+        }
+        let size = d * UNITS_PER_EM;
+        let (x0, x1) = match ink_extent {
+            Some((x0, x1)) => (e + a * x0, e + a * x1),
+            // Type3グリフは形を解釈しないので幅を半角とみなす
+            None => (e, e + size * 0.5),
+        };
+        Some(Self {
+            ch,
+            x0: x0 as f32,
+            x1: x1 as f32,
+            baseline: f as f32,
+            size: size as f32,
+        })
+    }
+}
+
+// 描かれた順につないだ1行のテキスト
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct TextLine {
+    pub(crate) text: String,
+    pub(crate) x0: f32,
+    pub(crate) x1: f32,
+    // 行の下端と上端（ベースラインからディセンダとアセンダを見込む）
+    pub(crate) bottom: f32,
+    pub(crate) top: f32,
+    pub(crate) size: f32,
+}
+
+// 内容ストリームでの描画順に、ベースラインがそろって右へ続くグリフを1行にまとめる
+pub(crate) fn text_lines(glyphs: &[PlacedGlyph]) -> Vec<TextLine> {
+    let mut lines = Vec::new();
+    let mut current: Option<(TextLine, PlacedGlyph)> = None;
+    for glyph in glyphs {
+        if let Some((line, last)) = &mut current {
+            let size = last.size.min(glyph.size);
+            let same_line = (glyph.baseline - last.baseline).abs() <= size * 0.5
+                && glyph.x0 >= last.x1 - size
+                && glyph.x0 - last.x1 <= size * 3.0;
+            if same_line {
+                if glyph.x0 - last.x1 > size * 0.25
+                    && !last.ch.is_whitespace()
+                    && !glyph.ch.is_whitespace()
+                {
+                    line.text.push(' ');
+                }
+                line.text.push(glyph.ch);
+                line.x1 = line.x1.max(glyph.x1);
+                line.bottom = line.bottom.min(glyph.baseline - glyph.size * 0.25);
+                line.top = line.top.max(glyph.baseline + glyph.size * 0.75);
+                line.size = line.size.max(glyph.size);
+                *last = *glyph;
+                continue;
+            }
+        }
+        if let Some((line, _)) = current.take() {
+            push_line(&mut lines, line);
+        }
+        current = Some((
+            TextLine {
+                text: glyph.ch.to_string(),
+                x0: glyph.x0,
+                x1: glyph.x1,
+                bottom: glyph.baseline - glyph.size * 0.25,
+                top: glyph.baseline + glyph.size * 0.75,
+                size: glyph.size,
+            },
+            *glyph,
+        ));
+    }
+    if let Some((line, _)) = current {
+        push_line(&mut lines, line);
+    }
+    lines
+}
+
+fn push_line(lines: &mut Vec<TextLine>, mut line: TextLine) {
+    let text = line.text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if text.is_empty() {
+        return;
+    }
+    line.text = text;
+    lines.push(line);
+}
+
+// 画像の上下distance以内で、画像と横方向に重なる行から
+// すぐ上・すぐ下の行と、"Figure"・"Fig."・"図"で始まるキャプションを探す
+pub(crate) fn find_nearby_text(
+    lines: &[TextLine],
+    bounds: &ImageBounds,
+    distance: f32,
+) -> (NearbyText, Option<String>) {
+    let overlapping = lines
+        .iter()
+        .filter(|line| line.x1 > bounds.x0 && line.x0 < bounds.x1);
+    // 画像の縁に少し食い込む行も上下の行とみなす
+    let mut below: Vec<&TextLine> = overlapping
+        .clone()
+        .filter(|line| {
+            let gap = bounds.y0 - line.top;
+            gap >= -line.size * 0.25 && gap <= distance
+        })
+        .collect();
+    below.sort_by(|a, b| b.top.total_cmp(&a.top));
+    let mut above: Vec<&TextLine> = overlapping
+        .filter(|line| {
+            let gap = line.bottom - bounds.y1;
+            gap >= -line.size * 0.25 && gap <= distance
+        })
+        .collect();
+    above.sort_by(|a, b| a.bottom.total_cmp(&b.bottom));
+
+    let nearby = NearbyText {
+        above: above.first().map(|line| line.text.clone()),
+        below: below.first().map(|line| line.text.clone()),
+    };
+    let caption = below
+        .iter()
+        .chain(&above)
+        .find(|line| is_caption(&line.text))
+        .map(|line| caption_text(lines, bounds, line));
+    (nearby, caption)
+}
+
+fn is_caption(text: &str) -> bool {
+    let lower = text.to_lowercase();
+    CAPTION_PREFIXES.iter().any(|prefix| {
+        lower.strip_prefix(prefix).is_some_and(|rest| {
+            !rest
+                .chars()
+                .next()
+                .is_some_and(|next| next.is_ascii_alphabetic())
+        })
+    })
+}
+
+// 行間の狭い次の行はキャプションの続きとしてつなげる
+fn caption_text(lines: &[TextLine], bounds: &ImageBounds, first: &TextLine) -> String {
+    let mut text = first.text.clone();
+    let mut previous = first;
+    // 画像の上のキャプションは画像の中の文字まで続けない
+    let above_image = first.bottom >= bounds.y1 - first.size * 0.25;
+    for _ in 1..MAX_CAPTION_LINES {
+        let next = lines
+            .iter()
+            .filter(|line| {
+                let gap = previous.bottom - line.top;
+                line.x1 > bounds.x0
+                    && line.x0 < bounds.x1
+                    && gap >= -previous.size * 0.5
+                    && gap <= previous.size * 0.5
+                    && (!above_image || line.bottom >= bounds.y1 - line.size * 0.25)
+            })
+            .max_by(|a, b| a.top.total_cmp(&b.top));
+        let Some(next) = next else {
+            break;
+        };
+        // 日本語などの行末と行頭は空白を挟まずにつなげる
+        if !(text.chars().last().is_some_and(|c| !c.is_ascii())
+            && next.text.chars().next().is_some_and(|c| !c.is_ascii()))
+        {
+            text.push(' ');
+        }
+        text.push_str(&next.text);
+        previous = next;
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PlacedGlyph, TextLine, find_nearby_text, is_caption, text_lines};
+    use crate::ImageBounds;
+
+    fn glyphs(text: &str, x: f32, baseline: f32, size: f32) -> Vec<PlacedGlyph> {
+        text.chars()
+            .enumerate()
+            .map(|(i, ch)| {
+                let x0 = x + i as f32 * size * 0.5;
+                PlacedGlyph {
+                    ch,
+                    x0,
+                    x1: if ch == ' ' { x0 } else { x0 + size * 0.45 },
+                    baseline,
+                    size,
+                }
+            })
+            .collect()
+    }
+
+    fn line(text: &str, baseline: f32) -> TextLine {
+        text_lines(&glyphs(text, 100.0, baseline, 10.0)).remove(0)
+    }
+
+    #[test]
+    fn groups_glyphs_into_lines() {
+        let mut placed = glyphs("Fig. 1", 100.0, 500.0, 10.0);
+        // 空白グリフのない単語の区切り
+        placed.extend(glyphs("Map", 140.0, 500.0, 10.0));
+        placed.extend(glyphs("Next line", 100.0, 488.0, 10.0));
+        let lines = text_lines(&placed);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].text, "Fig. 1 Map");
+        assert_eq!(lines[1].text, "Next line");
+        assert!(lines[1].top < lines[0].bottom + 5.0);
+    }
+
+    #[test]
+    fn recognizes_caption_prefixes() {
+        assert!(is_caption("Figure 3: Sales"));
+        assert!(is_caption("FIG. 2"));
+        assert!(is_caption("図1 売上"));
+        assert!(!is_caption("Figures are shown below"));
+        assert!(!is_caption("Configuration"));
+    }
+
+    #[test]
+    fn finds_text_above_and_below_and_multiline_captions() {
+        let bounds = ImageBounds {
+            x0: 100.0,
+            y0: 300.0,
+            x1: 300.0,
+            y1: 500.0,
+        };
+        let lines = vec![
+            line("Quarterly report", 510.0),
+            line("Source: survey", 290.0),
+            line("Figure 2: Sales by", 276.0),
+            line("region", 264.0),
+            line("Body text far away", 150.0),
+        ];
+        let (nearby, caption) = find_nearby_text(&lines, &bounds, 36.0);
+        assert_eq!(nearby.above.as_deref(), Some("Quarterly report"));
+        assert_eq!(nearby.below.as_deref(), Some("Source: survey"));
+        assert_eq!(caption.as_deref(), Some("Figure 2: Sales by region"));
+
+        let (nearby, caption) = find_nearby_text(&lines, &bounds, 5.0);
+        assert_eq!(nearby.below.as_deref(), Some("Source: survey"));
+        assert_eq!(caption, None);
+    }
+
+    #[test]
+    fn ignores_text_beside_the_image() {
+        let bounds = ImageBounds {
+            x0: 400.0,
+            y0: 300.0,
+            x1: 500.0,
+            y1: 500.0,
+        };
+        let (nearby, caption) = find_nearby_text(&[line("Figure 1", 290.0)], &bounds, 36.0);
+        assert_eq!(nearby, Default::default());
+        assert_eq!(caption, None);
+    }
+}
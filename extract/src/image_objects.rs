@@ -0,0 +1,500 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::iter;
+
+use hayro_interpret::font::Glyph;
+use hayro_interpret::{
+    BlendMode, CacheKey, ClipPath, Context, Device, GlyphDrawMode, Image, InterpreterSettings,
+    Paint, PathDrawMode, SoftMask, interpret,
+};
+use hayro_syntax::content::ops::{InlineImage, TypedInstruction};
+use hayro_syntax::object::dict::keys::{
+    BITS_PER_COMPONENT, CHAR_PROCS, COLORSPACE, FILTER, FORM, HEIGHT, IMAGE, IMAGE_MASK, RESOURCES,
+    SMASK, SUBTYPE, TYPE3, WIDTH,
+};
+use hayro_syntax::object::stream::{DecodeFailure, ImageDecodeParams};
+use hayro_syntax::object::{Array, Dict, Name, ObjRef, Object, ObjectIdentifier, Stream};
+use hayro_syntax::page::Resources;
+use hayro_syntax::xref::XRef;
+use image::DynamicImage;
+use kurbo::{Affine, BezPath, Rect};
+
+use crate::{ExtractImageWarningKind, catch_quietly, panic_message, raster_to_dynamic_image};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ObjectRef {
+    pub number: i32,
+    pub generation: i32,
+}
+
+impl fmt::Display for ObjectRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} R", self.number, self.generation)
+    }
+}
+
+impl From<ObjRef> for ObjectRef {
+    fn from(obj_ref: ObjRef) -> Self {
+        ObjectRef {
+            number: obj_ref.obj_number,
+            generation: obj_ref.gen_number,
+        }
+    }
+}
+
+// hayro-syntaxはObjectIdentifierの番号を公開していないので、
+// 番号・世代の順に並ぶ順序を使って二分探索で求める
+pub(crate) fn object_ref_of(id: ObjectIdentifier) -> ObjectRef {
+    let number = first_not_below(|number| ObjectIdentifier::new(number, i32::MAX) < id);
+    let generation = first_not_below(|generation| ObjectIdentifier::new(number, generation) < id);
+    ObjectRef { number, generation }
+}
+
+// belowがtrueからfalseに変わる最初の値
+fn first_not_below(below: impl Fn(i32) -> bool) -> i32 {
+    let (mut low, mut high) = (i64::from(i32::MIN), i64::from(i32::MAX));
+    while low < high {
+        let middle = low + (high - low) / 2;
+        if below(middle as i32) {
+            low = middle + 1;
+        } else {
+            high = middle;
+        }
+    }
+    low as i32
+}
+
+#[derive(Clone)]
+pub(crate) struct ImageObject<'a> {
+    pub(crate) object_ref: Option<ObjectRef>,
+    pub(crate) stream: Stream<'a>,
+}
+
+// Deviceに渡される画像からは元のストリームを辿れないため、
+// hayroのキャッシュキー（辞書のハッシュ）で画像XObjectを引けるようにしておく。
+// 辞書が同じでも別のオブジェクトは別々に持つ
+#[derive(Default)]
+pub(crate) struct ImageObjectIndex<'a> {
+    images: Vec<ImageObject<'a>>,
+    objects: HashSet<(Option<ObjectRef>, u128)>,
+    by_cache_key: HashMap<u128, Vec<usize>>,
+    visited: HashSet<u128>,
+}
+
+impl<'a> ImageObjectIndex<'a> {
+    // 辞書が同じ画像XObjectが複数あると、どれが描かれたかは分からないので参照を返さない
+    pub(crate) fn get(&self, cache_key: u128) -> Option<ImageObject<'a>> {
+        let candidates = self.by_cache_key.get(&cache_key)?;
+        let image = &self.images[candidates[0]];
+        Some(ImageObject {
+            object_ref: image.object_ref.filter(|_| candidates.len() == 1),
+            stream: image.stream.clone(),
+        })
+    }
+
+    pub(crate) fn images(&self) -> impl Iterator<Item = &ImageObject<'a>> {
+        self.images.iter()
+    }
+
+    pub(crate) fn add_resources(&mut self, resources: &Resources<'a>) {
+        let mut current = Some(resources);
+        while let Some(resources) = current {
+            self.add_x_objects(resources);
+            current = resources.parent();
+        }
+    }
+
+    fn add_x_objects(&mut self, resources: &Resources<'a>) {
+        self.add_patterns(resources);
+        self.add_type3_fonts(resources);
+
+        let x_objects = &resources.x_objects;
+        if !self.visited.insert(x_objects.cache_key()) {
+            return;
+        }
+
+        for name in x_objects.keys() {
+            let Some(stream) = x_objects.get::<Stream<'a>>(name.clone()) else {
+                continue;
+            };
+            let object_ref = x_objects.get_ref(name).map(ObjectRef::from);
+
+            match stream.dict().get::<Name<'_>>(SUBTYPE).as_deref() {
+                Some(IMAGE) => self.insert(ImageObject { object_ref, stream }),
+                Some(FORM) => {
+                    let form_resources = Resources::from_parent(
+                        stream.dict().get::<Dict<'_>>(RESOURCES).unwrap_or_default(),
+                        resources.clone(),
+                    );
+                    self.add_x_objects(&form_resources);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn insert(&mut self, image: ImageObject<'a>) {
+        let cache_key = image.stream.cache_key();
+        if !self.objects.insert((image.object_ref, cache_key)) {
+            return;
+        }
+        self.by_cache_key
+            .entry(cache_key)
+            .or_default()
+            .push(self.images.len());
+        self.images.push(image);
+    }
+
+    // タイリングパターンの中で使われる画像も辿る
+    fn add_patterns(&mut self, resources: &Resources<'a>) {
+        let patterns = &resources.patterns;
+        if !self.visited.insert(patterns.cache_key()) {
+            return;
+        }
+
+        for name in patterns.keys() {
+            // シェーディングパターンは辞書、タイリングパターンはストリーム
+            let Some(stream) = patterns.get::<Stream<'a>>(name) else {
+                continue;
+            };
+            let pattern_resources = Resources::from_parent(
+                stream.dict().get::<Dict<'_>>(RESOURCES).unwrap_or_default(),
+                resources.clone(),
+            );
+            self.add_x_objects(&pattern_resources);
+        }
+    }
+
+    // Type3フォントのグリフ手続きの中で使われる画像も辿る
+    fn add_type3_fonts(&mut self, resources: &Resources<'a>) {
+        let fonts = &resources.fonts;
+        if !self.visited.insert(fonts.cache_key()) {
+            return;
+        }
+
+        for name in fonts.keys() {
+            let Some(font) = fonts.get::<Dict<'a>>(name) else {
+                continue;
+            };
+            if font.get::<Name<'_>>(SUBTYPE).as_deref() != Some(TYPE3) {
+                continue;
+            }
+            let font_resources = Resources::from_parent(
+                font.get::<Dict<'_>>(RESOURCES).unwrap_or_default(),
+                resources.clone(),
+            );
+            self.add_x_objects(&font_resources);
+
+            let char_procs = font.get::<Dict<'a>>(CHAR_PROCS).unwrap_or_default();
+            for glyph in char_procs.keys() {
+                let Some(procedure) = char_procs.get::<Stream<'a>>(glyph) else {
+                    continue;
+                };
+                if let Some(procedure_resources) = procedure.dict().get::<Dict<'_>>(RESOURCES) {
+                    let procedure_resources =
+                        Resources::from_parent(procedure_resources, font_resources.clone());
+                    self.add_x_objects(&procedure_resources);
+                }
+            }
+        }
+    }
+}
+
+// hayroは未知のフィルタを黙って無視するので、デコード前に弾く
+pub(crate) fn check_supported(image: &ImageObject<'_>) -> Result<(), ExtractImageWarningKind> {
+    let dict = image.stream.dict();
+
+    if let Some(filter) = filter_names(dict)
+        .into_iter()
+        .find(|filter| !is_supported_filter(filter))
+    {
+        return Err(ExtractImageWarningKind::UnsupportedFilter {
+            object: image.object_ref,
+            filter,
+        });
+    }
+
+    if dict.get::<bool>(IMAGE_MASK).unwrap_or(false) {
+        return Ok(());
+    }
+    if let Some(color_space) = color_space_family(dict)
+        && !is_supported_color_space(&color_space)
+    {
+        return Err(ExtractImageWarningKind::UnsupportedColorSpace {
+            object: image.object_ref,
+            color_space,
+        });
+    }
+
+    Ok(())
+}
+
+// /SMaskの幅と高さ。hayroは画像本体と一緒にデコードするので、デコード前の上限の確認に使う
+pub(crate) fn soft_mask_size(image: &ImageObject<'_>) -> Option<(u32, u32)> {
+    let mask = image.stream.dict().get::<Stream<'_>>(SMASK)?;
+    let dict = mask.dict();
+    Some((
+        dict.get::<u32>(WIDTH).unwrap_or(0),
+        dict.get::<u32>(HEIGHT).unwrap_or(0),
+    ))
+}
+
+// hayroはデコード失敗の理由を返さないので、フィルタだけを掛け直して原因を推定する
+pub(crate) fn diagnose_decode_failure(image: &ImageObject<'_>) -> ExtractImageWarningKind {
+    let dict = image.stream.dict();
+    let params = ImageDecodeParams {
+        bpc: dict.get::<u8>(BITS_PER_COMPONENT),
+        width: dict.get::<u32>(WIDTH).unwrap_or(0),
+        height: dict.get::<u32>(HEIGHT).unwrap_or(0),
+        ..Default::default()
+    };
+    let filters = filter_names(dict);
+
+    match image.stream.decoded_image(&params) {
+        Err(DecodeFailure::ImageDecode) => ExtractImageWarningKind::ImageDecodeFailed {
+            object: image.object_ref,
+            filter: filters.into_iter().rfind(|filter| is_image_filter(filter)),
+        },
+        // 壊れたFlateなどは失敗せずに空のデータを返すことがある
+        Err(_) => ExtractImageWarningKind::StreamDecodeFailed {
+            object: image.object_ref,
+            filter: filters.into_iter().find(|filter| !is_image_filter(filter)),
+        },
+        Ok(result) if result.data.is_empty() && result.image_data.is_none() => {
+            ExtractImageWarningKind::StreamDecodeFailed {
+                object: image.object_ref,
+                filter: filters.into_iter().find(|filter| !is_image_filter(filter)),
+            }
+        }
+        Ok(_) => ExtractImageWarningKind::ImageDecodeFailed {
+            object: image.object_ref,
+            filter: None,
+        },
+    }
+}
+
+// ページに描かれない画像ストリーム（/Thumbや使われていない画像XObject）をデコードする。
+// hayroは画像のデコードを公開していないので、インライン画像として描かせる
+// （インライン画像も正式なキー名を受け付ける）
+pub(crate) fn decode_image_object(
+    image: &ImageObject<'_>,
+    resources: &Resources<'_>,
+    xref: &XRef,
+) -> Result<DynamicImage, ExtractImageWarningKind> {
+    let decoded = catch_quietly(|| {
+        let mut decoder = StandaloneImageDecoder { decoded: None };
+        let mut context = Context::new(
+            Affine::IDENTITY,
+            Rect::new(0.0, 0.0, 1.0, 1.0),
+            xref,
+            InterpreterSettings::default(),
+        );
+        let ops = iter::once(TypedInstruction::InlineImage(InlineImage(
+            image.stream.clone(),
+        )));
+        interpret(ops, resources, &mut context, &mut decoder);
+        decoder.decoded
+    });
+    match decoded {
+        Ok(Some(decoded)) => decoded,
+        Ok(None) => Err(diagnose_decode_failure(image)),
+        Err(payload) => Err(ExtractImageWarningKind::ImageDecodePanicked {
+            message: panic_message(payload.as_ref()),
+        }),
+    }
+}
+
+struct StandaloneImageDecoder {
+    decoded: Option<Result<DynamicImage, ExtractImageWarningKind>>,
+}
+
+impl<'a> Device<'a> for StandaloneImageDecoder {
+    fn set_soft_mask(&mut self, _mask: Option<SoftMask<'_>>) {}
+
+    fn set_blend_mode(&mut self, _blend_mode: BlendMode) {}
+
+    fn draw_path(
+        &mut self,
+        _path: &BezPath,
+        _transform: Affine,
+        _paint: &Paint<'a>,
+        _draw_mode: &PathDrawMode,
+    ) {
+    }
+
+    fn push_clip_path(&mut self, _clip_path: &ClipPath) {}
+
+    fn push_transparency_group(
+        &mut self,
+        _opacity: f32,
+        _mask: Option<SoftMask<'_>>,
+        _blend_mode: BlendMode,
+    ) {
+    }
+
+    fn draw_glyph(
+        &mut self,
+        _glyph: &Glyph<'a>,
+        _transform: Affine,
+        _glyph_transform: Affine,
+        _paint: &Paint<'a>,
+        _draw_mode: &GlyphDrawMode,
+    ) {
+    }
+
+    fn draw_image(&mut self, image: Image<'a, '_>, _transform: Affine) {
+        if let Image::Raster(raster) = image {
+            raster.with_rgba(
+                |rgb, alpha| self.decoded = Some(raster_to_dynamic_image(&rgb, alpha.as_ref())),
+                None,
+            );
+        }
+    }
+
+    fn pop_clip_path(&mut self) {}
+
+    fn pop_transparency_group(&mut self) {}
+}
+
+fn filter_names(dict: &Dict<'_>) -> Vec<String> {
+    if let Some(name) = dict.get::<Name<'_>>(FILTER) {
+        vec![name.as_str().to_string()]
+    } else if let Some(names) = dict.get::<Array<'_>>(FILTER) {
+        names
+            .iter::<Name<'_>>()
+            .map(|name| name.as_str().to_string())
+            .collect()
+    } else {
+        vec![]
+    }
+}
+
+fn color_space_family(dict: &Dict<'_>) -> Option<String> {
+    match dict.get::<Object<'_>>(COLORSPACE)? {
+        Object::Name(name) => Some(name.as_str().to_string()),
+        Object::Array(array) => array
+            .iter::<Name<'_>>()
+            .next()
+            .map(|name| name.as_str().to_string()),
+        _ => None,
+    }
+}
+
+fn is_supported_filter(name: &str) -> bool {
+    matches!(
+        name,
+        "ASCIIHexDecode"
+            | "AHx"
+            | "ASCII85Decode"
+            | "A85"
+            | "LZWDecode"
+            | "LZW"
+            | "FlateDecode"
+            | "Fl"
+            | "RunLengthDecode"
+            | "RL"
+            | "CCITTFaxDecode"
+            | "CCF"
+            | "JBIG2Decode"
+            | "DCTDecode"
+            | "DCT"
+            | "JPXDecode"
+            | "Crypt"
+    )
+}
+
+fn is_image_filter(name: &str) -> bool {
+    matches!(
+        name,
+        "CCITTFaxDecode" | "CCF" | "JBIG2Decode" | "DCTDecode" | "DCT" | "JPXDecode"
+    )
+}
+
+fn is_supported_color_space(family: &str) -> bool {
+    matches!(
+        family,
+        "DeviceGray"
+            | "G"
+            | "DeviceRGB"
+            | "RGB"
+            | "DeviceCMYK"
+            | "CMYK"
+            | "CalGray"
+            | "CalRGB"
+            | "CalCMYK"
+            | "Lab"
+            | "ICCBased"
+            | "Indexed"
+            | "I"
+            | "Separation"
+            | "DeviceN"
+    )
+}
+
+pub(crate) fn describe_object(object: &Option<ObjectRef>) -> String {
+    match object {
+        Some(object_ref) => format!("image object {object_ref}"),
+        None => "inline image".to_string(),
+    }
+}
+
+pub(crate) fn describe_filter(filter: &Option<String>) -> String {
+    match filter {
+        Some(filter) => format!("/{filter}"),
+        None => "image".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        ObjectRef, describe_filter, describe_object, is_image_filter, is_supported_color_space,
+        is_supported_filter, object_ref_of,
+    };
+    use hayro_syntax::object::ObjectIdentifier;
+
+    #[test]
+    fn object_ref_displays_as_pdf_reference() {
+        let object_ref = ObjectRef {
+            number: 12,
+            generation: 0,
+        };
+        assert_eq!(object_ref.to_string(), "12 0 R");
+        assert_eq!(describe_object(&Some(object_ref)), "image object 12 0 R");
+        assert_eq!(describe_object(&None), "inline image");
+    }
+
+    #[test]
+    fn reads_object_numbers_from_identifiers() {
+        for (number, generation) in [(1, 0), (12, 3), (65_535, 65_535), (0, 0)] {
+            assert_eq!(
+                object_ref_of(ObjectIdentifier::new(number, generation)),
+                ObjectRef { number, generation }
+            );
+        }
+    }
+
+    #[test]
+    fn recognizes_filter_names_and_abbreviations() {
+        assert!(is_supported_filter("FlateDecode"));
+        assert!(is_supported_filter("DCT"));
+        assert!(!is_supported_filter("BrotliDecode"));
+        assert!(is_image_filter("JBIG2Decode"));
+        assert!(!is_image_filter("FlateDecode"));
+    }
+
+    #[test]
+    fn recognizes_color_space_families() {
+        assert!(is_supported_color_space("ICCBased"));
+        assert!(!is_supported_color_space("DeviceXYZ"));
+    }
+
+    #[test]
+    fn describes_missing_filter_as_image() {
+        assert_eq!(
+            describe_filter(&Some("FlateDecode".to_string())),
+            "/FlateDecode"
+        );
+        assert_eq!(describe_filter(&None), "image");
+    }
+}
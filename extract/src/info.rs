@@ -0,0 +1,431 @@
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::rc::Rc;
+
+use hayro_interpret::font::Glyph;
+use hayro_interpret::pattern::Pattern;
+use hayro_interpret::{
+    BlendMode, CacheKey, ClipPath, Context, Device, GlyphDrawMode, Image, Paint, PathDrawMode,
+    RectExt, SoftMask, interpret,
+};
+use hayro_syntax::PdfVersion;
+use hayro_syntax::content::TypedIter;
+use hayro_syntax::object::Dict;
+use hayro_syntax::object::dict::keys::{OC, RESOURCES};
+use hayro_syntax::page::{Page, Resources};
+use hayro_syntax::xref::XRef;
+use kurbo::{Affine, BezPath, Rect};
+
+use crate::annotations::annotation_appearances;
+use crate::image_objects::ImageObjectIndex;
+use crate::layers::{MarkedContentTracker, OptionalContent, read_layers};
+use crate::orientation::page_rotation_degrees;
+use crate::page_labels::read_page_labels;
+use crate::text::decode_text_string;
+use crate::{ExtractError, Layer, LayerVisibility, catch_quietly, open_pdf, page_only_settings};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DocumentInfo {
+    // ヘッダ（カタログの/Versionがあればそちら）の版。例: "1.7"
+    pub version: String,
+    // トレーラに/Encryptがあるか（空のユーザパスワードで開けた文書も含む）
+    pub encrypted: bool,
+    // 文書情報辞書の/Producerと/Creator
+    pub producer: Option<String>,
+    pub creator: Option<String>,
+    pub pages: Vec<PageInfo>,
+    // /OCProperties /OCGsの順
+    pub layers: Vec<Layer>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PageInfo {
+    // /PageLabelsによるページラベル（なければ1始まりのページ番号）
+    pub label: String,
+    // クロップボックスの大きさ（ポイント、/Rotate適用前）
+    pub width: f32,
+    pub height: f32,
+    // /Rotate（時計回りの度数）
+    pub rotation: u16,
+    // 既定の設定で取り出される、ページの内容ストリームと注釈の外観の画像の数（解釈に失敗したらNone）
+    pub image_count: Option<usize>,
+}
+
+pub fn document_info(pdf_bytes: &[u8]) -> Result<DocumentInfo, ExtractError> {
+    let pdf = open_pdf(pdf_bytes)?;
+    let metadata = pdf.metadata();
+    let mut counter = ImageCounter::new(pdf.xref());
+    let labels = read_page_labels(pdf.xref(), pdf.pages().len());
+    let pages = pdf
+        .pages()
+        .iter()
+        .zip(labels)
+        .map(|(page, label)| {
+            let crop_box = page.intersected_crop_box();
+            PageInfo {
+                label,
+                width: (crop_box.x1 - crop_box.x0) as f32,
+                height: (crop_box.y1 - crop_box.y0) as f32,
+                rotation: page_rotation_degrees(page.rotation()),
+                image_count: counter.count_page_images(page),
+            }
+        })
+        .collect();
+
+    Ok(DocumentInfo {
+        version: version_string(pdf.version()).to_string(),
+        encrypted: has_encrypt_entry(pdf_bytes),
+        producer: metadata.producer.as_deref().map(decode_text_string),
+        creator: metadata.creator.as_deref().map(decode_text_string),
+        pages,
+        layers: read_layers(pdf.xref()),
+    })
+}
+
+fn version_string(version: PdfVersion) -> &'static str {
+    match version {
+        PdfVersion::Pdf10 => "1.0",
+        PdfVersion::Pdf11 => "1.1",
+        PdfVersion::Pdf12 => "1.2",
+        PdfVersion::Pdf13 => "1.3",
+        PdfVersion::Pdf14 => "1.4",
+        PdfVersion::Pdf15 => "1.5",
+        PdfVersion::Pdf16 => "1.6",
+        PdfVersion::Pdf17 => "1.7",
+        PdfVersion::Pdf20 => "2.0",
+    }
+}
+
+// hayro-syntaxはトレーラを公開していないので、トレーラと相互参照ストリームの辞書に
+// /Encryptという名前があるかをバイト列から探す（/EncryptMetadataのような長い名前は除く）
+fn has_encrypt_entry(pdf_bytes: &[u8]) -> bool {
+    let trailers =
+        keyword_positions(pdf_bytes, b"trailer").filter_map(|end| dictionary_after(pdf_bytes, end));
+    let xref_streams = keyword_positions(pdf_bytes, b"obj")
+        .filter_map(|end| dictionary_after(pdf_bytes, end))
+        .filter(|dict| contains_name(dict, b"/XRef"));
+    trailers
+        .chain(xref_streams)
+        .any(|dict| contains_name(dict, b"/Encrypt"))
+}
+
+// 前後が名前や数字に続いていないキーワードの、直後の位置
+fn keyword_positions<'b>(bytes: &'b [u8], keyword: &'b [u8]) -> impl Iterator<Item = usize> + 'b {
+    bytes
+        .windows(keyword.len())
+        .enumerate()
+        .filter(move |(start, window)| {
+            *window == keyword
+                && (*start == 0 || !is_regular_name_byte(bytes[start - 1]))
+                && bytes
+                    .get(start + keyword.len())
+                    .is_none_or(|&byte| !is_regular_name_byte(byte))
+        })
+        .map(move |(start, _)| start + keyword.len())
+}
+
+// トレーラや相互参照ストリームの辞書はこれより大きくならない
+const MAX_DICTIONARY_BYTES: usize = 64 * 1024;
+
+// 空白を挟んで続く辞書（<< ... >>）を、入れ子と文字列を考えて切り出す
+fn dictionary_after(bytes: &[u8], position: usize) -> Option<&[u8]> {
+    let start = position
+        + bytes[position..]
+            .iter()
+            .position(|byte| !byte.is_ascii_whitespace())?;
+    if !bytes[start..].starts_with(b"<<") {
+        return None;
+    }
+    let end = bytes.len().min(start + MAX_DICTIONARY_BYTES);
+    let mut depth = 0usize;
+    let mut i = start;
+    while i < end {
+        match bytes[i] {
+            b'<' if bytes.get(i + 1) == Some(&b'<') => {
+                depth += 1;
+                i += 2;
+            }
+            b'>' if bytes.get(i + 1) == Some(&b'>') => {
+                depth -= 1;
+                i += 2;
+                if depth == 0 {
+                    return Some(&bytes[start..i]);
+                }
+            }
+            b'<' => i += bytes[i..end].iter().position(|&byte| byte == b'>')? + 1,
+            b'(' => i = string_end(bytes, i, end)?,
+            _ => i += 1,
+        }
+    }
+    None
+}
+
+// 括弧の対応とエスケープを考えて、リテラル文字列の直後の位置を返す
+fn string_end(bytes: &[u8], start: usize, end: usize) -> Option<usize> {
+    let mut depth = 0usize;
+    let mut i = start;
+    while i < end {
+        match bytes[i] {
+            b'\\' => i += 1,
+            b'(' => depth += 1,
+            b')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i + 1);
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+fn contains_name(bytes: &[u8], name: &[u8]) -> bool {
+    bytes
+        .windows(name.len() + 1)
+        .any(|window| window.starts_with(name) && !is_regular_name_byte(window[name.len()]))
+}
+
+fn is_regular_name_byte(byte: u8) -> bool {
+    !byte.is_ascii_whitespace()
+        && !matches!(
+            byte,
+            b'(' | b')' | b'<' | b'>' | b'[' | b']' | b'{' | b'}' | b'/' | b'%' | b'\0'
+        )
+}
+
+// 抽出の既定（注釈の外観を含む、既定のレイヤ表示）と同じ画像を、デコードせずに数える
+struct ImageCounter<'a> {
+    xref: &'a XRef,
+    images: usize,
+    // 抽出と同じく、タイリングパターンの内容は文書全体で1回だけ解釈する
+    pattern_depth: usize,
+    visited_patterns: HashSet<u128>,
+    image_objects: ImageObjectIndex<'a>,
+    optional_content: Rc<RefCell<OptionalContent<'a>>>,
+}
+
+impl<'a> ImageCounter<'a> {
+    fn new(xref: &'a XRef) -> Self {
+        ImageCounter {
+            xref,
+            images: 0,
+            pattern_depth: 0,
+            visited_patterns: HashSet::new(),
+            image_objects: ImageObjectIndex::default(),
+            optional_content: Rc::new(RefCell::new(OptionalContent::new(
+                xref,
+                LayerVisibility::Default,
+            ))),
+        }
+    }
+
+    fn count_page_images(&mut self, page: &Page<'a>) -> Option<usize> {
+        self.images = 0;
+        self.pattern_depth = 0;
+        self.image_objects.add_resources(page.resources());
+        self.optional_content.borrow_mut().reset();
+        let crop_box = page.intersected_crop_box().to_kurbo();
+        let mut context = Context::new(Affine::IDENTITY, crop_box, self.xref, page_only_settings());
+        catch_quietly(|| {
+            let ops = MarkedContentTracker::new(
+                page.typed_operations(),
+                page.resources().clone(),
+                Rc::clone(&self.optional_content),
+            );
+            interpret(ops, page.resources(), &mut context, self);
+            self.count_annotation_images(page, crop_box);
+        })
+        .ok()?;
+        Some(self.images)
+    }
+
+    // 抽出のinterpret_annotationsと同じく、注釈の外観をページの後に解釈する
+    fn count_annotation_images(&mut self, page: &Page<'a>, crop_box: Rect) {
+        for appearance in annotation_appearances(page) {
+            let Ok(content) = appearance.stream.decoded() else {
+                continue;
+            };
+            let resources = Resources::from_parent(
+                appearance
+                    .stream
+                    .dict()
+                    .get::<Dict<'_>>(RESOURCES)
+                    .unwrap_or_default(),
+                page.resources().clone(),
+            );
+            self.image_objects.add_resources(&resources);
+            let membership = appearance
+                .optional_content
+                .and_then(|obj_ref| self.optional_content.borrow().membership(obj_ref));
+            self.optional_content
+                .borrow_mut()
+                .begin(membership, None, false);
+            let mut context = Context::new(
+                appearance.transform,
+                crop_box,
+                self.xref,
+                page_only_settings(),
+            );
+            let ops = MarkedContentTracker::new(
+                TypedIter::new(&content),
+                resources.clone(),
+                Rc::clone(&self.optional_content),
+            );
+            interpret(ops, &resources, &mut context, self);
+            self.optional_content.borrow_mut().reset();
+        }
+    }
+
+    // 抽出のextract_pattern_imagesと同じく、パターンの中の画像を数える
+    fn count_pattern_images(&mut self, paint: &Paint<'a>, is_stroke: bool) {
+        let Paint::Pattern(pattern) = paint else {
+            return;
+        };
+        let Pattern::Tiling(tiling) = pattern.as_ref() else {
+            return;
+        };
+        if !self.optional_content.borrow().current(None).0
+            || !self.visited_patterns.insert(tiling.cache_key())
+        {
+            return;
+        }
+        self.pattern_depth += 1;
+        tiling.interpret(self, tiling.matrix, is_stroke);
+        self.pattern_depth -= 1;
+    }
+}
+
+impl<'a> Device<'a> for ImageCounter<'a> {
+    fn set_soft_mask(&mut self, _mask: Option<SoftMask<'_>>) {}
+
+    fn set_blend_mode(&mut self, _blend_mode: BlendMode) {}
+
+    fn draw_path(
+        &mut self,
+        _path: &BezPath,
+        _transform: Affine,
+        paint: &Paint<'a>,
+        draw_mode: &PathDrawMode,
+    ) {
+        self.count_pattern_images(paint, matches!(draw_mode, PathDrawMode::Stroke(_)));
+    }
+
+    fn push_clip_path(&mut self, _clip_path: &ClipPath) {}
+
+    fn push_transparency_group(
+        &mut self,
+        _opacity: f32,
+        _mask: Option<SoftMask<'_>>,
+        _blend_mode: BlendMode,
+    ) {
+    }
+
+    fn draw_glyph(
+        &mut self,
+        _glyph: &Glyph<'a>,
+        _transform: Affine,
+        _glyph_transform: Affine,
+        paint: &Paint<'a>,
+        draw_mode: &GlyphDrawMode,
+    ) {
+        match draw_mode {
+            GlyphDrawMode::Fill => self.count_pattern_images(paint, false),
+            GlyphDrawMode::Stroke(_) => self.count_pattern_images(paint, true),
+            GlyphDrawMode::Invisible => {}
+        }
+    }
+
+    // ステンシルマスクは塗りの形なので数えない
+    fn draw_image(&mut self, image: Image<'a, '_>, _transform: Affine) {
+        if !matches!(image, Image::Raster(_)) {
+            return;
+        }
+        let painted = if self.pattern_depth == 0 {
+            self.optional_content
+                .borrow_mut()
+                .take_painted_image(image.cache_key())
+        } else {
+            None
+        };
+        let image_object = match &painted {
+            Some(painted) => painted.object.clone(),
+            None => self.image_objects.get(image.cache_key()),
+        };
+        let optional_content = self.optional_content.borrow();
+        let membership = image_object
+            .and_then(|image_object| image_object.stream.dict().get_ref(OC))
+            .and_then(|obj_ref| optional_content.membership(obj_ref));
+        let form_memberships = painted.iter().flat_map(|painted| &painted.memberships);
+        let (visible, _) = optional_content.current(form_memberships.chain(&membership));
+        if visible {
+            self.images += 1;
+        }
+    }
+
+    fn pop_clip_path(&mut self) {}
+
+    fn pop_transparency_group(&mut self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{document_info, has_encrypt_entry};
+    use crate::tests::{pixel_image, single_page_pdf, stream_object};
+    use crate::{ExtractOptions, extract_images_with_options};
+
+    #[test]
+    fn finds_encrypt_entry_in_trailer() {
+        assert!(has_encrypt_entry(
+            b"trailer\n<< /Size 9 /Root 1 0 R /Encrypt 8 0 R >>"
+        ));
+        assert!(has_encrypt_entry(
+            b"trailer<</ID[(>>)<ab>]/Encrypt<</Filter/Standard>>>>"
+        ));
+    }
+
+    #[test]
+    fn finds_encrypt_entry_in_xref_stream() {
+        assert!(has_encrypt_entry(
+            b"9 0 obj\n<< /Type /XRef /Size 9 /Root 1 0 R /Encrypt 8 0 R /Length 0 >>\nstream"
+        ));
+        assert!(!has_encrypt_entry(
+            b"9 0 obj\n<< /Type /XObject /Subtype /Form /Encrypt 8 0 R >>\nstream"
+        ));
+    }
+
+    #[test]
+    fn ignores_longer_names_and_other_objects() {
+        assert!(!has_encrypt_entry(
+            b"trailer\n<< /Size 9 /EncryptMetadata false >>"
+        ));
+        assert!(!has_encrypt_entry(b"trailer\n<< /Size 9 /Root 1 0 R >>"));
+        assert!(!has_encrypt_entry(
+            b"3 0 obj\n<< /Encrypt 8 0 R >>\nendobj\ntrailer\n<< /Size 9 >>"
+        ));
+    }
+
+    #[test]
+    fn counts_images_in_tiling_patterns() {
+        let pdf = single_page_pdf(
+            "",
+            "<< /XObject << /Im1 6 0 R >> /Pattern << /P1 5 0 R >> >>",
+            "/Pattern cs /P1 scn 0 0 50 50 re f q 10 0 0 10 60 60 cm /Im1 Do Q",
+            &[
+                stream_object(
+                    "/Type /Pattern /PatternType 1 /PaintType 1 /TilingType 1 \
+                     /BBox [0 0 10 10] /XStep 10 /YStep 10 /Resources << /XObject << /Im1 7 0 R >> >>",
+                    b"10 0 0 10 0 0 cm /Im1 Do",
+                ),
+                pixel_image([255, 0, 0]),
+                pixel_image([0, 0, 255]),
+            ],
+        );
+        let info = document_info(&pdf).unwrap();
+        let result = extract_images_with_options(&pdf, &[], &ExtractOptions::default()).unwrap();
+
+        assert_eq!(result.images.len(), 2);
+        assert_eq!(info.pages[0].image_count, Some(2));
+    }
+}
@@ -0,0 +1,644 @@
+use std::cell::RefCell;
+use std::collections::{HashSet, VecDeque};
+use std::rc::Rc;
+
+use hayro_interpret::CacheKey;
+use hayro_syntax::content::TypedIter;
+use hayro_syntax::content::ops::{
+    BeginMarkedContent, BeginMarkedContentWithProperties, TypedInstruction,
+};
+use hayro_syntax::object::dict::keys::{
+    ARTIFACT, BASE_STATE, D, FORM, IMAGE, MCID, NAME, OC, OCGS, OCMD, OCPROPERTIES, OFF, ON, P,
+    RESOURCES, STRUCT_PARENTS, SUBTYPE, TYPE,
+};
+use hayro_syntax::object::{Array, Dict, Name, ObjRef, ObjectIdentifier, Stream, dict_or_stream};
+use hayro_syntax::page::Resources;
+use hayro_syntax::xref::XRef;
+
+use crate::ObjectRef;
+use crate::image_objects::{ImageObject, object_ref_of};
+use crate::text::decode_text_string;
+
+const OCG: &[u8] = b"OCG";
+const MAX_FORM_DEPTH: usize = 32;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Layer {
+    pub object: ObjectRef,
+    pub name: String,
+    // 文書の既定の表示設定（/OCProperties /D）で表示されるか
+    pub visible_by_default: bool,
+}
+
+// どのレイヤの内容を表示されているものとして扱うか
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum LayerVisibility {
+    // 文書の既定の表示設定に従う
+    #[default]
+    Default,
+    // 非表示のレイヤも含めてすべて
+    All,
+    // 名前で指定したレイヤだけ（レイヤに属さない内容は常に表示）
+    Only(Vec<String>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum VisibilityPolicy {
+    AllOn,
+    AnyOn,
+    AnyOff,
+    AllOff,
+}
+
+// /OCが指すOCG、またはOCMDに含まれるOCG（参照と名前）と表示条件
+#[derive(Debug, Clone)]
+pub(crate) struct Membership {
+    ocgs: Vec<(ObjectRef, String)>,
+    policy: VisibilityPolicy,
+}
+
+pub(crate) fn read_layers(xref: &XRef) -> Vec<Layer> {
+    let Some(properties) = xref
+        .get::<Dict<'_>>(xref.root_id())
+        .and_then(|catalog| catalog.get::<Dict<'_>>(OCPROPERTIES))
+    else {
+        return Vec::new();
+    };
+    let all = object_refs(&properties, OCGS);
+    let hidden = hidden_by_default(&properties, &all);
+
+    all.into_iter()
+        .map(|object| Layer {
+            object,
+            name: layer_name(xref, object),
+            visible_by_default: !hidden.contains(&object),
+        })
+        .collect()
+}
+
+// hayroと同じく/BaseState、/ON、/OFFの順に適用する
+fn hidden_by_default(properties: &Dict<'_>, all: &[ObjectRef]) -> HashSet<ObjectRef> {
+    let Some(config) = properties.get::<Dict<'_>>(D) else {
+        return HashSet::new();
+    };
+    let mut hidden = HashSet::new();
+    if config.get::<Name<'_>>(BASE_STATE).as_deref() == Some(OFF) {
+        hidden.extend(all.iter().copied());
+    }
+    for object in object_refs(&config, ON) {
+        hidden.remove(&object);
+    }
+    hidden.extend(object_refs(&config, OFF));
+    hidden
+}
+
+fn object_refs(dict: &Dict<'_>, key: &[u8]) -> Vec<ObjectRef> {
+    if let Some(array) = dict.get::<Array<'_>>(key) {
+        array
+            .raw_iter()
+            .filter_map(|item| item.as_obj_ref())
+            .map(to_object_ref)
+            .collect()
+    } else {
+        dict.get_ref(key).map(to_object_ref).into_iter().collect()
+    }
+}
+
+fn layer_name(xref: &XRef, object: ObjectRef) -> String {
+    xref.get::<Dict<'_>>(ObjectIdentifier::new(object.number, object.generation))
+        .and_then(|ocg| ocg.get::<hayro_syntax::object::String<'_>>(NAME))
+        .map(|name| decode_text_string(&name.get()))
+        .unwrap_or_default()
+}
+
+fn to_object_ref(obj_ref: ObjRef) -> ObjectRef {
+    ObjectRef {
+        number: obj_ref.obj_number,
+        generation: obj_ref.gen_number,
+    }
+}
+
+// マーク付きコンテンツの入れ子の1段
+#[derive(Clone)]
+struct MarkedContent {
+    // /OCを持たなければNone
+    membership: Option<Membership>,
+    // 構造要素と結び付ける/MCID
+    mcid: Option<i32>,
+    // 装飾などの構造に含まれない内容（/Artifact）か
+    artifact: bool,
+}
+
+// Doで描かれる画像1つ分の記録。フォームXObjectの中の画像は、hayroが描く順に並べておく
+pub(crate) struct PaintedImage<'a> {
+    cache_key: u128,
+    // インライン画像ならNone
+    pub(crate) object: Option<ImageObject<'a>>,
+    // ページから画像までに入れ子になったフォームXObjectの中の/OC（最も外側のフォーム自身のものは除く）
+    pub(crate) memberships: Vec<Membership>,
+    pub(crate) mcid: Option<i32>,
+    pub(crate) artifact: bool,
+    // 画像を含む最も内側の、/StructParentsを持つフォームXObjectの値
+    pub(crate) struct_parents: Option<i32>,
+}
+
+pub(crate) struct OptionalContent<'a> {
+    xref: &'a XRef,
+    states: LayerStates,
+    marked_content: Vec<MarkedContent>,
+    // 実行中のDoで直接呼ばれたXObjectの/OC
+    invoked_x_object: Option<Membership>,
+    // 実行中のDoで描かれる画像
+    painted_images: VecDeque<PaintedImage<'a>>,
+}
+
+impl<'a> OptionalContent<'a> {
+    pub(crate) fn new(xref: &'a XRef, visibility: LayerVisibility) -> Self {
+        Self {
+            xref,
+            states: LayerStates {
+                visibility,
+                layers: read_layers(xref),
+            },
+            marked_content: Vec::new(),
+            invoked_x_object: None,
+            painted_images: VecDeque::new(),
+        }
+    }
+
+    pub(crate) fn reset(&mut self) {
+        self.marked_content.clear();
+        self.invoked_x_object = None;
+        self.painted_images.clear();
+    }
+
+    // hayroが描いた画像の記録を取り出す。非表示やデコードできずに描かれなかった画像の記録は読み飛ばす
+    pub(crate) fn take_painted_image(&mut self, cache_key: u128) -> Option<PaintedImage<'a>> {
+        let position = self
+            .painted_images
+            .iter()
+            .position(|painted| painted.cache_key == cache_key)?;
+        self.painted_images.drain(..position);
+        self.painted_images.pop_front()
+    }
+
+    pub(crate) fn begin(
+        &mut self,
+        membership: Option<Membership>,
+        mcid: Option<i32>,
+        artifact: bool,
+    ) {
+        self.marked_content.push(MarkedContent {
+            membership,
+            mcid,
+            artifact,
+        });
+    }
+
+    pub(crate) fn end(&mut self) {
+        self.marked_content.pop();
+    }
+
+    // 内側から見て最初の/MCID
+    pub(crate) fn current_mcid(&self) -> Option<i32> {
+        self.marked_content
+            .iter()
+            .rev()
+            .find_map(|marked_content| marked_content.mcid)
+    }
+
+    pub(crate) fn in_artifact(&self) -> bool {
+        self.marked_content
+            .iter()
+            .any(|marked_content| marked_content.artifact)
+    }
+
+    // /OCの参照先がOCGかOCMDのときだけ表示条件として扱う
+    pub(crate) fn membership(&self, obj_ref: ObjRef) -> Option<Membership> {
+        let dict = self.xref.get::<Dict<'_>>(obj_ref.into())?;
+        let (ocgs, policy) = match dict.get::<Name<'_>>(TYPE).as_deref() {
+            Some(OCG) => (vec![to_object_ref(obj_ref)], VisibilityPolicy::AnyOn),
+            Some(OCMD) => {
+                let policy = match dict.get::<Name<'_>>(P).as_deref() {
+                    Some(b"AllOn") => VisibilityPolicy::AllOn,
+                    Some(b"AnyOff") => VisibilityPolicy::AnyOff,
+                    Some(b"AllOff") => VisibilityPolicy::AllOff,
+                    _ => VisibilityPolicy::AnyOn,
+                };
+                (object_refs(&dict, OCGS), policy)
+            }
+            _ => return None,
+        };
+        Some(Membership {
+            ocgs: ocgs
+                .into_iter()
+                .map(|ocg| (ocg, layer_name(self.xref, ocg)))
+                .collect(),
+            policy,
+        })
+    }
+
+    // 現在の内容（とフォームXObjectの中や描画中の画像XObject自身の/OC）が表示されるかと、属するレイヤ名
+    pub(crate) fn current<'m>(
+        &'m self,
+        others: impl IntoIterator<Item = &'m Membership>,
+    ) -> (bool, Vec<String>) {
+        let memberships = self
+            .marked_content
+            .iter()
+            .filter_map(|marked_content| marked_content.membership.as_ref())
+            .chain(&self.invoked_x_object)
+            .chain(others);
+        self.states.evaluate(memberships)
+    }
+
+    // 既定の表示設定では、hayroもフォームXObjectの中の非表示のOCGの区間を描かない（OCMDは見ない）
+    fn hidden_by_hayro(&self, obj_ref: ObjRef) -> bool {
+        self.states.visibility == LayerVisibility::Default
+            && self
+                .states
+                .layers
+                .iter()
+                .any(|layer| layer.object == to_object_ref(obj_ref) && !layer.visible_by_default)
+    }
+
+    fn x_object_membership(&self, stream: &Stream<'_>) -> Option<Membership> {
+        stream
+            .dict()
+            .get_ref(OC)
+            .and_then(|obj_ref| self.membership(obj_ref))
+    }
+
+    // Doで描かれる画像を記録する。フォームXObjectの中はhayroが解釈するので、先に命令を読んでおく
+    fn record_painted_images(&mut self, stream: &Stream<'a>, resources: &Resources<'a>) {
+        self.painted_images.clear();
+        match stream.dict().get::<Name<'_>>(SUBTYPE).as_deref() {
+            Some(IMAGE) => self.painted_images.push_back(PaintedImage {
+                cache_key: stream.cache_key(),
+                object: Some(image_object(stream)),
+                memberships: Vec::new(),
+                mcid: None,
+                artifact: false,
+                struct_parents: None,
+            }),
+            Some(FORM) => {
+                let mut images = Vec::new();
+                self.scan_form(stream, resources, FormScope::default(), 0, &mut images);
+                self.painted_images.extend(images);
+            }
+            _ => {}
+        }
+    }
+
+    fn scan_form(
+        &self,
+        form: &Stream<'a>,
+        parent: &Resources<'a>,
+        mut scope: FormScope,
+        depth: usize,
+        images: &mut Vec<PaintedImage<'a>>,
+    ) {
+        if depth > MAX_FORM_DEPTH {
+            return;
+        }
+        let Ok(content) = form.decoded() else {
+            return;
+        };
+        let resources = Resources::from_parent(
+            form.dict().get::<Dict<'_>>(RESOURCES).unwrap_or_default(),
+            parent.clone(),
+        );
+        // /StructParentsを持つフォームの中のMCIDは、そのフォームの値で構造要素を引く
+        if let Some(struct_parents) = form.dict().get::<i32>(STRUCT_PARENTS) {
+            scope.struct_parents = Some(struct_parents);
+            scope.mcid = None;
+        }
+
+        let mut marked_content: Vec<MarkedContent> = Vec::new();
+        let mut hidden_by_hayro: Vec<bool> = Vec::new();
+        for op in TypedIter::new(&content) {
+            match op {
+                TypedInstruction::BeginMarkedContentWithProperties(bdc) => {
+                    let obj_ref = optional_content_ref(&resources, &bdc);
+                    hidden_by_hayro
+                        .push(obj_ref.is_some_and(|obj_ref| self.hidden_by_hayro(obj_ref)));
+                    marked_content.push(MarkedContent {
+                        membership: obj_ref.and_then(|obj_ref| self.membership(obj_ref)),
+                        mcid: marked_content_id(&resources, &bdc),
+                        artifact: &*bdc.0 == ARTIFACT,
+                    });
+                }
+                TypedInstruction::BeginMarkedContent(bmc) => {
+                    hidden_by_hayro.push(false);
+                    marked_content.push(MarkedContent {
+                        membership: None,
+                        mcid: None,
+                        artifact: &*bmc.0 == ARTIFACT,
+                    });
+                }
+                TypedInstruction::EndMarkedContent(_) => {
+                    hidden_by_hayro.pop();
+                    marked_content.pop();
+                }
+                // hayroが描かない画像の記録は残さない
+                _ if hidden_by_hayro.contains(&true) => {}
+                TypedInstruction::XObject(x_object) => {
+                    let Some(stream) = resources.get_x_object(x_object.0) else {
+                        continue;
+                    };
+                    let inner = scope.enter(&marked_content);
+                    match stream.dict().get::<Name<'_>>(SUBTYPE).as_deref() {
+                        // 画像XObject自身の/OCは描くときに読む
+                        Some(IMAGE) => images
+                            .push(inner.painted(stream.cache_key(), Some(image_object(&stream)))),
+                        Some(FORM) => {
+                            let mut inner = inner;
+                            inner.memberships.extend(self.x_object_membership(&stream));
+                            self.scan_form(&stream, &resources, inner, depth + 1, images);
+                        }
+                        _ => {}
+                    }
+                }
+                TypedInstruction::InlineImage(inline) => {
+                    images.push(
+                        scope
+                            .enter(&marked_content)
+                            .painted(inline.0.cache_key(), None),
+                    );
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+fn image_object<'a>(stream: &Stream<'a>) -> ImageObject<'a> {
+    ImageObject {
+        object_ref: stream.dict().obj_id().map(object_ref_of),
+        stream: stream.clone(),
+    }
+}
+
+// フォームXObjectの中を読むときの、外側のフォームから引き継ぐマーク付きコンテンツ
+#[derive(Clone, Default)]
+struct FormScope {
+    memberships: Vec<Membership>,
+    mcid: Option<i32>,
+    artifact: bool,
+    struct_parents: Option<i32>,
+}
+
+impl FormScope {
+    fn enter(&self, marked_content: &[MarkedContent]) -> Self {
+        let mut scope = self.clone();
+        for marked_content in marked_content {
+            scope.memberships.extend(marked_content.membership.clone());
+            scope.mcid = marked_content.mcid.or(scope.mcid);
+            scope.artifact |= marked_content.artifact;
+        }
+        scope
+    }
+
+    fn painted<'a>(self, cache_key: u128, object: Option<ImageObject<'a>>) -> PaintedImage<'a> {
+        PaintedImage {
+            cache_key,
+            object,
+            memberships: self.memberships,
+            mcid: self.mcid,
+            artifact: self.artifact,
+            struct_parents: self.struct_parents,
+        }
+    }
+}
+
+// プロパティはリソースの名前か、/OCを持つインライン辞書
+fn optional_content_ref(
+    resources: &Resources<'_>,
+    bdc: &BeginMarkedContentWithProperties<'_>,
+) -> Option<ObjRef> {
+    if let Some(name) = bdc.1.clone().into_name() {
+        resources.properties.get_ref(name)
+    } else {
+        dict_or_stream(&bdc.1)?.0.get_ref(OC)
+    }
+}
+
+fn marked_content_id(
+    resources: &Resources<'_>,
+    bdc: &BeginMarkedContentWithProperties<'_>,
+) -> Option<i32> {
+    let properties = if let Some(name) = bdc.1.clone().into_name() {
+        resources.properties.get::<Dict<'_>>(name)?
+    } else {
+        dict_or_stream(&bdc.1)?.0
+    };
+    properties.get::<i32>(MCID)
+}
+
+struct LayerStates {
+    visibility: LayerVisibility,
+    layers: Vec<Layer>,
+}
+
+impl LayerStates {
+    fn evaluate<'m>(
+        &self,
+        memberships: impl Iterator<Item = &'m Membership>,
+    ) -> (bool, Vec<String>) {
+        let mut visible = true;
+        let mut names: Vec<String> = Vec::new();
+        for membership in memberships {
+            visible &= self.is_visible(membership);
+            for (_, name) in &membership.ocgs {
+                if !names.contains(name) {
+                    names.push(name.clone());
+                }
+            }
+        }
+        (visible, names)
+    }
+
+    fn is_visible(&self, membership: &Membership) -> bool {
+        if membership.ocgs.is_empty() {
+            return true;
+        }
+        let mut states = membership
+            .ocgs
+            .iter()
+            .map(|(ocg, name)| self.is_layer_on(*ocg, name));
+        match membership.policy {
+            VisibilityPolicy::AnyOn => states.any(|on| on),
+            VisibilityPolicy::AllOn => states.all(|on| on),
+            VisibilityPolicy::AnyOff => states.any(|on| !on),
+            VisibilityPolicy::AllOff => states.all(|on| !on),
+        }
+    }
+
+    fn is_layer_on(&self, ocg: ObjectRef, name: &str) -> bool {
+        match &self.visibility {
+            // /OCGsに載っていないOCGは既定で表示とみなす
+            LayerVisibility::Default => self
+                .layers
+                .iter()
+                .find(|layer| layer.object == ocg)
+                .is_none_or(|layer| layer.visible_by_default),
+            LayerVisibility::All => true,
+            LayerVisibility::Only(names) => names.iter().any(|selected| selected == name),
+        }
+    }
+}
+
+// 内容ストリームの命令をhayroへ渡しながら、マーク付きコンテンツの入れ子を追う
+pub(crate) struct MarkedContentTracker<'a, I> {
+    ops: I,
+    resources: Resources<'a>,
+    state: Rc<RefCell<OptionalContent<'a>>>,
+}
+
+impl<'a, I> MarkedContentTracker<'a, I> {
+    pub(crate) fn new(
+        ops: I,
+        resources: Resources<'a>,
+        state: Rc<RefCell<OptionalContent<'a>>>,
+    ) -> Self {
+        Self {
+            ops,
+            resources,
+            state,
+        }
+    }
+}
+
+impl<'a, 'b, I> Iterator for MarkedContentTracker<'a, I>
+where
+    I: Iterator<Item = TypedInstruction<'b>>,
+{
+    type Item = TypedInstruction<'b>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let op = self.ops.next()?;
+        let mut state = self.state.borrow_mut();
+        state.invoked_x_object = None;
+        state.painted_images.clear();
+
+        match op {
+            TypedInstruction::BeginMarkedContentWithProperties(bdc) => {
+                let membership = optional_content_ref(&self.resources, &bdc)
+                    .and_then(|obj_ref| state.membership(obj_ref));
+                let is_optional_content = membership.is_some();
+                state.begin(
+                    membership,
+                    marked_content_id(&self.resources, &bdc),
+                    &*bdc.0 == ARTIFACT,
+                );
+                if is_optional_content {
+                    // 表示の判定はこちらで行うので、hayroには/OCのない区間として渡す
+                    return Some(TypedInstruction::BeginMarkedContent(BeginMarkedContent(
+                        bdc.0,
+                    )));
+                }
+                Some(TypedInstruction::BeginMarkedContentWithProperties(bdc))
+            }
+            TypedInstruction::BeginMarkedContent(bmc) => {
+                state.begin(None, None, &*bmc.0 == ARTIFACT);
+                Some(TypedInstruction::BeginMarkedContent(bmc))
+            }
+            TypedInstruction::EndMarkedContent(emc) => {
+                state.end();
+                Some(TypedInstruction::EndMarkedContent(emc))
+            }
+            TypedInstruction::XObject(x_object) => {
+                if let Some(stream) = self.resources.get_x_object(x_object.0.clone()) {
+                    state.invoked_x_object = state.x_object_membership(&stream);
+                    state.record_painted_images(&stream, &self.resources);
+                }
+                Some(TypedInstruction::XObject(x_object))
+            }
+            op => Some(op),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Layer, LayerStates, LayerVisibility, Membership, VisibilityPolicy};
+    use crate::ObjectRef;
+
+    fn object(number: i32) -> ObjectRef {
+        ObjectRef {
+            number,
+            generation: 0,
+        }
+    }
+
+    // 10: Basemap（既定で表示）、11: Labels（既定で非表示）
+    fn states(visibility: LayerVisibility) -> LayerStates {
+        let layer = |number, name: &str, visible_by_default| Layer {
+            object: object(number),
+            name: name.to_string(),
+            visible_by_default,
+        };
+        LayerStates {
+            visibility,
+            layers: vec![layer(10, "Basemap", true), layer(11, "Labels", false)],
+        }
+    }
+
+    fn membership(ocgs: &[i32], policy: VisibilityPolicy) -> Membership {
+        let name = |number| match number {
+            10 => "Basemap".to_string(),
+            11 => "Labels".to_string(),
+            _ => format!("Layer {number}"),
+        };
+        Membership {
+            ocgs: ocgs
+                .iter()
+                .map(|&number| (object(number), name(number)))
+                .collect(),
+            policy,
+        }
+    }
+
+    #[test]
+    fn default_visibility_follows_the_document() {
+        let states = states(LayerVisibility::Default);
+        let basemap = membership(&[10], VisibilityPolicy::AnyOn);
+        let labels = membership(&[11], VisibilityPolicy::AnyOn);
+
+        assert_eq!(
+            states.evaluate([&basemap].into_iter()),
+            (true, vec!["Basemap".to_string()])
+        );
+        let (visible, layers) = states.evaluate([&basemap, &labels].into_iter());
+        assert!(!visible);
+        assert_eq!(layers, ["Basemap", "Labels"]);
+
+        // /OCGsに載っていないOCGは表示する
+        assert!(states.is_visible(&membership(&[12], VisibilityPolicy::AnyOn)));
+    }
+
+    #[test]
+    fn all_layers_are_visible() {
+        let states = states(LayerVisibility::All);
+        assert!(states.is_visible(&membership(&[11], VisibilityPolicy::AnyOn)));
+    }
+
+    #[test]
+    fn only_named_layers_are_visible() {
+        let states = states(LayerVisibility::Only(vec!["Labels".to_string()]));
+        // レイヤに属さない内容は表示する
+        assert!(states.evaluate(std::iter::empty()).0);
+        assert!(!states.is_visible(&membership(&[10], VisibilityPolicy::AnyOn)));
+        assert!(states.is_visible(&membership(&[11], VisibilityPolicy::AnyOn)));
+    }
+
+    #[test]
+    fn membership_policies_combine_layers() {
+        let states = states(LayerVisibility::Default);
+        assert!(states.is_visible(&membership(&[10, 11], VisibilityPolicy::AnyOn)));
+        assert!(!states.is_visible(&membership(&[10, 11], VisibilityPolicy::AllOn)));
+        assert!(states.is_visible(&membership(&[10, 11], VisibilityPolicy::AnyOff)));
+        assert!(!states.is_visible(&membership(&[10, 11], VisibilityPolicy::AllOff)));
+        assert!(states.is_visible(&membership(&[], VisibilityPolicy::AllOff)));
+    }
+}
@@ -1,29 +1,165 @@
+mod annotations;
+mod attachments;
+mod audit;
+mod captions;
+mod image_objects;
+mod info;
+mod layers;
+mod limits;
+mod number_tree;
+mod orientation;
+mod page_labels;
+mod page_selection;
+mod placement;
+mod progress;
+mod render;
+mod revisions;
+mod stitching;
+mod structure;
+mod svg;
+mod text;
+mod thumbnails;
+mod unused;
+mod xmp;
+
+use std::any::Any;
+use std::cell::Cell;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::panic::{self, AssertUnwindSafe};
+use std::rc::Rc;
 use std::sync::Arc;
+use std::sync::Once;
 
+use hayro_interpret::font::{Glyph, Type3Glyph};
+use hayro_interpret::pattern::Pattern;
 use hayro_interpret::{
-    Context, Device, Image, InterpreterSettings, LumaData, RgbData, interpret_page,
+    CacheKey, ClipPath, Context, Device, FillRule, GlyphDrawMode, Image, InterpreterSettings,
+    LumaData, Paint, PathDrawMode, RectExt, RgbData, interpret,
 };
 use hayro_syntax::Pdf;
+use hayro_syntax::content::TypedIter;
+use hayro_syntax::object::dict::keys::{METADATA, OC, RESOURCES, STRUCT_PARENTS};
+use hayro_syntax::object::{Dict, ObjRef, Stream};
+use hayro_syntax::page::{Page, Resources};
+use hayro_syntax::xref::XRef;
 use image::{DynamicImage, ImageBuffer, Luma, imageops::FilterType};
-use kurbo::{Affine, BezPath, Rect};
+use kurbo::{Affine, BezPath, Rect, Shape};
 use thiserror::Error;
 
+use crate::annotations::annotation_appearances;
+pub use crate::attachments::{
+    AttachmentContent, AttachmentFormat, AttachmentImage, AttachmentWarning, AttachmentWarningKind,
+};
+use crate::attachments::{Attachments, collect_attachments};
+pub use crate::audit::{AccessibilityAudit, AuditIssue, AuditIssueKind, audit_accessibility};
+pub use crate::captions::NearbyText;
+use crate::captions::{PlacedGlyph, find_nearby_text, text_lines};
+pub use crate::image_objects::ObjectRef;
+use crate::image_objects::{
+    ImageObjectIndex, check_supported, describe_filter, describe_object, diagnose_decode_failure,
+    soft_mask_size,
+};
+pub use crate::info::{DocumentInfo, PageInfo, document_info};
+pub use crate::layers::{Layer, LayerVisibility};
+use crate::layers::{MarkedContentTracker, OptionalContent, PaintedImage};
+pub use crate::limits::ExtractLimits;
+use crate::limits::estimated_decoded_bytes;
+pub use crate::orientation::Orientation;
+use crate::orientation::{page_rotation_degrees, reading_orientation};
+pub use crate::page_labels::page_labels;
+use crate::page_labels::read_page_labels;
+pub use crate::page_selection::{PageSelection, PageSelectionError, resolve_page_selection};
+pub use crate::placement::ImageBounds;
+use crate::placement::{Clip, crop_to_displayed, displayed_area};
+pub use crate::progress::{CancellationToken, ExtractProgress};
+pub use crate::render::{
+    InvalidBackgroundColor, RenderOptions, RenderPagesResult, RenderedPage, parse_background_color,
+    render_pages, render_pages_with_progress,
+};
+pub use crate::revisions::{
+    ExtractRevisionsResult, ImageChange, ImageChangeKind, Revision, RevisionImages,
+    extract_revisions, list_revisions,
+};
+use crate::stitching::{Fragment, stitch_fragments};
+pub use crate::structure::StructureElement;
+use crate::structure::StructureTree;
+pub use crate::svg::{ExportSvgResult, SvgGraphic, SvgOptions, export_svg};
+pub use crate::thumbnails::{ExtractThumbnailsResult, PageThumbnail, extract_thumbnails};
+pub use crate::unused::{UnusedImage, UnusedImageKind, UnusedImageWarning};
+use crate::unused::{UnusedImages, collect_unused_images};
+use crate::xmp::read_xmp;
+pub use crate::xmp::{XmpEmbedError, XmpMetadata, embed_xmp_in_png};
+
 #[derive(Debug)]
 pub struct ExtractedImage {
     pub page_index: usize,
+    // 文書の/PageLabelsによるページラベル（なければ1始まりのページ番号）
+    pub page_label: String,
     pub image: DynamicImage,
+    // 向きを補正しなかった場合は回転0・反転なし
+    pub orientation: Orientation,
+    // 注釈の外観ストリームから取り出した画像なら注釈の/Subtype（Stamp、Widgetなど）
+    pub annotation: Option<String>,
+    // タイリングパターンの塗りに使われていた画像か
+    pub from_pattern: bool,
+    // Type3フォントのグリフ手続きの中で描かれた画像か
+    pub from_type3_glyph: bool,
+    // そのグリフのToUnicodeによる文字（分からなければNone）
+    pub glyph_unicode: Option<char>,
+    // 画像が属するオプショナルコンテンツ（レイヤ）の名前
+    pub layers: Vec<String>,
+    pub source: ImageSource,
+    // 画像XObjectの参照（インライン画像ならNone）
+    pub object: Option<ObjectRef>,
+    // タグ付きPDFで画像を含む構造要素（/MCIDまたは画像XObjectの/StructParentで結び付くもの）
+    pub structure: Option<StructureElement>,
+    // /Artifactのマーク付きコンテンツ（装飾など、構造に含めない内容）の中で描かれたか
+    pub artifact: bool,
+    // 画像を配置した矩形（ページ座標、クリップや/Rotateの適用前）
+    pub bounds: ImageBounds,
+    // 画像の上下にある"Figure"・"Fig."・"図"で始まるキャプション（続く行を含む）
+    pub caption: Option<String>,
+    // 画像のすぐ上と下の行（caption_distanceを指定しなければ空）
+    pub nearby_text: NearbyText,
+    // 画像XObjectの/MetadataのXMP（作成者や権利の情報）
+    pub xmp: Option<XmpMetadata>,
+    // 帯やタイルに分けて描かれた画像を継ぎ合わせたときの断片の数（それ以外は1）
+    pub fragment_count: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageSource {
+    // /Resourcesの/XObjectから Do で描かれた画像
+    XObject,
+    // 内容ストリームに直接埋め込まれた画像（BI … ID … EI）
+    Inline,
 }
 
 #[derive(Debug)]
 pub struct ExtractImagesResult {
     pub images: Vec<ExtractedImage>,
     pub errors: Vec<ExtractImageWarning>,
+    // 添付ファイルの画像と、添付PDFの画像（include_attachmentsを指定しなければ空）
+    pub attachments: Vec<AttachmentImage>,
+    pub attachment_errors: Vec<AttachmentWarning>,
+    // 文書のどこでも描かれない画像XObject（include_unusedを指定しなければ空）
+    pub unused_images: Vec<UnusedImage>,
+    pub unused_errors: Vec<UnusedImageWarning>,
 }
 
 #[derive(Debug, Error)]
 pub enum ExtractError {
     #[error("failed to parse pdf: {0}")]
     PdfParse(String),
+    #[error("document selects {pages} pages, exceeding the limit of {limit}")]
+    PageLimitExceeded { pages: usize, limit: usize },
+    #[error("extraction was cancelled")]
+    Cancelled,
+    #[error("render dpi must be a positive number, got {0}")]
+    InvalidDpi(f32),
+    #[error(transparent)]
+    InvalidPageSelection(#[from] PageSelectionError),
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Error)]
@@ -34,11 +170,49 @@ pub enum ExtractImageWarningKind {
     InvalidRgbBufferShape,
     #[error("rgba image buffer shape is invalid")]
     InvalidRgbaBufferShape,
+    #[error("image of {width}x{height} pixels exceeds the pixel limit")]
+    ImageTooLarge { width: u32, height: u32 },
+    #[error("page of {width}x{height} pixels exceeds the pixel limit")]
+    PageTooLarge { width: u32, height: u32 },
+    #[error("image count limit reached; remaining images were skipped")]
+    ImageLimitReached,
+    #[error("decoded size limit reached; remaining images were skipped")]
+    DecodedBytesLimitReached,
+    #[error("page count limit reached; remaining pages were skipped")]
+    PageLimitReached,
+    #[error("failed to interpret page: {message}")]
+    PageInterpretFailed { message: String },
+    #[error("image is entirely outside the visible area of the page")]
+    ImageClippedOut,
+    #[error("image decoder panicked: {message}")]
+    ImageDecodePanicked { message: String },
+    #[error("{} uses unsupported filter /{filter}", describe_object(.object))]
+    UnsupportedFilter {
+        object: Option<ObjectRef>,
+        filter: String,
+    },
+    #[error("{} uses unsupported color space /{color_space}", describe_object(.object))]
+    UnsupportedColorSpace {
+        object: Option<ObjectRef>,
+        color_space: String,
+    },
+    #[error("failed to decode {} data of {}", describe_filter(.filter), describe_object(.object))]
+    StreamDecodeFailed {
+        object: Option<ObjectRef>,
+        filter: Option<String>,
+    },
+    #[error("failed to decode {} data of {}", describe_filter(.filter), describe_object(.object))]
+    ImageDecodeFailed {
+        object: Option<ObjectRef>,
+        filter: Option<String>,
+    },
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ExtractImageWarning {
     pub page_index: usize,
+    // ページ内で何番目に描画された画像か（ページ単位の警告ではNone）
+    pub image_index: Option<usize>,
     pub kind: ExtractImageWarningKind,
 }
 
@@ -48,39 +222,304 @@ pub struct PageRange {
     pub end: usize,
 }
 
+#[derive(Debug, Clone, Default)]
+pub struct ExtractOptions {
+    pub limits: ExtractLimits,
+    // 埋め込まれたビットマップではなく、ページ上の見た目（回転・反転・クリップ後）で取り出す
+    pub as_displayed: bool,
+    // 配置の回転・反転とページの/Rotateを打ち消して、画面上で読める向きにそろえる
+    pub normalize_orientation: bool,
+    // 注釈（スタンプ、署名、フォームのウィジェットなど）の外観を解釈せず、ページ本体だけから取り出す
+    pub exclude_annotations: bool,
+    // Type3フォントのグリフに含まれるビットマップ（ステンシルマスクを含む）もグリフごとに1回取り出す
+    pub include_type3_glyphs: bool,
+    // どのレイヤの画像を取り出すか
+    pub layers: LayerVisibility,
+    // インライン画像を取り出さない（小さな飾りやノイズであることが多い）
+    pub exclude_inline_images: bool,
+    // 画像の上下この距離（ポイント）以内のテキストをキャプションとして拾う（Noneならテキストを集めない）
+    pub caption_distance: Option<f32>,
+    // /EmbeddedFilesとファイル添付注釈の画像ファイルを返し、添付PDFからも画像を取り出す
+    pub include_attachments: bool,
+    // 相互参照表をたどり、描かれない画像XObjectとどのページからも参照されない画像ストリームも取り出す
+    pub include_unused: bool,
+    // 帯やタイルに分けて、辺を接して描かれた同じ解像度の画像を1枚に継ぎ合わせる
+    pub stitch_fragments: bool,
+    pub cancellation: CancellationToken,
+}
+
 pub fn extract_images(
     pdf_bytes: &[u8],
     page_ranges: &[PageRange],
 ) -> Result<ExtractImagesResult, ExtractError> {
-    let pdf = Pdf::new(Arc::new(pdf_bytes.to_vec()))
-        .map_err(|err| ExtractError::PdfParse(format!("{err:?}")))?;
-    let mut extractor = ImageExtractor::new();
+    extract_images_with_options(pdf_bytes, page_ranges, &ExtractOptions::default())
+}
+
+pub fn extract_images_with_options(
+    pdf_bytes: &[u8],
+    page_ranges: &[PageRange],
+    options: &ExtractOptions,
+) -> Result<ExtractImagesResult, ExtractError> {
+    extract_images_with_progress(pdf_bytes, page_ranges, options, |_| {})
+}
+
+pub fn extract_images_with_progress(
+    pdf_bytes: &[u8],
+    page_ranges: &[PageRange],
+    options: &ExtractOptions,
+    on_progress: impl FnMut(ExtractProgress),
+) -> Result<ExtractImagesResult, ExtractError> {
+    let pdf = open_pdf(pdf_bytes)?;
+    let layer_free = layer_free_for(options);
+    let interpreter_xref = layer_free.as_ref().unwrap_or(&pdf).xref();
+    let mut extractor = ImageExtractor::new(
+        options,
+        pdf.xref(),
+        read_page_labels(pdf.xref(), pdf.pages().len()),
+    );
+    let selected_pages = interpret_pages(
+        &pdf,
+        interpreter_xref,
+        page_ranges,
+        options,
+        &mut extractor,
+        on_progress,
+    )?;
+
+    let attachments = if options.include_attachments && !extractor.limit_reached {
+        collect_attachments(
+            &pdf,
+            Some(&selected_pages),
+            options,
+            selected_pages.iter().filter(|selected| **selected).count(),
+            extractor.images.len(),
+            extractor.decoded_bytes,
+        )
+    } else {
+        Attachments::default()
+    };
+    let unused = if options.include_unused && !extractor.limit_reached {
+        collect_unused_images(
+            &pdf,
+            options,
+            extractor.images.len() + attachments.images.len(),
+            extractor.decoded_bytes,
+        )
+    } else {
+        UnusedImages::default()
+    };
+
+    // 最後のページの途中で中断された場合も結果は返さない
+    if options.cancellation.is_cancelled() {
+        return Err(ExtractError::Cancelled);
+    }
+
+    Ok(ExtractImagesResult {
+        images: extractor.images,
+        errors: extractor.warnings,
+        attachments: attachments.images,
+        attachment_errors: attachments.warnings,
+        unused_images: unused.images,
+        unused_errors: unused.warnings,
+    })
+}
 
+// 画像をデコードせずに、描かれる画像の配置と構造だけを集める（アクセシビリティの検査用）
+pub(crate) fn inspect_images(
+    pdf_bytes: &[u8],
+    page_ranges: &[PageRange],
+    options: &ExtractOptions,
+) -> Result<(Vec<InspectedImage>, Vec<ExtractImageWarning>), ExtractError> {
+    let pdf = open_pdf(pdf_bytes)?;
+    let layer_free = layer_free_for(options);
+    let interpreter_xref = layer_free.as_ref().unwrap_or(&pdf).xref();
+    let mut extractor = ImageExtractor::new(
+        options,
+        pdf.xref(),
+        read_page_labels(pdf.xref(), pdf.pages().len()),
+    );
+    extractor.inspect_only = true;
+    interpret_pages(
+        &pdf,
+        interpreter_xref,
+        page_ranges,
+        options,
+        &mut extractor,
+        |_| {},
+    )?;
+    if options.cancellation.is_cancelled() {
+        return Err(ExtractError::Cancelled);
+    }
+    Ok((extractor.inspected, extractor.warnings))
+}
+
+// hayroは/OCの内容を文書の既定の表示設定で描き分けるので、ほかの選び方では
+// レイヤのない文書のXRefを渡してすべて描かせ、表示の判定はこちらだけで行う
+fn layer_free_for(options: &ExtractOptions) -> Option<Pdf> {
+    (options.layers != LayerVisibility::Default)
+        .then(layer_free_pdf)
+        .flatten()
+}
+
+// 選ばれたページ（と注釈）を順に解釈し、選ばれたページの印を返す
+fn interpret_pages<'a>(
+    pdf: &'a Pdf,
+    interpreter_xref: &'a XRef,
+    page_ranges: &[PageRange],
+    options: &ExtractOptions,
+    extractor: &mut ImageExtractor<'a>,
+    mut on_progress: impl FnMut(ExtractProgress),
+) -> Result<Vec<bool>, ExtractError> {
     let pages = pdf.pages();
-    let total = pages.len();
-    let selected_pages = select_pages(total, page_ranges);
+    let (selected_pages, selected_count) =
+        select_pages_within_limit(pages.len(), page_ranges, &options.limits)?;
+
+    let mut progress = ExtractProgress {
+        pages_done: 0,
+        total_pages: selected_count,
+        images_found: 0,
+    };
+    on_progress(progress);
 
     for (i, page) in pages.iter().enumerate() {
         if !selected_pages[i] {
             continue;
         }
-        extractor.set_current_page_index(i);
+        if options.cancellation.is_cancelled() {
+            return Err(ExtractError::Cancelled);
+        }
+        if extractor.limit_reached {
+            break;
+        }
+        let crop_box = page.intersected_crop_box().to_kurbo();
+        extractor.start_page(
+            i,
+            page.resources(),
+            crop_box,
+            page_rotation_degrees(page.rotation()),
+            page.raw().get::<i32>(STRUCT_PARENTS),
+        );
 
-        // ダミーのbboxを使用
+        // ページ座標のまま解釈する（クロップボックスを覆うクリップはhayroが省略する）
         let mut context = Context::new(
             Affine::IDENTITY,
-            Rect::new(0.0, 0.0, 1.0, 1.0),
-            pdf.xref(),
-            InterpreterSettings::default(),
+            crop_box,
+            interpreter_xref,
+            page_only_settings(),
         );
 
-        interpret_page(page, &mut context, &mut extractor);
+        // 壊れたストリームでhayroがpanicしても、それまでの結果を残して次のページへ進む
+        // （wasmではpanic=abortのため効かない。catch_quietlyを参照）
+        let interpreted = catch_quietly(|| {
+            let ops = MarkedContentTracker::new(
+                page.typed_operations(),
+                page.resources().clone(),
+                Rc::clone(&extractor.optional_content),
+            );
+            interpret(ops, page.resources(), &mut context, extractor);
+            if !options.exclude_annotations {
+                interpret_annotations(page, interpreter_xref, crop_box, extractor);
+            }
+        });
+        if let Err(payload) = interpreted {
+            extractor.push_page_warning(ExtractImageWarningKind::PageInterpretFailed {
+                message: panic_message(payload.as_ref()),
+            });
+        }
+        extractor.finish_page();
+
+        progress.pages_done += 1;
+        progress.images_found = extractor.images.len();
+        on_progress(progress);
     }
 
-    Ok(ExtractImagesResult {
-        images: extractor.images,
-        errors: extractor.warnings,
-    })
+    Ok(selected_pages)
+}
+
+// 注釈はhayroに任せず、注釈の種類が分かるよう自前で解釈する
+pub(crate) fn page_only_settings() -> InterpreterSettings {
+    InterpreterSettings {
+        render_annotations: false,
+        ..Default::default()
+    }
+}
+
+fn interpret_annotations<'a>(
+    page: &Page<'a>,
+    interpreter_xref: &'a XRef,
+    crop_box: Rect,
+    extractor: &mut ImageExtractor<'a>,
+) {
+    for appearance in annotation_appearances(page) {
+        if extractor.limit_reached || extractor.cancellation.is_cancelled() {
+            return;
+        }
+        let Ok(content) = appearance.stream.decoded() else {
+            continue;
+        };
+        let resources = Resources::from_parent(
+            appearance
+                .stream
+                .dict()
+                .get::<Dict<'_>>(RESOURCES)
+                .unwrap_or_default(),
+            page.resources().clone(),
+        );
+        extractor.image_objects.add_resources(&resources);
+        extractor.current_annotation = Some(appearance.subtype);
+        let membership = appearance
+            .optional_content
+            .and_then(|obj_ref| extractor.optional_content.borrow().membership(obj_ref));
+        extractor
+            .optional_content
+            .borrow_mut()
+            .begin(membership, None, false);
+
+        let mut context = Context::new(
+            appearance.transform,
+            crop_box,
+            interpreter_xref,
+            page_only_settings(),
+        );
+        // 外観ストリームは/BBoxの外側を描かない
+        extractor.push_clip_path(&ClipPath {
+            path: appearance.transform * appearance.bbox.to_path(0.1),
+            fill: FillRule::NonZero,
+        });
+        let ops = MarkedContentTracker::new(
+            TypedIter::new(&content),
+            resources.clone(),
+            Rc::clone(&extractor.optional_content),
+        );
+        interpret(ops, &resources, &mut context, extractor);
+        extractor.pop_clip_path();
+        extractor.optional_content.borrow_mut().reset();
+        extractor.current_annotation = None;
+    }
+}
+
+pub(crate) fn open_pdf(pdf_bytes: &[u8]) -> Result<Pdf, ExtractError> {
+    Pdf::new(Arc::new(pdf_bytes.to_vec())).map_err(|err| ExtractError::PdfParse(format!("{err:?}")))
+}
+
+// hayroのContextはXRefをカタログの/OCPropertiesを読むためだけに使い、オブジェクトはリソースからたどる
+fn layer_free_pdf() -> Option<Pdf> {
+    const LAYER_FREE_PDF: &[u8] = b"%PDF-1.7\n1 0 obj\n<< /Type /Catalog /Pages 2 0 R >>\nendobj\n\
+        2 0 obj\n<< /Type /Pages /Kids [] /Count 0 >>\nendobj\n\
+        xref\n0 3\n0000000000 65535 f \n0000000009 00000 n \n0000000058 00000 n \n\
+        trailer\n<< /Size 3 /Root 1 0 R >>\nstartxref\n110\n%%EOF\n";
+    Pdf::new(Arc::new(LAYER_FREE_PDF.to_vec())).ok()
+}
+
+pub(crate) fn select_pages_within_limit(
+    total: usize,
+    page_ranges: &[PageRange],
+    limits: &ExtractLimits,
+) -> Result<(Vec<bool>, usize), ExtractError> {
+    let selected_pages = select_pages(total, page_ranges);
+    let selected_count = selected_pages.iter().filter(|&&selected| selected).count();
+    limits.check_page_count(selected_count)?;
+    Ok((selected_pages, selected_count))
 }
 
 fn select_pages(total: usize, page_ranges: &[PageRange]) -> Vec<bool> {
@@ -120,78 +559,525 @@ fn normalize_page_range(page_range: &PageRange, total: usize) -> Option<(usize,
     Some((start, end))
 }
 
-struct ImageExtractor {
+// デコードの前に分かる、描かれた画像の情報
+pub(crate) struct InspectedImage {
+    pub page_index: usize,
+    pub page_label: String,
+    // ページ内で何番目に描画された画像か
+    pub image_index: usize,
+    pub object: Option<ObjectRef>,
+    pub structure: Option<StructureElement>,
+    pub artifact: bool,
+    pub bounds: ImageBounds,
+}
+
+struct DrawnImage {
+    layers: Vec<String>,
+    object: Option<ObjectRef>,
+    source: ImageSource,
+    structure: Option<StructureElement>,
+    artifact: bool,
+    bounds: ImageBounds,
+    // 画像XObjectの/Metadata（取り出すときに初めて読む）
+    metadata: Option<ObjRef>,
+    // 継ぎ合わせの候補としての配置（stitch_fragmentsを指定しなければNone）
+    fragment: Option<Fragment>,
+}
+
+struct ImageExtractor<'a> {
     images: Vec<ExtractedImage>,
     warnings: Vec<ExtractImageWarning>,
     current_page_index: usize,
+    current_image_index: usize,
+    // ページごとのラベル（/PageLabels）
+    page_labels: Vec<String>,
+    image_objects: ImageObjectIndex<'a>,
+    limits: ExtractLimits,
+    decoded_bytes: u64,
+    limit_reached: bool,
+    as_displayed: bool,
+    normalize_orientation: bool,
+    current_page_rotation: u16,
+    clips: Vec<Clip>,
+    current_annotation: Option<String>,
+    // 解釈中のタイリングパターンの入れ子の深さ
+    pattern_depth: usize,
+    visited_patterns: HashSet<u128>,
+    include_type3_glyphs: bool,
+    exclude_inline_images: bool,
+    type3_glyph_depth: usize,
+    current_glyph_unicode: Option<char>,
+    visited_glyphs: HashSet<u128>,
+    // 内容ストリームを読む側と共有するマーク付きコンテンツの状態
+    optional_content: Rc<RefCell<OptionalContent<'a>>>,
+    structure: StructureTree<'a>,
+    // ページの/StructParents（/ParentTreeでMCIDから構造要素を引くキー）
+    current_struct_parents: Option<i32>,
+    caption_distance: Option<f32>,
+    // キャプションを探すために集めたページ上の文字
+    page_glyphs: Vec<PlacedGlyph>,
+    // このページで最初に取り出した画像の位置
+    page_first_image: usize,
+    stitch_fragments: bool,
+    // このページで取り出した画像の配置（imagesのpage_first_image以降と同じ順）
+    page_fragments: Vec<Option<Fragment>>,
+    cancellation: CancellationToken,
+    // デコードせずにinspectedへ記録するだけにする
+    inspect_only: bool,
+    inspected: Vec<InspectedImage>,
+    xref: &'a XRef,
+    // /Metadataのストリームごとに1回だけ読む
+    xmp_by_metadata: HashMap<ObjRef, Option<XmpMetadata>>,
 }
 
-impl ImageExtractor {
-    fn new() -> Self {
+impl<'a> ImageExtractor<'a> {
+    fn new(options: &ExtractOptions, xref: &'a XRef, page_labels: Vec<String>) -> Self {
         ImageExtractor {
             images: Vec::new(),
             warnings: Vec::new(),
             current_page_index: 0,
+            current_image_index: 0,
+            page_labels,
+            image_objects: ImageObjectIndex::default(),
+            limits: options.limits,
+            decoded_bytes: 0,
+            limit_reached: false,
+            as_displayed: options.as_displayed,
+            normalize_orientation: options.normalize_orientation,
+            current_page_rotation: 0,
+            clips: Vec::new(),
+            current_annotation: None,
+            pattern_depth: 0,
+            visited_patterns: HashSet::new(),
+            include_type3_glyphs: options.include_type3_glyphs,
+            exclude_inline_images: options.exclude_inline_images,
+            type3_glyph_depth: 0,
+            current_glyph_unicode: None,
+            visited_glyphs: HashSet::new(),
+            optional_content: Rc::new(RefCell::new(OptionalContent::new(
+                xref,
+                options.layers.clone(),
+            ))),
+            structure: StructureTree::new(xref),
+            current_struct_parents: None,
+            caption_distance: options.caption_distance,
+            page_glyphs: Vec::new(),
+            page_first_image: 0,
+            stitch_fragments: options.stitch_fragments,
+            page_fragments: Vec::new(),
+            cancellation: options.cancellation.clone(),
+            inspect_only: false,
+            inspected: Vec::new(),
+            xref,
+            xmp_by_metadata: HashMap::new(),
         }
     }
 
-    fn set_current_page_index(&mut self, page_index: usize) {
+    fn start_page(
+        &mut self,
+        page_index: usize,
+        resources: &Resources<'a>,
+        crop_box: Rect,
+        rotation: u16,
+        struct_parents: Option<i32>,
+    ) {
         self.current_page_index = page_index;
+        self.current_struct_parents = struct_parents;
+        self.current_image_index = 0;
+        self.current_page_rotation = rotation;
+        self.current_annotation = None;
+        self.pattern_depth = 0;
+        self.type3_glyph_depth = 0;
+        self.current_glyph_unicode = None;
+        self.page_glyphs.clear();
+        self.page_first_image = self.images.len();
+        self.page_fragments.clear();
+        self.optional_content.borrow_mut().reset();
+        self.image_objects.add_resources(resources);
+        // ページ外にはみ出した部分も見えていないものとして扱う
+        self.clips.clear();
+        self.clips.push(Clip::from_rect(crop_box));
+    }
+
+    // 画像より後に描かれた断片や文字もあるので、ページを解釈し終えてから継ぎ合わせ、キャプションを探す
+    fn finish_page(&mut self) {
+        if self.stitch_fragments {
+            let page_images = self.images.split_off(self.page_first_image);
+            let stitched = stitch_fragments(
+                page_images,
+                &self.page_fragments,
+                &self.limits,
+                self.decoded_bytes,
+            );
+            self.images.extend(stitched);
+        }
+        let Some(distance) = self.caption_distance else {
+            return;
+        };
+        let lines = text_lines(&self.page_glyphs);
+        for image in &mut self.images[self.page_first_image..] {
+            let (nearby_text, caption) = find_nearby_text(&lines, &image.bounds, distance);
+            image.nearby_text = nearby_text;
+            image.caption = caption;
+        }
+    }
+
+    fn record_glyph(&mut self, glyph: &Glyph<'a>, transform: Affine, glyph_transform: Affine) {
+        // パターンやType3グリフの中の文字はページの文章ではない
+        if self.caption_distance.is_none()
+            || self.pattern_depth > 0
+            || self.in_type3_glyph()
+            || !self.content_visible()
+        {
+            return;
+        }
+        let Some(ch) = glyph.as_unicode() else {
+            return;
+        };
+        let ink_extent = match glyph {
+            Glyph::Outline(outline) => {
+                let ink = outline.outline().bounding_box();
+                Some((ink.x0, ink.x1))
+            }
+            Glyph::Type3(_) => None,
+        };
+        let Some(placed) = PlacedGlyph::new(ch, transform * glyph_transform, ink_extent) else {
+            return;
+        };
+        // 塗りと線の両方で描かれる文字は2回呼ばれる
+        if self.page_glyphs.last() != Some(&placed) {
+            self.page_glyphs.push(placed);
+        }
+    }
+
+    fn push_page_warning(&mut self, kind: ExtractImageWarningKind) {
+        self.warnings.push(ExtractImageWarning {
+            page_index: self.current_page_index,
+            image_index: None,
+            kind,
+        });
+    }
+
+    fn push_warning(&mut self, kind: ExtractImageWarningKind) {
+        self.warnings.push(ExtractImageWarning {
+            page_index: self.current_page_index,
+            image_index: Some(self.current_image_index),
+            kind,
+        });
+    }
+
+    // 文書全体の上限に達したら警告を1回だけ出して以降の画像を無視する
+    fn stop_at_limit(&mut self, kind: ExtractImageWarningKind) {
+        self.push_warning(kind);
+        self.limit_reached = true;
     }
 
-    fn raster_to_dynamic_image(
+    // デコード前に宣言サイズ（/SMaskを含む）で上限を確認する
+    fn check_limits_before_decode(
         &self,
-        rgb: &RgbData,
-        alpha: Option<&LumaData>,
-    ) -> Result<DynamicImage, ExtractImageWarningKind> {
-        match alpha {
-            Some(alpha) => {
-                let expected_pixels = validate_rgb_shape(rgb.width, rgb.height, rgb.data.len())?;
-
-                let alpha_data: Vec<u8> = if alpha.width == rgb.width && alpha.height == rgb.height
-                {
-                    alpha.data.clone()
-                } else {
-                    let alpha_buf: ImageBuffer<Luma<u8>, Vec<u8>> =
-                        ImageBuffer::from_raw(alpha.width, alpha.height, alpha.data.clone())
-                            .ok_or(ExtractImageWarningKind::InvalidAlphaBufferShape)?;
-
-                    let resized_alpha = image::imageops::resize(
-                        &alpha_buf,
-                        rgb.width,
-                        rgb.height,
-                        FilterType::Triangle,
-                    );
-                    resized_alpha.into_raw()
-                };
+        width: u32,
+        height: u32,
+        soft_mask: Option<(u32, u32)>,
+    ) -> Result<(), LimitViolation> {
+        self.limits
+            .check_image_count(self.images.len())
+            .map_err(LimitViolation::Document)?;
+        let estimated = self
+            .limits
+            .check_image_with_mask(width, height, soft_mask)
+            .map_err(LimitViolation::Image)?;
+        self.limits
+            .check_decoded_bytes(self.decoded_bytes.saturating_add(estimated))
+            .map_err(LimitViolation::Document)
+    }
 
-                validate_alpha_shape(expected_pixels, alpha_data.len())?;
+    // 完全に隠れている画像はNone
+    fn place_image(
+        &self,
+        image: DynamicImage,
+        transform: Affine,
+        declared_width: u32,
+        declared_height: u32,
+    ) -> Result<Option<(DynamicImage, Orientation)>, LimitViolation> {
+        // デコード結果が宣言サイズと異なる場合に備えて実サイズへ合わせる
+        let pixel_transform = transform
+            * Affine::scale_non_uniform(
+                f64::from(declared_width) / f64::from(image.width()),
+                f64::from(declared_height) / f64::from(image.height()),
+            );
+        let image = if self.as_displayed {
+            let Some(area) =
+                displayed_area(image.width(), image.height(), pixel_transform, &self.clips)
+            else {
+                return Ok(None);
+            };
+            self.limits
+                .check_image_dimensions(area.width, area.height)
+                .map_err(LimitViolation::Image)?;
+            self.limits
+                .check_decoded_bytes(
+                    self.decoded_bytes
+                        .saturating_add(estimated_decoded_bytes(area.width, area.height)),
+                )
+                .map_err(LimitViolation::Document)?;
+            match crop_to_displayed(&image, pixel_transform, &self.clips, &area) {
+                Some(image) => image,
+                None => return Ok(None),
+            }
+        } else {
+            image
+        };
+        if !self.normalize_orientation {
+            return Ok(Some((image, Orientation::default())));
+        }
 
-                let mut interleaved = Vec::with_capacity(rgb.data.len() / 3 * 4);
-                let mut rgb_chunks = rgb.data.chunks_exact(3);
-                if !rgb_chunks.remainder().is_empty() {
-                    return Err(ExtractImageWarningKind::InvalidRgbBufferShape);
-                }
-                for (pixel, &a) in rgb_chunks.by_ref().zip(alpha_data.iter()) {
-                    interleaved.extend_from_slice(&[pixel[0], pixel[1], pixel[2], a]);
-                }
+        // 見た目どおりに切り出した画像は配置の回転を適用済みなので、ページの/Rotateだけが残る
+        let orientation = if self.as_displayed {
+            Orientation {
+                rotation: self.current_page_rotation,
+                mirrored: false,
+            }
+        } else {
+            reading_orientation(pixel_transform, self.current_page_rotation)
+        };
+        Ok(Some((orientation.apply(image), orientation)))
+    }
+
+    fn push_inspected(&mut self, drawn: DrawnImage) {
+        if let Err(kind) = self.limits.check_image_count(self.inspected.len()) {
+            self.stop_at_limit(kind);
+            return;
+        }
+        self.inspected.push(InspectedImage {
+            page_index: self.current_page_index,
+            page_label: self
+                .page_labels
+                .get(self.current_page_index)
+                .cloned()
+                .unwrap_or_default(),
+            image_index: self.current_image_index,
+            object: drawn.object,
+            structure: drawn.structure,
+            artifact: drawn.artifact,
+            bounds: drawn.bounds,
+        });
+        self.current_image_index += 1;
+    }
+
+    fn image_xmp(&mut self, metadata: ObjRef) -> Option<XmpMetadata> {
+        let xref = self.xref;
+        self.xmp_by_metadata
+            .entry(metadata)
+            .or_insert_with(|| {
+                xref.get::<Stream<'_>>(metadata.into())
+                    .and_then(|stream| read_xmp(&stream))
+            })
+            .clone()
+    }
 
-                Ok(DynamicImage::ImageRgba8(
-                    ImageBuffer::from_raw(rgb.width, rgb.height, interleaved)
-                        .ok_or(ExtractImageWarningKind::InvalidRgbaBufferShape)?,
-                ))
+    fn push_image(&mut self, image: DynamicImage, orientation: Orientation, drawn: DrawnImage) {
+        // デコーダが宣言と異なるサイズを返すことがあるので実サイズでも確認する
+        if let Err(kind) = self
+            .limits
+            .check_image_dimensions(image.width(), image.height())
+        {
+            self.push_warning(kind);
+            return;
+        }
+        let decoded_bytes = self
+            .decoded_bytes
+            .saturating_add(image.as_bytes().len() as u64);
+        if let Err(kind) = self.limits.check_decoded_bytes(decoded_bytes) {
+            self.stop_at_limit(kind);
+            return;
+        }
+
+        self.decoded_bytes = decoded_bytes;
+        self.page_fragments.push(drawn.fragment);
+        let xmp = drawn.metadata.and_then(|metadata| self.image_xmp(metadata));
+        self.images.push(ExtractedImage {
+            page_index: self.current_page_index,
+            page_label: self
+                .page_labels
+                .get(self.current_page_index)
+                .cloned()
+                .unwrap_or_default(),
+            image,
+            orientation,
+            annotation: self.current_annotation.clone(),
+            from_pattern: self.pattern_depth > 0,
+            from_type3_glyph: self.in_type3_glyph(),
+            glyph_unicode: self.current_glyph_unicode,
+            layers: drawn.layers,
+            source: drawn.source,
+            object: drawn.object,
+            structure: drawn.structure,
+            artifact: drawn.artifact,
+            bounds: drawn.bounds,
+            caption: None,
+            nearby_text: NearbyText::default(),
+            xmp,
+            fragment_count: 1,
+        });
+    }
+
+    // 内側のマーク付きコンテンツの/MCID、なければ画像XObjectの/StructParentで構造要素を探す。
+    // /StructParentsを持つフォームXObjectの中のMCIDはフォームの値で引く。
+    // 注釈の外観ストリームのMCIDはページの/StructParentsとは別のキーなので扱わない
+    fn structure_element(
+        &self,
+        painted: Option<&PaintedImage<'_>>,
+        image_dict: Option<&Dict<'_>>,
+    ) -> Option<StructureElement> {
+        if self.structure.is_empty() || self.current_annotation.is_some() {
+            return None;
+        }
+        let (struct_parents, mcid) = match painted {
+            Some(painted) if painted.struct_parents.is_some() => {
+                (painted.struct_parents, painted.mcid)
             }
-            None => {
-                validate_rgb_shape(rgb.width, rgb.height, rgb.data.len())?;
-                Ok(DynamicImage::ImageRgb8(
-                    ImageBuffer::from_raw(rgb.width, rgb.height, rgb.data.clone())
-                        .ok_or(ExtractImageWarningKind::InvalidRgbBufferShape)?,
-                ))
+            _ => (
+                self.current_struct_parents,
+                painted
+                    .and_then(|painted| painted.mcid)
+                    .or_else(|| self.optional_content.borrow().current_mcid()),
+            ),
+        };
+        if let (Some(struct_parents), Some(mcid)) = (struct_parents, mcid)
+            && let Some(element) = self.structure.marked_content_element(struct_parents, mcid)
+        {
+            return Some(element);
+        }
+        self.structure.object_element(image_dict?)
+    }
+
+    // タイルごとに描かれる画像は同じなので、パターンの内容を最初のタイルの位置で1回だけ解釈する
+    fn extract_pattern_images(&mut self, paint: &Paint<'a>, is_stroke: bool) {
+        let Paint::Pattern(pattern) = paint else {
+            return;
+        };
+        let Pattern::Tiling(tiling) = pattern.as_ref() else {
+            return;
+        };
+        if self.limit_reached
+            || self.cancellation.is_cancelled()
+            || !self.content_visible()
+            || !self.visited_patterns.insert(tiling.cache_key())
+        {
+            return;
+        }
+
+        // 塗られる範囲ではなく、タイルの中のクリップだけで切り出す
+        let clips = std::mem::take(&mut self.clips);
+        self.pattern_depth += 1;
+        tiling.interpret(self, tiling.matrix, is_stroke);
+        self.pattern_depth -= 1;
+        self.clips = clips;
+    }
+
+    fn extract_type3_glyph_images(
+        &mut self,
+        glyph: &Type3Glyph<'a>,
+        transform: Affine,
+        glyph_transform: Affine,
+        paint: &Paint<'a>,
+    ) {
+        // 同じグリフは文字の出現回数によらず最初の1回だけ取り出す
+        if !self.include_type3_glyphs
+            || self.limit_reached
+            || self.cancellation.is_cancelled()
+            || !self.content_visible()
+            || !self.visited_glyphs.insert(glyph.cache_key())
+        {
+            return;
+        }
+
+        let outer_unicode = self.current_glyph_unicode;
+        self.current_glyph_unicode = glyph.as_unicode();
+        self.type3_glyph_depth += 1;
+        glyph.interpret(self, transform, glyph_transform, paint);
+        self.type3_glyph_depth -= 1;
+        self.current_glyph_unicode = outer_unicode;
+    }
+
+    fn content_visible(&self) -> bool {
+        self.optional_content.borrow().current(None).0
+    }
+
+    fn in_type3_glyph(&self) -> bool {
+        self.type3_glyph_depth > 0
+    }
+}
+
+enum LimitViolation {
+    // この画像だけをスキップする
+    Image(ExtractImageWarningKind),
+    // 以降の画像をすべてスキップする
+    Document(ExtractImageWarningKind),
+}
+
+pub(crate) fn raster_to_dynamic_image(
+    rgb: &RgbData,
+    alpha: Option<&LumaData>,
+) -> Result<DynamicImage, ExtractImageWarningKind> {
+    match alpha {
+        Some(alpha) => {
+            let expected_pixels = validate_rgb_shape(rgb.width, rgb.height, rgb.data.len())?;
+
+            let alpha_data: Vec<u8> = if alpha.width == rgb.width && alpha.height == rgb.height {
+                alpha.data.clone()
+            } else {
+                let alpha_buf: ImageBuffer<Luma<u8>, Vec<u8>> =
+                    ImageBuffer::from_raw(alpha.width, alpha.height, alpha.data.clone())
+                        .ok_or(ExtractImageWarningKind::InvalidAlphaBufferShape)?;
+
+                let resized_alpha = image::imageops::resize(
+                    &alpha_buf,
+                    rgb.width,
+                    rgb.height,
+                    FilterType::Triangle,
+                );
+                resized_alpha.into_raw()
+            };
+
+            validate_alpha_shape(expected_pixels, alpha_data.len())?;
+
+            let mut interleaved = Vec::with_capacity(rgb.data.len() / 3 * 4);
+            let mut rgb_chunks = rgb.data.chunks_exact(3);
+            if !rgb_chunks.remainder().is_empty() {
+                return Err(ExtractImageWarningKind::InvalidRgbBufferShape);
+            }
+            for (pixel, &a) in rgb_chunks.by_ref().zip(alpha_data.iter()) {
+                interleaved.extend_from_slice(&[pixel[0], pixel[1], pixel[2], a]);
             }
+
+            Ok(DynamicImage::ImageRgba8(
+                ImageBuffer::from_raw(rgb.width, rgb.height, interleaved)
+                    .ok_or(ExtractImageWarningKind::InvalidRgbaBufferShape)?,
+            ))
+        }
+        None => {
+            validate_rgb_shape(rgb.width, rgb.height, rgb.data.len())?;
+            Ok(DynamicImage::ImageRgb8(
+                ImageBuffer::from_raw(rgb.width, rgb.height, rgb.data.clone())
+                    .ok_or(ExtractImageWarningKind::InvalidRgbBufferShape)?,
+            ))
         }
     }
 }
 
+// ステンシルマスクは塗られる部分を不透明な黒、それ以外を透明にする
+fn stencil_to_dynamic_image(mask: &LumaData) -> Result<DynamicImage, ExtractImageWarningKind> {
+    let expected_pixels = expected_pixel_count(mask.width, mask.height)
+        .ok_or(ExtractImageWarningKind::InvalidAlphaBufferShape)?;
+    validate_alpha_shape(expected_pixels, mask.data.len())?;
+    let data = mask.data.iter().flat_map(|&alpha| [0, alpha]).collect();
+    Ok(DynamicImage::ImageLumaA8(
+        ImageBuffer::from_raw(mask.width, mask.height, data)
+            .ok_or(ExtractImageWarningKind::InvalidAlphaBufferShape)?,
+    ))
+}
+
 fn validate_rgb_shape(
     width: u32,
     height: u32,
@@ -222,8 +1108,40 @@ fn expected_pixel_count(width: u32, height: u32) -> Option<usize> {
     (width as usize).checked_mul(height as usize)
 }
 
-// 画像の抽出に必要なdraw_imageのみ実装
-impl Device<'_> for ImageExtractor {
+// 壊れたPDFでのhayroのpanicは警告として返すので、既定のフックがstderrに出力しないようにする。
+// フックはプロセス全体で共有されるため、一度だけ入れ替え、isolateの中のスレッドでだけ黙らせる。
+// wasm32-unknown-unknownはpanic=abortなので、ここで捕まえられず処理全体が止まる
+pub(crate) fn catch_quietly<R>(f: impl FnOnce() -> R) -> std::thread::Result<R> {
+    static INSTALL_HOOK: Once = Once::new();
+    thread_local! {
+        static QUIET_DEPTH: Cell<usize> = const { Cell::new(0) };
+    }
+    INSTALL_HOOK.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if QUIET_DEPTH.with(Cell::get) == 0 {
+                previous(info);
+            }
+        }));
+    });
+    QUIET_DEPTH.with(|depth| depth.set(depth.get() + 1));
+    let result = panic::catch_unwind(AssertUnwindSafe(f));
+    QUIET_DEPTH.with(|depth| depth.set(depth.get() - 1));
+    result
+}
+
+pub(crate) fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+// 画像の抽出に必要なdraw_imageと、パターンの塗りを辿るdraw_path・draw_glyph（キャプション用の文字の収集を含む）のみ実装
+impl<'a> Device<'a> for ImageExtractor<'a> {
     fn set_soft_mask(&mut self, _mask: Option<hayro_interpret::SoftMask<'_>>) {}
 
     fn set_blend_mode(&mut self, _blend_mode: hayro_interpret::BlendMode) {}
@@ -232,12 +1150,17 @@ impl Device<'_> for ImageExtractor {
         &mut self,
         _path: &BezPath,
         _transform: Affine,
-        _paint: &hayro_interpret::Paint<'_>,
-        _draw_mode: &hayro_interpret::PathDrawMode,
+        paint: &Paint<'a>,
+        draw_mode: &PathDrawMode,
     ) {
+        self.extract_pattern_images(paint, matches!(draw_mode, PathDrawMode::Stroke(_)));
     }
 
-    fn push_clip_path(&mut self, _clip_path: &hayro_interpret::ClipPath) {}
+    fn push_clip_path(&mut self, clip_path: &ClipPath) {
+        if self.as_displayed {
+            self.clips.push(Clip::from_clip_path(clip_path));
+        }
+    }
 
     fn push_transparency_group(
         &mut self,
@@ -249,33 +1172,163 @@ impl Device<'_> for ImageExtractor {
 
     fn draw_glyph(
         &mut self,
-        _glyph: &hayro_interpret::font::Glyph<'_>,
-        _transform: Affine,
-        _glyph_transform: Affine,
-        _paint: &hayro_interpret::Paint<'_>,
-        _draw_mode: &hayro_interpret::GlyphDrawMode,
+        glyph: &Glyph<'a>,
+        transform: Affine,
+        glyph_transform: Affine,
+        paint: &Paint<'a>,
+        draw_mode: &GlyphDrawMode,
     ) {
+        // 透明テキスト（スキャンのOCR結果など）もキャプションとして読む
+        self.record_glyph(glyph, transform, glyph_transform);
+        match draw_mode {
+            GlyphDrawMode::Fill => self.extract_pattern_images(paint, false),
+            GlyphDrawMode::Stroke(_) => self.extract_pattern_images(paint, true),
+            GlyphDrawMode::Invisible => return,
+        }
+        if let Glyph::Type3(glyph) = glyph {
+            self.extract_type3_glyph_images(glyph, transform, glyph_transform, paint);
+        }
     }
 
-    fn draw_image(&mut self, image: Image<'_, '_>, _: Affine) {
-        if let Image::Raster(raster) = image {
-            raster.with_rgba(
-                |rgb, alpha| match self.raster_to_dynamic_image(&rgb, alpha.as_ref()) {
-                    Ok(image) => self.images.push(ExtractedImage {
-                        page_index: self.current_page_index,
-                        image,
-                    }),
-                    Err(kind) => self.warnings.push(ExtractImageWarning {
-                        page_index: self.current_page_index,
-                        kind,
-                    }),
-                },
-                None,
-            );
+    fn draw_image(&mut self, image: Image<'a, '_>, transform: Affine) {
+        // ステンシルマスクは塗りの形でしかないので、Type3グリフの形として取り出すときだけ扱う
+        if matches!(image, Image::Stencil(_)) && !self.in_type3_glyph() {
+            return;
+        }
+        // 中断後はページの残りの命令を読み流すだけにする
+        if self.limit_reached || self.cancellation.is_cancelled() {
+            return;
+        }
+        // パターンやグリフの中の画像は、内容ストリームのDoではなくhayroが直接描く
+        let painted = if self.pattern_depth == 0 && !self.in_type3_glyph() {
+            self.optional_content
+                .borrow_mut()
+                .take_painted_image(image.cache_key())
+        } else {
+            None
+        };
+        let image_object = match &painted {
+            Some(painted) => painted.object.clone(),
+            None => self.image_objects.get(image.cache_key()),
+        };
+        let image_object = image_object.as_ref();
+        // 画像XObjectはすべて索引にあるので、見つからなければインライン画像
+        let source = match image_object {
+            Some(_) => ImageSource::XObject,
+            None => ImageSource::Inline,
+        };
+        if source == ImageSource::Inline && self.exclude_inline_images {
+            return;
+        }
+        let object = image_object.and_then(|image_object| image_object.object_ref);
+        // 非表示のレイヤの画像は描かれないものとして数えない
+        let (visible, layers) = {
+            let optional_content = self.optional_content.borrow();
+            let membership = image_object
+                .and_then(|image_object| image_object.stream.dict().get_ref(OC))
+                .and_then(|obj_ref| optional_content.membership(obj_ref));
+            let form_memberships = painted.iter().flat_map(|painted| &painted.memberships);
+            optional_content.current(form_memberships.chain(&membership))
+        };
+        if !visible {
+            return;
+        }
+        let (width, height) = (image.width(), image.height());
+        let mut drawn = DrawnImage {
+            layers,
+            object,
+            source,
+            structure: self.structure_element(
+                painted.as_ref(),
+                image_object.map(|image_object| image_object.stream.dict()),
+            ),
+            artifact: painted.as_ref().is_some_and(|painted| painted.artifact)
+                || self.optional_content.borrow().in_artifact(),
+            bounds: ImageBounds::from_transform(transform, width, height),
+            metadata: image_object
+                .and_then(|image_object| image_object.stream.dict().get_ref(METADATA)),
+            fragment: None,
+        };
+        if self.inspect_only {
+            self.push_inspected(drawn);
+            return;
         }
+        if let Some(Err(kind)) = image_object.map(check_supported) {
+            self.push_warning(kind);
+            self.current_image_index += 1;
+            return;
+        }
+        let soft_mask = image_object.and_then(soft_mask_size);
+        match self.check_limits_before_decode(width, height, soft_mask) {
+            Ok(()) => {}
+            Err(LimitViolation::Image(kind)) => {
+                self.push_warning(kind);
+                self.current_image_index += 1;
+                return;
+            }
+            Err(LimitViolation::Document(kind)) => {
+                self.stop_at_limit(kind);
+                return;
+            }
+        }
+
+        let decoded = catch_quietly(|| {
+            let mut decoded = None;
+            match &image {
+                Image::Raster(raster) => raster.with_rgba(
+                    |rgb, alpha| decoded = Some(raster_to_dynamic_image(&rgb, alpha.as_ref())),
+                    None,
+                ),
+                Image::Stencil(stencil) => stencil.with_stencil(
+                    |mask, _paint| decoded = Some(stencil_to_dynamic_image(&mask)),
+                    None,
+                ),
+            }
+            decoded
+        });
+        match decoded {
+            Ok(Some(Ok(decoded))) => match self.place_image(decoded, transform, width, height) {
+                Ok(Some((decoded, orientation))) => {
+                    // 切り抜かれた画像や向きを変えた画像、パターンやグリフの中の画像は継ぎ合わせない
+                    if self.stitch_fragments
+                        && decoded.width() == width
+                        && decoded.height() == height
+                        && orientation == Orientation::default()
+                        && self.pattern_depth == 0
+                        && !self.in_type3_glyph()
+                    {
+                        drawn.fragment = Fragment::new(transform, width, height);
+                    }
+                    self.push_image(decoded, orientation, drawn)
+                }
+                Ok(None) => self.push_warning(ExtractImageWarningKind::ImageClippedOut),
+                Err(LimitViolation::Image(kind)) => self.push_warning(kind),
+                Err(LimitViolation::Document(kind)) => self.stop_at_limit(kind),
+            },
+            Ok(Some(Err(kind))) => self.push_warning(kind),
+            Ok(None) => {
+                let kind = match image_object {
+                    Some(image_object) => diagnose_decode_failure(image_object),
+                    None => ExtractImageWarningKind::ImageDecodeFailed {
+                        object: None,
+                        filter: None,
+                    },
+                };
+                self.push_warning(kind);
+            }
+            Err(payload) => self.push_warning(ExtractImageWarningKind::ImageDecodePanicked {
+                message: panic_message(payload.as_ref()),
+            }),
+        }
+        self.current_image_index += 1;
     }
 
-    fn pop_clip_path(&mut self) {}
+    fn pop_clip_path(&mut self) {
+        // ページのクロップボックスは残す
+        if self.as_displayed && self.clips.len() > 1 {
+            self.clips.pop();
+        }
+    }
 
     fn pop_transparency_group(&mut self) {}
 }
@@ -283,9 +1336,231 @@ impl Device<'_> for ImageExtractor {
 #[cfg(test)]
 mod tests {
     use super::{
-        ExtractImageWarningKind, PageRange, normalize_page_range, select_pages,
-        validate_alpha_shape, validate_rgb_shape,
+        ExtractImageWarningKind, ExtractOptions, LayerVisibility, ObjectRef, PageRange,
+        catch_quietly, extract_images_with_options, normalize_page_range, panic_message,
+        select_pages, stencil_to_dynamic_image, validate_alpha_shape, validate_rgb_shape,
     };
+    use hayro_interpret::LumaData;
+
+    // 1 0 Rから順に番号を振ったオブジェクトを並べ、1 0 Rをカタログとする
+    pub(crate) fn build_pdf(objects: &[Vec<u8>]) -> Vec<u8> {
+        let mut pdf = b"%PDF-1.7\n".to_vec();
+        let mut offsets = Vec::new();
+        for (i, object) in objects.iter().enumerate() {
+            offsets.push(pdf.len());
+            pdf.extend(format!("{} 0 obj\n", i + 1).bytes());
+            pdf.extend(object);
+            pdf.extend(b"\nendobj\n");
+        }
+        let xref = pdf.len();
+        pdf.extend(format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).bytes());
+        for offset in offsets {
+            pdf.extend(format!("{offset:010} 00000 n \n").bytes());
+        }
+        pdf.extend(
+            format!(
+                "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{xref}\n%%EOF\n",
+                objects.len() + 1
+            )
+            .bytes(),
+        );
+        pdf
+    }
+
+    pub(crate) fn stream_object(dict: &str, data: &[u8]) -> Vec<u8> {
+        let mut object = format!("<< {dict} /Length {} >>\nstream\n", data.len()).into_bytes();
+        object.extend(data);
+        object.extend(b"\nendstream");
+        object
+    }
+
+    // 1x1のRGB画像
+    pub(crate) fn pixel_image(rgb: [u8; 3]) -> Vec<u8> {
+        stream_object(
+            "/Type /XObject /Subtype /Image /Width 1 /Height 1 /ColorSpace /DeviceRGB \
+             /BitsPerComponent 8",
+            &rgb,
+        )
+    }
+
+    // 3 0 Rを唯一のページとし、内容を4 0 Rに置く
+    pub(crate) fn single_page_pdf(
+        catalog: &str,
+        resources: &str,
+        content: &str,
+        rest: &[Vec<u8>],
+    ) -> Vec<u8> {
+        let mut objects = vec![
+            format!("<< /Type /Catalog /Pages 2 0 R {catalog} >>").into_bytes(),
+            b"<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_vec(),
+            format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 100 100] /Resources {resources} \
+                 /Contents 4 0 R /StructParents 0 >>"
+            )
+            .into_bytes(),
+            stream_object("", content.as_bytes()),
+        ];
+        objects.extend_from_slice(rest);
+        build_pdf(&objects)
+    }
+
+    fn object(number: i32) -> Option<ObjectRef> {
+        Some(ObjectRef {
+            number,
+            generation: 0,
+        })
+    }
+
+    #[test]
+    fn tells_apart_image_objects_with_identical_dictionaries() {
+        let pdf = single_page_pdf(
+            "",
+            "<< /XObject << /Im1 5 0 R /Im2 6 0 R >> >>",
+            "q 10 0 0 10 0 0 cm /Im2 Do Q q 10 0 0 10 20 0 cm /Im1 Do Q",
+            &[pixel_image([255, 0, 0]), pixel_image([0, 0, 255])],
+        );
+        let result = extract_images_with_options(&pdf, &[], &ExtractOptions::default()).unwrap();
+
+        let images: Vec<_> = result
+            .images
+            .iter()
+            .map(|image| (image.object, image.image.to_rgb8().get_pixel(0, 0).0))
+            .collect();
+        assert_eq!(images, [(object(6), [0, 0, 255]), (object(5), [255, 0, 0])]);
+    }
+
+    #[test]
+    fn checks_the_displayed_size_before_cropping() {
+        // 1x1の画像をほぼ潰れるほどせん断すると、元の解像度で切り出すには100000x100000ピクセル要る
+        let pdf = single_page_pdf(
+            "",
+            "<< /XObject << /Im1 5 0 R >> >>",
+            "100 100 0 0.00000001 0 0 cm /Im1 Do",
+            &[pixel_image([255, 0, 0])],
+        );
+        let options = ExtractOptions {
+            as_displayed: true,
+            ..ExtractOptions::default()
+        };
+        let result = extract_images_with_options(&pdf, &[], &options).unwrap();
+
+        assert!(result.images.is_empty());
+        assert_eq!(
+            result.errors[0].kind,
+            ExtractImageWarningKind::ImageTooLarge {
+                width: 100_000,
+                height: 100_000
+            }
+        );
+    }
+
+    // フォームXObjectの中で、既定で非表示のLabels（8 0 R）と表示のBase（9 0 R）に画像が1つずつ属する
+    fn layered_form_pdf() -> Vec<u8> {
+        single_page_pdf(
+            "/OCProperties << /OCGs [8 0 R 9 0 R] /D << /OFF [8 0 R] >> >>",
+            "<< /XObject << /Fm1 5 0 R >> >>",
+            "q 50 0 0 50 0 0 cm /Fm1 Do Q",
+            &[
+                stream_object(
+                    "/Type /XObject /Subtype /Form /BBox [0 0 1 1] \
+                     /Resources << /XObject << /Im1 6 0 R /Im2 7 0 R >> \
+                     /Properties << /L1 8 0 R /L2 9 0 R >> >>",
+                    b"/OC /L1 BDC /Im1 Do EMC /OC /L2 BDC /Im2 Do EMC",
+                ),
+                pixel_image([255, 0, 0]),
+                pixel_image([0, 0, 255]),
+                b"<< /Type /OCG /Name (Labels) >>".to_vec(),
+                b"<< /Type /OCG /Name (Base) >>".to_vec(),
+            ],
+        )
+    }
+
+    #[test]
+    fn selects_layers_inside_form_x_objects() {
+        let pdf = layered_form_pdf();
+        let extract = |layers| {
+            let options = ExtractOptions {
+                layers,
+                ..ExtractOptions::default()
+            };
+            extract_images_with_options(&pdf, &[], &options)
+                .unwrap()
+                .images
+                .into_iter()
+                .map(|image| (image.object, image.layers))
+                .collect::<Vec<_>>()
+        };
+        let labels = (object(6), vec!["Labels".to_string()]);
+        let base = (object(7), vec!["Base".to_string()]);
+
+        assert_eq!(
+            extract(LayerVisibility::All),
+            [labels.clone(), base.clone()]
+        );
+        assert_eq!(extract(LayerVisibility::Default), [base]);
+        assert_eq!(
+            extract(LayerVisibility::Only(vec!["Labels".to_string()])),
+            [labels]
+        );
+    }
+
+    #[test]
+    fn finds_structure_elements_of_marked_content_in_forms() {
+        let pdf = single_page_pdf(
+            "/StructTreeRoot 7 0 R",
+            "<< /XObject << /Fm1 5 0 R >> >>",
+            "/Figure << /MCID 0 >> BDC q 50 0 0 50 0 0 cm /Fm1 Do Q EMC",
+            &[
+                stream_object(
+                    "/Type /XObject /Subtype /Form /BBox [0 0 1 1] /StructParents 1 \
+                     /Resources << /XObject << /Im1 6 0 R >> >>",
+                    b"/Figure << /MCID 0 >> BDC /Im1 Do EMC",
+                ),
+                pixel_image([255, 0, 0]),
+                b"<< /Type /StructTreeRoot /K [8 0 R 9 0 R] \
+                  /ParentTree << /Nums [0 [8 0 R] 1 [9 0 R]] >> >>"
+                    .to_vec(),
+                b"<< /Type /StructElem /S /Figure /P 7 0 R /Alt (Page) /K 0 >>".to_vec(),
+                b"<< /Type /StructElem /S /Figure /P 7 0 R /Alt (Form) /K 0 >>".to_vec(),
+            ],
+        );
+        let result = extract_images_with_options(&pdf, &[], &ExtractOptions::default()).unwrap();
+
+        let structure = result.images[0].structure.as_ref().unwrap();
+        assert_eq!(structure.object, object(9));
+        assert_eq!(structure.alt.as_deref(), Some("Form"));
+    }
+
+    #[test]
+    fn reads_metadata_shared_by_image_x_objects() {
+        let image_with_metadata = |rgb: [u8; 3]| {
+            stream_object(
+                "/Type /XObject /Subtype /Image /Width 1 /Height 1 /ColorSpace /DeviceRGB \
+                 /BitsPerComponent 8 /Metadata 7 0 R",
+                &rgb,
+            )
+        };
+        let pdf = single_page_pdf(
+            "",
+            "<< /XObject << /Im1 5 0 R /Im2 6 0 R >> >>",
+            "q 10 0 0 10 0 0 cm /Im1 Do Q q 10 0 0 10 20 0 cm /Im2 Do Q",
+            &[
+                image_with_metadata([255, 0, 0]),
+                image_with_metadata([0, 0, 255]),
+                stream_object(
+                    "/Type /Metadata /Subtype /XML",
+                    b"<dc:source>scan 12</dc:source>",
+                ),
+            ],
+        );
+        let result = extract_images_with_options(&pdf, &[], &ExtractOptions::default()).unwrap();
+        let sources: Vec<_> = result
+            .images
+            .iter()
+            .map(|image| image.xmp.as_ref().and_then(|xmp| xmp.source.as_deref()))
+            .collect();
+        assert_eq!(sources, [Some("scan 12"), Some("scan 12")]);
+    }
 
     #[test]
     fn normalize_keeps_valid_range() {
@@ -375,4 +1650,34 @@ mod tests {
             Err(ExtractImageWarningKind::InvalidAlphaBufferShape)
         );
     }
+
+    #[test]
+    fn panic_message_reads_string_payloads() {
+        let payload = catch_quietly(|| panic!("broken {}", "stream")).unwrap_err();
+        assert_eq!(panic_message(payload.as_ref()), "broken stream");
+
+        let payload = catch_quietly(|| std::panic::panic_any(42)).unwrap_err();
+        assert_eq!(panic_message(payload.as_ref()), "unknown panic");
+    }
+
+    #[test]
+    fn catch_quietly_nests_and_returns_values() {
+        let inner = catch_quietly(|| catch_quietly(|| panic!("inner")).is_err());
+        assert_eq!(inner.ok(), Some(true));
+        assert_eq!(catch_quietly(|| 7).ok(), Some(7));
+    }
+
+    #[test]
+    fn stencil_becomes_black_on_transparent() {
+        let mask = LumaData {
+            data: vec![255, 0],
+            width: 2,
+            height: 1,
+            interpolate: false,
+            scale_factors: (1.0, 1.0),
+        };
+        let image = stencil_to_dynamic_image(&mask).unwrap().to_luma_alpha8();
+        assert_eq!(image.get_pixel(0, 0).0, [0, 255]);
+        assert_eq!(image.get_pixel(1, 0).0, [0, 0]);
+    }
 }
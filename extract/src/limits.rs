@@ -0,0 +1,256 @@
+use std::io::Read;
+
+use flate2::read::ZlibDecoder;
+use hayro_syntax::Filter;
+use hayro_syntax::object::Stream;
+use hayro_syntax::object::dict::keys::DECODE_PARMS;
+
+use crate::{ExtractError, ExtractImageWarningKind};
+
+// 信頼できないPDFでメモリを使い切らないための上限（Noneは無制限）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExtractLimits {
+    pub max_image_pixels: Option<u64>,
+    pub max_total_decoded_bytes: Option<u64>,
+    pub max_images: Option<usize>,
+    pub max_pages: Option<usize>,
+}
+
+impl ExtractLimits {
+    pub fn unlimited() -> Self {
+        Self {
+            max_image_pixels: None,
+            max_total_decoded_bytes: None,
+            max_images: None,
+            max_pages: None,
+        }
+    }
+
+    pub(crate) fn check_page_count(&self, pages: usize) -> Result<(), ExtractError> {
+        match self.max_pages {
+            Some(limit) if pages > limit => Err(ExtractError::PageLimitExceeded { pages, limit }),
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn check_image_dimensions(
+        &self,
+        width: u32,
+        height: u32,
+    ) -> Result<(), ExtractImageWarningKind> {
+        match self.max_image_pixels {
+            Some(limit) if pixel_count(width, height) > limit => {
+                Err(ExtractImageWarningKind::ImageTooLarge { width, height })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // /SMaskは画像本体とは別の大きさでデコードされるので、同じ上限で確かめる。
+    // デコード後の大きさの見積もり（画像はRGBA、マスクは1チャンネル）を返す
+    pub(crate) fn check_image_with_mask(
+        &self,
+        width: u32,
+        height: u32,
+        soft_mask: Option<(u32, u32)>,
+    ) -> Result<u64, ExtractImageWarningKind> {
+        self.check_image_dimensions(width, height)?;
+        let mut estimated = estimated_decoded_bytes(width, height);
+        if let Some((mask_width, mask_height)) = soft_mask {
+            self.check_image_dimensions(mask_width, mask_height)?;
+            estimated = estimated.saturating_add(pixel_count(mask_width, mask_height));
+        }
+        Ok(estimated)
+    }
+
+    pub(crate) fn check_image_count(&self, count: usize) -> Result<(), ExtractImageWarningKind> {
+        match self.max_images {
+            Some(limit) if count >= limit => Err(ExtractImageWarningKind::ImageLimitReached),
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn check_decoded_bytes(&self, total: u64) -> Result<(), ExtractImageWarningKind> {
+        match self.max_total_decoded_bytes {
+            Some(limit) if total > limit => Err(ExtractImageWarningKind::DecodedBytesLimitReached),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Default for ExtractLimits {
+    fn default() -> Self {
+        Self {
+            max_image_pixels: Some(100_000_000),
+            max_total_decoded_bytes: Some(1 << 30),
+            max_images: Some(10_000),
+            max_pages: Some(10_000),
+        }
+    }
+}
+
+pub(crate) fn pixel_count(width: u32, height: u32) -> u64 {
+    u64::from(width) * u64::from(height)
+}
+
+// デコード前に見積もるときはRGBAの最大サイズで数える
+pub(crate) fn estimated_decoded_bytes(width: u32, height: u32) -> u64 {
+    pixel_count(width, height).saturating_mul(4)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum StreamDecodeError {
+    Failed,
+    TooLarge,
+}
+
+// /Metadataや添付ファイルのように丸ごと読むストリームを、max_bytesを超えない範囲でデコードする。
+// FlateDecodeだけのストリームは上限を超えたところで展開をやめ、ほかのフィルタは元のデータの大きさも確かめる
+pub(crate) fn decode_stream_within(
+    stream: &Stream<'_>,
+    max_bytes: u64,
+) -> Result<Vec<u8>, StreamDecodeError> {
+    let raw = stream.raw_data();
+    let within = |bytes: Vec<u8>| {
+        if bytes.len() as u64 <= max_bytes {
+            Ok(bytes)
+        } else {
+            Err(StreamDecodeError::TooLarge)
+        }
+    };
+    match stream.filters() {
+        [] => return within(raw.into_owned()),
+        [Filter::FlateDecode] if !stream.dict().contains_key(DECODE_PARMS) => {
+            let mut decoded = Vec::new();
+            // 壊れたzlibはhayroの寛容なデコーダに任せる
+            if ZlibDecoder::new(raw.as_ref())
+                .take(max_bytes.saturating_add(1))
+                .read_to_end(&mut decoded)
+                .is_ok()
+            {
+                return within(decoded);
+            }
+        }
+        _ => {}
+    }
+    if raw.len() as u64 > max_bytes {
+        return Err(StreamDecodeError::TooLarge);
+    }
+    within(stream.decoded().map_err(|_| StreamDecodeError::Failed)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::Compression;
+    use flate2::write::ZlibEncoder;
+    use hayro_syntax::object::{ObjectIdentifier, Stream};
+
+    use super::{ExtractLimits, StreamDecodeError, decode_stream_within, estimated_decoded_bytes};
+    use crate::tests::{build_pdf, stream_object};
+    use crate::{ExtractError, ExtractImageWarningKind, open_pdf};
+
+    #[test]
+    fn rejects_oversized_image() {
+        assert_eq!(
+            ExtractLimits::default().check_image_dimensions(60_000, 60_000),
+            Err(ExtractImageWarningKind::ImageTooLarge {
+                width: 60_000,
+                height: 60_000
+            })
+        );
+    }
+
+    #[test]
+    fn accepts_image_at_pixel_limit() {
+        let limits = ExtractLimits {
+            max_image_pixels: Some(100),
+            ..ExtractLimits::unlimited()
+        };
+        assert_eq!(limits.check_image_dimensions(10, 10), Ok(()));
+    }
+
+    #[test]
+    fn checks_soft_mask_against_the_pixel_limit() {
+        let limits = ExtractLimits {
+            max_image_pixels: Some(100),
+            ..ExtractLimits::unlimited()
+        };
+        assert_eq!(limits.check_image_with_mask(10, 10, Some((5, 4))), Ok(420));
+        assert_eq!(
+            limits.check_image_with_mask(10, 10, Some((1000, 1000))),
+            Err(ExtractImageWarningKind::ImageTooLarge {
+                width: 1000,
+                height: 1000
+            })
+        );
+    }
+
+    #[test]
+    fn image_count_limit_is_exclusive() {
+        let limits = ExtractLimits {
+            max_images: Some(2),
+            ..ExtractLimits::unlimited()
+        };
+        assert_eq!(limits.check_image_count(1), Ok(()));
+        assert_eq!(
+            limits.check_image_count(2),
+            Err(ExtractImageWarningKind::ImageLimitReached)
+        );
+    }
+
+    #[test]
+    fn rejects_too_many_pages() {
+        let limits = ExtractLimits {
+            max_pages: Some(3),
+            ..ExtractLimits::unlimited()
+        };
+        assert!(matches!(
+            limits.check_page_count(4),
+            Err(ExtractError::PageLimitExceeded { pages: 4, limit: 3 })
+        ));
+    }
+
+    #[test]
+    fn unlimited_accepts_everything() {
+        let limits = ExtractLimits::unlimited();
+        assert_eq!(limits.check_image_dimensions(u32::MAX, u32::MAX), Ok(()));
+        assert_eq!(limits.check_decoded_bytes(u64::MAX), Ok(()));
+    }
+
+    #[test]
+    fn estimate_does_not_overflow() {
+        assert_eq!(estimated_decoded_bytes(u32::MAX, u32::MAX), u64::MAX);
+    }
+
+    #[test]
+    fn stops_decoding_streams_at_the_cap() {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&[0; 10_000]).unwrap();
+        let pdf = build_pdf(&[
+            b"<< /Type /Catalog /Pages 2 0 R >>".to_vec(),
+            b"<< /Type /Pages /Kids [] /Count 0 >>".to_vec(),
+            stream_object("/Filter /FlateDecode", &encoder.finish().unwrap()),
+            stream_object("", b"plain"),
+        ]);
+        let pdf = open_pdf(&pdf).unwrap();
+        let stream = |number| {
+            pdf.xref()
+                .get::<Stream<'_>>(ObjectIdentifier::new(number, 0))
+                .unwrap()
+        };
+
+        let inflated = decode_stream_within(&stream(3), 10_000);
+        assert_eq!(inflated.map(|bytes| bytes.len()), Ok(10_000));
+        assert_eq!(
+            decode_stream_within(&stream(3), 9_999),
+            Err(StreamDecodeError::TooLarge)
+        );
+        assert_eq!(decode_stream_within(&stream(4), 5), Ok(b"plain".to_vec()));
+        assert_eq!(
+            decode_stream_within(&stream(4), 4),
+            Err(StreamDecodeError::TooLarge)
+        );
+    }
+}
@@ -0,0 +1,60 @@
+use hayro_syntax::object::dict::keys::{KIDS, NUMS};
+use hayro_syntax::object::{Array, Dict, MaybeRef, ObjRef, Object};
+use hayro_syntax::xref::XRef;
+
+// ツリーが循環していても止まるように深さを制限する
+const MAX_TREE_DEPTH: usize = 32;
+
+#[derive(Clone)]
+pub(crate) struct NumberTreeEntry<'a> {
+    pub(crate) key: i32,
+    // 値が間接参照なら、その参照
+    pub(crate) object_ref: Option<ObjRef>,
+    pub(crate) value: Object<'a>,
+}
+
+// 数値ツリー（/Nums、/Kids）の値をすべて集める
+pub(crate) fn number_tree_entries<'a>(xref: &'a XRef, root: &Dict<'a>) -> Vec<NumberTreeEntry<'a>> {
+    let mut entries = Vec::new();
+    collect_entries(xref, root, &mut entries, 0);
+    entries
+}
+
+fn collect_entries<'a>(
+    xref: &'a XRef,
+    node: &Dict<'a>,
+    entries: &mut Vec<NumberTreeEntry<'a>>,
+    depth: usize,
+) {
+    if depth > MAX_TREE_DEPTH {
+        return;
+    }
+    if let Some(nums) = node.get::<Array<'a>>(NUMS) {
+        let mut items = nums.raw_iter();
+        while let (Some(key), Some(value)) = (items.next(), items.next()) {
+            let Some(key) = resolve(xref, key).and_then(|key| key.into_i32()) else {
+                continue;
+            };
+            let object_ref = value.as_obj_ref();
+            if let Some(value) = resolve(xref, value) {
+                entries.push(NumberTreeEntry {
+                    key,
+                    object_ref,
+                    value,
+                });
+            }
+        }
+    }
+    if let Some(kids) = node.get::<Array<'a>>(KIDS) {
+        for kid in kids.iter::<Dict<'a>>() {
+            collect_entries(xref, &kid, entries, depth + 1);
+        }
+    }
+}
+
+pub(crate) fn resolve<'a>(xref: &'a XRef, value: MaybeRef<Object<'a>>) -> Option<Object<'a>> {
+    match value {
+        MaybeRef::Ref(obj_ref) => xref.get::<Object<'a>>(obj_ref.into()),
+        MaybeRef::NotRef(object) => Some(object),
+    }
+}
@@ -0,0 +1,155 @@
+use hayro_syntax::page::Rotation;
+use image::DynamicImage;
+use kurbo::Affine;
+
+// 画像に適用した向きの補正（時計回りに回転してから、必要なら左右反転する）
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Orientation {
+    pub rotation: u16,
+    pub mirrored: bool,
+}
+
+impl Orientation {
+    pub(crate) fn apply(self, image: DynamicImage) -> DynamicImage {
+        let rotated = match self.rotation {
+            90 => image.rotate90(),
+            180 => image.rotate180(),
+            270 => image.rotate270(),
+            _ => image,
+        };
+        if self.mirrored {
+            rotated.fliph()
+        } else {
+            rotated
+        }
+    }
+}
+
+pub(crate) fn page_rotation_degrees(rotation: Rotation) -> u16 {
+    match rotation {
+        Rotation::None => 0,
+        Rotation::Horizontal => 90,
+        Rotation::Flipped => 180,
+        Rotation::FlippedHorizontal => 270,
+    }
+}
+
+// 画像のピクセル座標からページ座標への変換とページの/Rotateから、
+// 画面上で読める向きにするための90度単位の回転と反転を求める
+pub(crate) fn reading_orientation(transform: Affine, page_rotation: u16) -> Orientation {
+    let [a, b, c, d, _, _] = transform.as_coeffs();
+    // ページ座標（y軸が上向き）から画面座標（y軸が下向き）へ
+    let (a, b, c, d) = (a, -b, c, -d);
+    // 表示時の/Rotateは画面上で時計回り
+    let (sin, cos) = f64::from(page_rotation).to_radians().sin_cos();
+    let (m00, m01) = (cos * a - sin * b, cos * c - sin * d);
+    let (m10, m11) = (sin * a + cos * b, sin * c + cos * d);
+
+    // 画像のx軸・y軸が画面上でどの向きを指しているか
+    if m00.abs() + m11.abs() >= m01.abs() + m10.abs() {
+        match (m00 >= 0.0, m11 >= 0.0) {
+            (true, true) => Orientation::default(),
+            (false, true) => Orientation {
+                rotation: 0,
+                mirrored: true,
+            },
+            (true, false) => Orientation {
+                rotation: 180,
+                mirrored: true,
+            },
+            (false, false) => Orientation {
+                rotation: 180,
+                mirrored: false,
+            },
+        }
+    } else {
+        match (m10 >= 0.0, m01 >= 0.0) {
+            (true, false) => Orientation {
+                rotation: 90,
+                mirrored: false,
+            },
+            (false, true) => Orientation {
+                rotation: 270,
+                mirrored: false,
+            },
+            (true, true) => Orientation {
+                rotation: 90,
+                mirrored: true,
+            },
+            (false, false) => Orientation {
+                rotation: 270,
+                mirrored: true,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Orientation, reading_orientation};
+    use image::{DynamicImage, Rgb, RgbImage};
+    use kurbo::Affine;
+
+    // hayroと同じく、画像のピクセル座標を上下反転して配置する
+    fn placement(ctm: Affine) -> Affine {
+        ctm * Affine::new([0.5, 0.0, 0.0, -0.5, 0.0, 1.0])
+    }
+
+    fn orientation(rotation: u16, mirrored: bool) -> Orientation {
+        Orientation { rotation, mirrored }
+    }
+
+    #[test]
+    fn upright_placement_needs_no_correction() {
+        let transform = placement(Affine::scale(20.0));
+        assert_eq!(reading_orientation(transform, 0), Orientation::default());
+    }
+
+    #[test]
+    fn rotated_placements_match_the_page() {
+        // 反時計回りに90度置かれた画像は、ページ上と同じく時計回りに270度回す
+        let ccw = placement(Affine::new([0.0, 20.0, -20.0, 0.0, 20.0, 0.0]));
+        assert_eq!(reading_orientation(ccw, 0), orientation(270, false));
+
+        let cw = placement(Affine::new([0.0, -20.0, 20.0, 0.0, 0.0, 20.0]));
+        assert_eq!(reading_orientation(cw, 0), orientation(90, false));
+
+        let upside_down = placement(Affine::new([-20.0, 0.0, 0.0, -20.0, 20.0, 20.0]));
+        assert_eq!(reading_orientation(upside_down, 0), orientation(180, false));
+    }
+
+    #[test]
+    fn mirrored_placements_are_flipped() {
+        let horizontal = placement(Affine::new([-20.0, 0.0, 0.0, 20.0, 20.0, 0.0]));
+        assert_eq!(reading_orientation(horizontal, 0), orientation(0, true));
+
+        let vertical = placement(Affine::new([20.0, 0.0, 0.0, -20.0, 0.0, 20.0]));
+        assert_eq!(reading_orientation(vertical, 0), orientation(180, true));
+    }
+
+    #[test]
+    fn page_rotation_is_applied() {
+        let transform = placement(Affine::scale(20.0));
+        assert_eq!(reading_orientation(transform, 90), orientation(90, false));
+        assert_eq!(reading_orientation(transform, 270), orientation(270, false));
+
+        // ページの回転で画像の回転が打ち消される
+        let ccw = placement(Affine::new([0.0, 20.0, -20.0, 0.0, 20.0, 0.0]));
+        assert_eq!(reading_orientation(ccw, 90), Orientation::default());
+    }
+
+    #[test]
+    fn apply_rotates_then_mirrors() {
+        let mut image = RgbImage::from_pixel(2, 1, Rgb([0, 0, 255]));
+        image.put_pixel(0, 0, Rgb([255, 0, 0]));
+        let image = DynamicImage::ImageRgb8(image);
+
+        let rotated = orientation(90, false).apply(image.clone()).to_rgb8();
+        assert_eq!(rotated.dimensions(), (1, 2));
+        assert_eq!(rotated.get_pixel(0, 0), &Rgb([255, 0, 0]));
+
+        let transposed = orientation(90, true).apply(image).to_rgb8();
+        assert_eq!(transposed.get_pixel(0, 0), &Rgb([255, 0, 0]));
+        assert_eq!(transposed.get_pixel(0, 1), &Rgb([0, 0, 255]));
+    }
+}
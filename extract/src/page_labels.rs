@@ -0,0 +1,238 @@
+use std::collections::BTreeMap;
+
+use hayro_syntax::object::dict::keys::{P, PAGE_LABELS, S, ST};
+use hayro_syntax::object::{Dict, Name, String as PdfString};
+use hayro_syntax::xref::XRef;
+
+use crate::number_tree::number_tree_entries;
+use crate::text::decode_text_string;
+use crate::{ExtractError, open_pdf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum NumberingStyle {
+    Decimal,
+    UpperRoman,
+    LowerRoman,
+    UpperLetters,
+    LowerLetters,
+}
+
+// /PageLabelsの1つの範囲（開始ページから次の範囲の手前まで）
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct LabelRange {
+    style: Option<NumberingStyle>,
+    prefix: String,
+    start: u32,
+}
+
+pub fn page_labels(pdf_bytes: &[u8]) -> Result<Vec<String>, ExtractError> {
+    let pdf = open_pdf(pdf_bytes)?;
+    Ok(read_page_labels(pdf.xref(), pdf.pages().len()))
+}
+
+// 各ページのラベル。/PageLabelsがない、または範囲外のページは1始まりのページ番号
+pub(crate) fn read_page_labels(xref: &XRef, page_count: usize) -> Vec<String> {
+    let mut ranges = BTreeMap::new();
+    if let Some(tree) = xref
+        .get::<Dict<'_>>(xref.root_id())
+        .and_then(|catalog| catalog.get::<Dict<'_>>(PAGE_LABELS))
+    {
+        for entry in number_tree_entries(xref, &tree) {
+            let (Ok(page_index), Some(label)) =
+                (usize::try_from(entry.key), entry.value.into_dict())
+            else {
+                continue;
+            };
+            ranges.insert(page_index, label_range(&label));
+        }
+    }
+    labels_for_pages(&ranges, page_count)
+}
+
+fn label_range(label: &Dict<'_>) -> LabelRange {
+    let style = match label.get::<Name<'_>>(S).as_deref() {
+        Some(b"D") => Some(NumberingStyle::Decimal),
+        Some(b"R") => Some(NumberingStyle::UpperRoman),
+        Some(b"r") => Some(NumberingStyle::LowerRoman),
+        Some(b"A") => Some(NumberingStyle::UpperLetters),
+        Some(b"a") => Some(NumberingStyle::LowerLetters),
+        _ => None,
+    };
+    LabelRange {
+        style,
+        prefix: label
+            .get::<PdfString<'_>>(P)
+            .map(|prefix| decode_text_string(&prefix.get()))
+            .unwrap_or_default(),
+        start: label.get::<u32>(ST).filter(|start| *start > 0).unwrap_or(1),
+    }
+}
+
+fn labels_for_pages(ranges: &BTreeMap<usize, LabelRange>, page_count: usize) -> Vec<String> {
+    (0..page_count)
+        .map(|page_index| match ranges.range(..=page_index).next_back() {
+            Some((&first_page, range)) => {
+                let number = range
+                    .start
+                    .saturating_add(u32::try_from(page_index - first_page).unwrap_or(u32::MAX));
+                let mut label = range.prefix.clone();
+                if let Some(style) = range.style {
+                    label.push_str(&format_number(number, style));
+                }
+                label
+            }
+            None => (page_index + 1).to_string(),
+        })
+        .collect()
+}
+
+// ローマ数字と英字はこれより大きな番号で長くなりすぎるので、10進で書く
+const MAX_STYLED_NUMBER: u32 = 9999;
+
+fn format_number(number: u32, style: NumberingStyle) -> String {
+    if number > MAX_STYLED_NUMBER {
+        return number.to_string();
+    }
+    match style {
+        NumberingStyle::Decimal => number.to_string(),
+        NumberingStyle::UpperRoman => roman(number),
+        NumberingStyle::LowerRoman => roman(number).to_ascii_lowercase(),
+        NumberingStyle::UpperLetters => letters(number),
+        NumberingStyle::LowerLetters => letters(number).to_ascii_lowercase(),
+    }
+}
+
+// 4000以上はMを繰り返す
+fn roman(mut number: u32) -> String {
+    const NUMERALS: [(u32, &str); 13] = [
+        (1000, "M"),
+        (900, "CM"),
+        (500, "D"),
+        (400, "CD"),
+        (100, "C"),
+        (90, "XC"),
+        (50, "L"),
+        (40, "XL"),
+        (10, "X"),
+        (9, "IX"),
+        (5, "V"),
+        (4, "IV"),
+        (1, "I"),
+    ];
+    let mut out = String::new();
+    for (value, numeral) in NUMERALS {
+        while number >= value {
+            out.push_str(numeral);
+            number -= value;
+        }
+    }
+    out
+}
+
+// PDF 32000-1 12.4.2: A〜Z、AA〜ZZ、AAA〜ZZZ…と同じ文字を繰り返す
+fn letters(number: u32) -> String {
+    let index = number.saturating_sub(1);
+    let letter = char::from(b'A' + (index % 26) as u8);
+    std::iter::repeat_n(letter, (index / 26) as usize + 1).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::{
+        LabelRange, NumberingStyle, format_number, labels_for_pages, letters, page_labels, roman,
+    };
+    use crate::tests::single_page_pdf;
+
+    #[test]
+    fn formats_roman_numerals() {
+        assert_eq!(roman(4), "IV");
+        assert_eq!(roman(14), "XIV");
+        assert_eq!(roman(1999), "MCMXCIX");
+    }
+
+    #[test]
+    fn formats_letters_by_repeating() {
+        assert_eq!(letters(1), "A");
+        assert_eq!(letters(26), "Z");
+        assert_eq!(letters(27), "AA");
+        assert_eq!(letters(53), "AAA");
+    }
+
+    #[test]
+    fn falls_back_to_decimal_for_huge_start_values() {
+        assert_eq!(
+            format_number(9999, NumberingStyle::UpperRoman),
+            "MMMMMMMMMCMXCIX"
+        );
+        assert_eq!(format_number(9999, NumberingStyle::LowerLetters).len(), 385);
+        for style in [NumberingStyle::LowerRoman, NumberingStyle::UpperLetters] {
+            assert_eq!(format_number(4294967295, style), "4294967295");
+        }
+
+        let pdf = single_page_pdf(
+            "/PageLabels << /Nums [0 << /S /R /St 4294967295 >>] >>",
+            "<< >>",
+            "",
+            &[],
+        );
+        assert_eq!(page_labels(&pdf).unwrap(), ["4294967295"]);
+    }
+
+    #[test]
+    fn labels_follow_ranges() {
+        let ranges = BTreeMap::from([
+            (
+                0,
+                LabelRange {
+                    style: Some(NumberingStyle::LowerRoman),
+                    prefix: String::new(),
+                    start: 1,
+                },
+            ),
+            (
+                3,
+                LabelRange {
+                    style: Some(NumberingStyle::Decimal),
+                    prefix: String::new(),
+                    start: 1,
+                },
+            ),
+            (
+                5,
+                LabelRange {
+                    style: Some(NumberingStyle::Decimal),
+                    prefix: "A-".to_string(),
+                    start: 8,
+                },
+            ),
+            (
+                7,
+                LabelRange {
+                    style: None,
+                    prefix: "Cover".to_string(),
+                    start: 1,
+                },
+            ),
+        ]);
+        assert_eq!(
+            labels_for_pages(&ranges, 8),
+            ["i", "ii", "iii", "1", "2", "A-8", "A-9", "Cover"]
+        );
+    }
+
+    #[test]
+    fn pages_before_the_first_range_use_page_numbers() {
+        let ranges = BTreeMap::from([(
+            2,
+            LabelRange {
+                style: Some(NumberingStyle::UpperLetters),
+                prefix: String::new(),
+                start: 1,
+            },
+        )]);
+        assert_eq!(labels_for_pages(&ranges, 4), ["1", "2", "A", "B"]);
+        assert_eq!(labels_for_pages(&BTreeMap::new(), 2), ["1", "2"]);
+    }
+}
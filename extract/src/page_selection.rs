@@ -0,0 +1,546 @@
+use std::str::FromStr;
+
+use thiserror::Error;
+
+use crate::page_labels::read_page_labels;
+use crate::{ExtractError, PageRange, open_pdf};
+
+const LABEL_PREFIX: &str = "label:";
+const LAST: &str = "last";
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum PageSelectionError {
+    #[error("range token cannot be empty")]
+    EmptyToken,
+    #[error("invalid range text: {0}")]
+    InvalidToken(String),
+    #[error("invalid step in {0}: must be a positive number")]
+    InvalidStep(String),
+    #[error("unknown page label: {0}")]
+    UnknownLabel(String),
+    #[error("no pages match the page selection")]
+    NoPagesSelected,
+}
+
+// "1-3,5-,last-2-last,odd,1-20:3,!10-12,label:iv-x" のような指定。
+// ページ数やラベルに依存する部分は resolve で文書に合わせて解決する
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PageSelection {
+    items: Vec<SelectionItem>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct SelectionItem {
+    // "!"で始まる除外の指定か
+    exclude: bool,
+    pages: Pages,
+    step: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Pages {
+    // 省略された端は最初または最後のページ
+    Range {
+        start: Option<Endpoint>,
+        end: Option<Endpoint>,
+    },
+    Odd,
+    Even,
+    Label(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Endpoint {
+    // 1始まりのページ番号（0は最初のページとして扱う）
+    Number(usize),
+    // last-N（lastは0）
+    FromEnd(usize),
+}
+
+impl FromStr for PageSelection {
+    type Err = PageSelectionError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let items = input
+            .split(',')
+            .map(|token| parse_item(token.trim()))
+            .collect::<Result<_, _>>()?;
+        Ok(PageSelection { items })
+    }
+}
+
+impl PageSelection {
+    // ラベルの指定があるときだけ/PageLabelsを読めばよい
+    pub fn uses_page_labels(&self) -> bool {
+        self.items
+            .iter()
+            .any(|item| matches!(item.pages, Pages::Label(_)))
+    }
+
+    // 含める指定の和から除外の指定の和を引く。含める指定がなければ全ページから引く
+    pub fn resolve(
+        &self,
+        page_count: usize,
+        page_labels: &[String],
+    ) -> Result<Vec<PageRange>, PageSelectionError> {
+        let has_includes = self.items.iter().any(|item| !item.exclude);
+        let mut selected = vec![!has_includes; page_count];
+
+        for exclude in [false, true] {
+            for item in self.items.iter().filter(|item| item.exclude == exclude) {
+                for page_index in item.page_indexes(page_count, page_labels)? {
+                    selected[page_index] = !exclude;
+                }
+            }
+        }
+
+        let ranges = to_ranges(&selected);
+        if ranges.is_empty() {
+            // 空の範囲は全ページの意味になってしまうのでエラーにする
+            return Err(PageSelectionError::NoPagesSelected);
+        }
+        Ok(ranges)
+    }
+}
+
+// ページ数（と必要ならページラベル）を文書から読んで、0始まりの範囲にする
+pub fn resolve_page_selection(
+    pdf_bytes: &[u8],
+    selection: &PageSelection,
+) -> Result<Vec<PageRange>, ExtractError> {
+    let pdf = open_pdf(pdf_bytes)?;
+    let page_count = pdf.pages().len();
+    let page_labels = if selection.uses_page_labels() {
+        read_page_labels(pdf.xref(), page_count)
+    } else {
+        Vec::new()
+    };
+    Ok(selection.resolve(page_count, &page_labels)?)
+}
+
+fn parse_item(token: &str) -> Result<SelectionItem, PageSelectionError> {
+    if token.is_empty() {
+        return Err(PageSelectionError::EmptyToken);
+    }
+    let (exclude, body) = match token.strip_prefix('!') {
+        Some(body) => (true, body.trim()),
+        None => (false, token),
+    };
+    // ラベルにはコロンが含まれることもあるので、ラベルの刻みは解決するときに切り離す
+    if let Some(label) = body.strip_prefix(LABEL_PREFIX) {
+        let label = label.trim();
+        if label.is_empty() {
+            return Err(PageSelectionError::InvalidToken(token.to_string()));
+        }
+        return Ok(SelectionItem {
+            exclude,
+            pages: Pages::Label(label.to_string()),
+            step: 1,
+        });
+    }
+
+    let (body, step) = split_step(token, body)?;
+    let pages = match body {
+        "odd" => Pages::Odd,
+        "even" => Pages::Even,
+        _ => {
+            parse_range(body).ok_or_else(|| PageSelectionError::InvalidToken(token.to_string()))?
+        }
+    };
+    Ok(SelectionItem {
+        exclude,
+        pages,
+        step,
+    })
+}
+
+// 末尾の":N"を刻みとして切り離す
+fn split_step<'a>(token: &str, body: &'a str) -> Result<(&'a str, usize), PageSelectionError> {
+    let Some((range, raw_step)) = body.rsplit_once(':') else {
+        return Ok((body, 1));
+    };
+    match parse_step(raw_step) {
+        Some(step) => Ok((range.trim_end(), step)),
+        None => Err(PageSelectionError::InvalidStep(token.to_string())),
+    }
+}
+
+fn parse_step(input: &str) -> Option<usize> {
+    let input = input.trim();
+    if input.is_empty() || !input.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+    input.parse().ok().filter(|step| *step > 0)
+}
+
+// N、N-M、N-、-N（最後のNページ）、-last-N（端はNかlastかlast-N）
+fn parse_range(body: &str) -> Option<Pages> {
+    if let Some(raw_count) = body.strip_prefix('-')
+        && raw_count
+            .trim_start()
+            .starts_with(|ch: char| ch.is_ascii_digit())
+    {
+        let count = parse_step(raw_count)?;
+        return Some(Pages::Range {
+            start: Some(Endpoint::FromEnd(count - 1)),
+            end: Some(Endpoint::FromEnd(0)),
+        });
+    }
+    let (start, rest) = if body.starts_with('-') {
+        (None, body)
+    } else {
+        let (start, rest) = parse_endpoint_prefix(body)?;
+        (Some(start), rest.trim_start())
+    };
+
+    if rest.is_empty() {
+        return Some(Pages::Range { start, end: start });
+    }
+    let raw_end = rest.strip_prefix('-')?.trim();
+    let end = if raw_end.is_empty() {
+        None
+    } else {
+        match parse_endpoint_prefix(raw_end)? {
+            (end, "") => Some(end),
+            _ => return None,
+        }
+    };
+    // "-"だけでは何も指定していない
+    if start.is_none() && end.is_none() {
+        return None;
+    }
+    Some(Pages::Range { start, end })
+}
+
+// 先頭の端を読み、残りを返す。"last-2"は範囲ではなくlastから2つ前のページ
+fn parse_endpoint_prefix(input: &str) -> Option<(Endpoint, &str)> {
+    if let Some(rest) = input.strip_prefix(LAST) {
+        let trimmed = rest.trim_start();
+        if let Some(after_minus) = trimmed.strip_prefix('-') {
+            let after_minus = after_minus.trim_start();
+            let digits = leading_digits(after_minus);
+            if !digits.is_empty() {
+                let offset = digits.parse().ok()?;
+                return Some((Endpoint::FromEnd(offset), &after_minus[digits.len()..]));
+            }
+        }
+        return Some((Endpoint::FromEnd(0), rest));
+    }
+    let digits = leading_digits(input);
+    if digits.is_empty() {
+        return None;
+    }
+    Some((
+        Endpoint::Number(digits.parse().ok()?),
+        &input[digits.len()..],
+    ))
+}
+
+fn leading_digits(input: &str) -> &str {
+    let end = input
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(input.len());
+    &input[..end]
+}
+
+impl SelectionItem {
+    fn page_indexes(
+        &self,
+        page_count: usize,
+        page_labels: &[String],
+    ) -> Result<Vec<usize>, PageSelectionError> {
+        if page_count == 0 {
+            return Ok(Vec::new());
+        }
+        let last = page_count as i64;
+        let (start, end, step) = match &self.pages {
+            Pages::Range {
+                start: Some(start),
+                end: Some(end),
+            } => {
+                let start = resolve_endpoint(*start, last);
+                let end = resolve_endpoint(*end, last);
+                // 逆順の範囲は並べ替える
+                (start.min(end), start.max(end), self.step)
+            }
+            // 片側が省略された"7-"は、最後のページより後から始まるなら何も選ばない
+            Pages::Range { start, end } => (
+                start.map_or(1, |start| resolve_endpoint(start, last)),
+                end.map_or(last, |end| resolve_endpoint(end, last)),
+                self.step,
+            ),
+            Pages::Odd => (1, last, self.step.saturating_mul(2)),
+            Pages::Even => (2, last, self.step.saturating_mul(2)),
+            Pages::Label(label) => {
+                let (start, end, step) = resolve_label_with_step(label, page_labels)
+                    .ok_or_else(|| PageSelectionError::UnknownLabel(label.clone()))?;
+                (start as i64 + 1, end as i64 + 1, step)
+            }
+        };
+
+        let step = i64::try_from(step).unwrap_or(i64::MAX);
+        // 文書の外にはみ出した部分は無視する
+        let first = if start < 1 {
+            let steps = (1 - start).unsigned_abs().div_ceil(step.unsigned_abs());
+            start.saturating_add(
+                i64::try_from(steps)
+                    .unwrap_or(i64::MAX)
+                    .saturating_mul(step),
+            )
+        } else {
+            start
+        };
+        let mut pages = Vec::new();
+        let mut page = first;
+        while page <= end.min(last) {
+            pages.push((page - 1) as usize);
+            page = page.saturating_add(step);
+        }
+        Ok(pages)
+    }
+}
+
+fn resolve_endpoint(endpoint: Endpoint, last: i64) -> i64 {
+    match endpoint {
+        Endpoint::Number(number) => i64::try_from(number).unwrap_or(i64::MAX).max(1),
+        Endpoint::FromEnd(offset) => last.saturating_sub(i64::try_from(offset).unwrap_or(i64::MAX)),
+    }
+}
+
+// "C:1"のようなラベルがなければ、末尾の":N"を刻みとみなす
+fn resolve_label_with_step(input: &str, page_labels: &[String]) -> Option<(usize, usize, usize)> {
+    if let Some((start, end)) = resolve_label_range(input, page_labels) {
+        return Some((start, end, 1));
+    }
+    let (label, raw_step) = input.rsplit_once(':')?;
+    let step = parse_step(raw_step)?;
+    let (start, end) = resolve_label_range(label.trim(), page_labels)?;
+    Some((start, end, step))
+}
+
+// ラベル自体に"-"を含むこと（A-1など）があるので、まず全体を1つのラベルとして探し、
+// 見つからなければ"-"で区切れる位置を前から試す
+fn resolve_label_range(input: &str, page_labels: &[String]) -> Option<(usize, usize)> {
+    if let Some(page_index) = find_label(page_labels, input, 0) {
+        return Some((page_index, page_index));
+    }
+
+    input.match_indices('-').find_map(|(split, _)| {
+        let start = find_label(page_labels, input[..split].trim(), 0)?;
+        // 同じラベルが複数の節にある場合は開始ページ以降で最初のものを終わりとする
+        let end = find_label(page_labels, input[split + 1..].trim(), start)?;
+        Some((start, end))
+    })
+}
+
+fn find_label(page_labels: &[String], label: &str, from: usize) -> Option<usize> {
+    if label.is_empty() {
+        return None;
+    }
+    page_labels
+        .iter()
+        .skip(from)
+        .position(|page_label| page_label == label)
+        .map(|position| position + from)
+}
+
+fn to_ranges(selected: &[bool]) -> Vec<PageRange> {
+    let mut ranges: Vec<PageRange> = Vec::new();
+    for (page_index, _) in selected
+        .iter()
+        .enumerate()
+        .filter(|(_, selected)| **selected)
+    {
+        match ranges.last_mut() {
+            Some(range) if range.end + 1 == page_index => range.end = page_index,
+            _ => ranges.push(PageRange {
+                start: page_index,
+                end: page_index,
+            }),
+        }
+    }
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PageSelection, PageSelectionError};
+    use crate::PageRange;
+
+    fn resolve(input: &str, page_count: usize) -> Result<Vec<PageRange>, PageSelectionError> {
+        input
+            .parse::<PageSelection>()?
+            .resolve(page_count, &labels(&[]))
+    }
+
+    // 0始まりのページ番号の一覧にする
+    fn pages(input: &str, page_count: usize) -> Vec<usize> {
+        resolve(input, page_count)
+            .unwrap()
+            .into_iter()
+            .flat_map(|range| range.start..=range.end)
+            .collect()
+    }
+
+    fn labels(labels: &[&str]) -> Vec<String> {
+        labels.iter().map(|label| label.to_string()).collect()
+    }
+
+    fn range(start: usize, end: usize) -> PageRange {
+        PageRange { start, end }
+    }
+
+    #[test]
+    fn parses_single_pages_and_ranges() {
+        assert_eq!(resolve("1", 10).unwrap(), vec![range(0, 0)]);
+        assert_eq!(
+            resolve("1, 3-5", 10).unwrap(),
+            vec![range(0, 0), range(2, 4)]
+        );
+        assert_eq!(resolve("5-3", 10).unwrap(), vec![range(2, 4)]);
+        assert_eq!(resolve("0", 10).unwrap(), vec![range(0, 0)]);
+    }
+
+    #[test]
+    fn merges_overlapping_ranges_in_page_order() {
+        assert_eq!(
+            resolve("4-6,1,5-8", 10).unwrap(),
+            vec![range(0, 0), range(3, 7)]
+        );
+    }
+
+    #[test]
+    fn parses_open_ended_ranges() {
+        assert_eq!(resolve("8-", 10).unwrap(), vec![range(7, 9)]);
+        assert_eq!(resolve("-last-7", 10).unwrap(), vec![range(0, 2)]);
+        assert_eq!(resolve("last-0", 10).unwrap(), vec![range(9, 9)]);
+    }
+
+    #[test]
+    fn counts_from_the_end() {
+        assert_eq!(resolve("last", 10).unwrap(), vec![range(9, 9)]);
+        assert_eq!(resolve("-1", 10).unwrap(), vec![range(9, 9)]);
+        assert_eq!(resolve("-3", 10).unwrap(), vec![range(7, 9)]);
+        assert_eq!(resolve("-20", 10).unwrap(), vec![range(0, 9)]);
+        assert_eq!(resolve("last-2", 10).unwrap(), vec![range(7, 7)]);
+        assert_eq!(resolve("last-2-last", 10).unwrap(), vec![range(7, 9)]);
+        assert_eq!(resolve("5 - last - 1", 10).unwrap(), vec![range(4, 8)]);
+        assert_eq!(resolve("last-20-2", 10).unwrap(), vec![range(0, 1)]);
+    }
+
+    #[test]
+    fn selects_by_parity_and_step() {
+        assert_eq!(pages("odd", 6), [0, 2, 4]);
+        assert_eq!(pages("even", 6), [1, 3, 5]);
+        assert_eq!(pages("1-10:3", 20), [0, 3, 6, 9]);
+        assert_eq!(pages("2-:5", 12), [1, 6, 11]);
+        assert_eq!(pages("odd:2", 10), [0, 4, 8]);
+    }
+
+    #[test]
+    fn applies_exclusions() {
+        assert_eq!(pages("1-8,!3-5", 10), [0, 1, 5, 6, 7]);
+        assert_eq!(pages("!1-8", 10), [8, 9]);
+        assert_eq!(pages("!odd,!last", 6), [1, 3]);
+    }
+
+    #[test]
+    fn ignores_pages_outside_the_document() {
+        assert_eq!(resolve("3-20", 5).unwrap(), vec![range(2, 4)]);
+        assert_eq!(resolve("2,9", 5).unwrap(), vec![range(1, 1)]);
+        assert_eq!(resolve("9", 5), Err(PageSelectionError::NoPagesSelected));
+        assert_eq!(resolve("7-", 5), Err(PageSelectionError::NoPagesSelected));
+        assert_eq!(resolve("9-7", 5), Err(PageSelectionError::NoPagesSelected));
+        assert_eq!(
+            resolve("last-9", 5),
+            Err(PageSelectionError::NoPagesSelected)
+        );
+        assert_eq!(resolve("!1-5", 5), Err(PageSelectionError::NoPagesSelected));
+    }
+
+    #[test]
+    fn rejects_invalid_text() {
+        assert_eq!(resolve("1,,2", 5), Err(PageSelectionError::EmptyToken));
+        assert!(matches!(
+            resolve("x-2", 5),
+            Err(PageSelectionError::InvalidToken(_))
+        ));
+        assert!(matches!(
+            resolve("-", 5),
+            Err(PageSelectionError::InvalidToken(_))
+        ));
+        assert!(matches!(
+            resolve("-0", 5),
+            Err(PageSelectionError::InvalidToken(_))
+        ));
+        assert!(matches!(
+            resolve("1-2-3", 5),
+            Err(PageSelectionError::InvalidToken(_))
+        ));
+        assert!(matches!(
+            resolve("1-10:0", 5),
+            Err(PageSelectionError::InvalidStep(_))
+        ));
+        assert!(matches!(
+            resolve("1-10:x", 5),
+            Err(PageSelectionError::InvalidStep(_))
+        ));
+    }
+
+    #[test]
+    fn resolves_label_ranges() {
+        let book = labels(&["i", "ii", "iii", "iv", "1", "2", "3"]);
+        let selection: PageSelection = "label:ii-iv, label:2, 1".parse().unwrap();
+        assert!(selection.uses_page_labels());
+        assert_eq!(
+            selection.resolve(7, &book).unwrap(),
+            vec![range(0, 3), range(5, 5)]
+        );
+        assert!(!"1-3".parse::<PageSelection>().unwrap().uses_page_labels());
+
+        let unknown: PageSelection = "label:x".parse().unwrap();
+        assert_eq!(
+            unknown.resolve(7, &book),
+            Err(PageSelectionError::UnknownLabel("x".to_string()))
+        );
+        let backwards: PageSelection = "label:3-1".parse().unwrap();
+        assert!(backwards.resolve(7, &book).is_err());
+    }
+
+    #[test]
+    fn labels_may_contain_hyphens_and_colons() {
+        let appendix = labels(&["A-1", "A-2", "A-3", "B-1", "C:1"]);
+        let resolve_labels = |input: &str| {
+            input
+                .parse::<PageSelection>()
+                .unwrap()
+                .resolve(5, &appendix)
+                .unwrap()
+        };
+        assert_eq!(resolve_labels("label:A-2"), vec![range(1, 1)]);
+        assert_eq!(resolve_labels("label:A-1-B-1"), vec![range(0, 3)]);
+        assert_eq!(
+            resolve_labels("label:A-1-B-1:2"),
+            vec![range(0, 0), range(2, 2)]
+        );
+        assert_eq!(resolve_labels("label:C:1"), vec![range(4, 4)]);
+        assert_eq!(
+            resolve_labels("1-5,!label:A-2"),
+            vec![range(0, 0), range(2, 4)]
+        );
+    }
+
+    #[test]
+    fn repeated_labels_resolve_after_the_start() {
+        let sections = labels(&["1", "2", "1", "2", "3"]);
+        let resolve_labels = |input: &str| {
+            input
+                .parse::<PageSelection>()
+                .unwrap()
+                .resolve(5, &sections)
+                .unwrap()
+        };
+        assert_eq!(resolve_labels("label:1"), vec![range(0, 0)]);
+        assert_eq!(resolve_labels("label:2-3"), vec![range(1, 4)]);
+    }
+}
@@ -0,0 +1,327 @@
+use hayro_interpret::{ClipPath, FillRule};
+use image::{DynamicImage, Rgba, RgbaImage};
+use kurbo::{Affine, BezPath, PathEl, Point, Rect, Shape};
+
+// 回転・反転とみなす係数の許容誤差
+const AXIS_EPSILON: f64 = 1e-6;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ImageBounds {
+    pub x0: f32,
+    pub y0: f32,
+    pub x1: f32,
+    pub y1: f32,
+}
+
+impl ImageBounds {
+    // 画像のピクセル座標（width x height）からページ座標への変換で、画像全体が収まる矩形
+    pub(crate) fn from_transform(transform: Affine, width: u32, height: u32) -> Self {
+        let rect =
+            transform.transform_rect_bbox(Rect::new(0.0, 0.0, f64::from(width), f64::from(height)));
+        Self {
+            x0: rect.x0 as f32,
+            y0: rect.y0 as f32,
+            x1: rect.x1 as f32,
+            y1: rect.y1 as f32,
+        }
+    }
+
+    pub fn width(&self) -> f32 {
+        self.x1 - self.x0
+    }
+
+    pub fn height(&self) -> f32 {
+        self.y1 - self.y0
+    }
+}
+
+pub(crate) struct Clip {
+    path: BezPath,
+    fill: FillRule,
+    // 軸に平行な矩形ならピクセルごとの内外判定を省ける
+    rect: Option<Rect>,
+}
+
+impl Clip {
+    pub(crate) fn from_rect(rect: Rect) -> Self {
+        Self {
+            path: rect.to_path(0.1),
+            fill: FillRule::NonZero,
+            rect: Some(rect),
+        }
+    }
+
+    pub(crate) fn from_clip_path(clip_path: &ClipPath) -> Self {
+        Self {
+            path: clip_path.path.clone(),
+            fill: clip_path.fill,
+            rect: path_as_rect(&clip_path.path),
+        }
+    }
+
+    fn contains(&self, point: Point) -> bool {
+        let winding = self.path.winding(point);
+        match self.fill {
+            FillRule::NonZero => winding != 0,
+            FillRule::EvenOdd => winding % 2 != 0,
+        }
+    }
+}
+
+// 見た目どおりに切り出すときの、ページ上で見えている範囲と出力の大きさ
+#[derive(Clone, Copy)]
+pub(crate) struct DisplayedArea {
+    visible: Rect,
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+}
+
+// 画像のピクセル座標からページ座標への変換とクリップから、切り出す範囲を求める（完全に隠れているときはNone）。
+// せん断などで出力は元の画像よりずっと大きくなりうるので、確保する前に大きさを確かめられるよう分けてある
+pub(crate) fn displayed_area(
+    width: u32,
+    height: u32,
+    transform: Affine,
+    clips: &[Clip],
+) -> Option<DisplayedArea> {
+    if width == 0 || height == 0 {
+        return None;
+    }
+    let image_rect = Rect::new(0.0, 0.0, f64::from(width), f64::from(height));
+    let mut visible = transform.transform_rect_bbox(image_rect);
+    for clip in clips {
+        visible = visible.intersect(clip.path.bounding_box());
+    }
+    if visible.width() <= 0.0 || visible.height() <= 0.0 {
+        return None;
+    }
+
+    let (scale_x, scale_y) = pixels_per_unit(transform)?;
+    Some(DisplayedArea {
+        visible,
+        width: pixel_length(visible.width() * scale_x)?,
+        height: pixel_length(visible.height() * scale_y)?,
+    })
+}
+
+// クリップで見えている部分だけを元の解像度のまま切り出す（見えるピクセルがなければNone）
+pub(crate) fn crop_to_displayed(
+    image: &DynamicImage,
+    transform: Affine,
+    clips: &[Clip],
+    area: &DisplayedArea,
+) -> Option<DynamicImage> {
+    let (width, height) = (image.width(), image.height());
+    let image_rect = Rect::new(0.0, 0.0, f64::from(width), f64::from(height));
+    let DisplayedArea {
+        visible,
+        width: out_width,
+        height: out_height,
+    } = *area;
+
+    let inverse = transform.inverse();
+    let shaped_clips: Vec<&Clip> = clips.iter().filter(|clip| clip.rect.is_none()).collect();
+    let source = image.to_rgba8();
+    let mut output = RgbaImage::new(out_width, out_height);
+    let mut any_visible = false;
+
+    for (x, y, pixel) in output.enumerate_pixels_mut() {
+        // 出力の上端がページ上の上側になるようにy軸を反転する
+        let page_point = Point::new(
+            visible.x0 + (f64::from(x) + 0.5) * visible.width() / f64::from(out_width),
+            visible.y1 - (f64::from(y) + 0.5) * visible.height() / f64::from(out_height),
+        );
+        if !shaped_clips.iter().all(|clip| clip.contains(page_point)) {
+            continue;
+        }
+        let source_point = inverse * page_point;
+        if !image_rect.contains(source_point) {
+            continue;
+        }
+        let source_x = (source_point.x.floor() as u32).min(width - 1);
+        let source_y = (source_point.y.floor() as u32).min(height - 1);
+        *pixel = *source.get_pixel(source_x, source_y);
+        any_visible = true;
+    }
+
+    if !any_visible {
+        return None;
+    }
+    Some(shrink_to_source_color(image, output))
+}
+
+// 元画像1ピクセルがページ上で占める大きさから、出力の解像度を決める
+fn pixels_per_unit(transform: Affine) -> Option<(f64, f64)> {
+    let [a, b, c, d, _, _] = transform.as_coeffs();
+    let det = a * d - b * c;
+    if det.abs() < f64::EPSILON {
+        return None;
+    }
+
+    let scale = a.abs().max(b.abs()).max(c.abs()).max(d.abs());
+    let is_zero = |value: f64| value.abs() <= scale * AXIS_EPSILON;
+    if is_zero(b) && is_zero(c) {
+        Some((1.0 / a.abs(), 1.0 / d.abs()))
+    } else if is_zero(a) && is_zero(d) {
+        Some((1.0 / c.abs(), 1.0 / b.abs()))
+    } else {
+        // 任意角の回転やせん断は面積が保たれるように等方的に拡大する
+        let uniform = (1.0 / det.abs()).sqrt();
+        Some((uniform, uniform))
+    }
+}
+
+fn pixel_length(length: f64) -> Option<u32> {
+    let rounded = length.round().max(1.0);
+    if rounded.is_finite() && rounded <= f64::from(u32::MAX) {
+        Some(rounded as u32)
+    } else {
+        None
+    }
+}
+
+// 切り出しで透明な部分が生じなかったときは元の色形式に揃える
+fn shrink_to_source_color(source: &DynamicImage, output: RgbaImage) -> DynamicImage {
+    let opaque = output
+        .pixels()
+        .all(|Rgba([_, _, _, alpha])| *alpha == u8::MAX);
+    let output = DynamicImage::ImageRgba8(output);
+    if opaque && !source.color().has_alpha() {
+        DynamicImage::ImageRgb8(output.to_rgb8())
+    } else {
+        output
+    }
+}
+
+fn path_as_rect(path: &BezPath) -> Option<Rect> {
+    let mut points = Vec::with_capacity(5);
+    for element in path.elements() {
+        match element {
+            PathEl::MoveTo(point) | PathEl::LineTo(point) => points.push(*point),
+            PathEl::ClosePath => {}
+            PathEl::QuadTo(..) | PathEl::CurveTo(..) => return None,
+        }
+    }
+    if points.len() == 5 && points[0] == points[4] {
+        points.pop();
+    }
+    if points.len() != 4 {
+        return None;
+    }
+
+    let is_axis_aligned = (0..4).all(|i| {
+        let (from, to) = (points[i], points[(i + 1) % 4]);
+        from.x == to.x || from.y == to.y
+    });
+    is_axis_aligned.then(|| path.bounding_box())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Clip, crop_to_displayed, displayed_area, path_as_rect, pixels_per_unit};
+    use image::{DynamicImage, Rgb, RgbImage};
+    use kurbo::{Affine, BezPath, Circle, Rect, Shape};
+
+    fn crop(image: &DynamicImage, transform: Affine, clips: &[Clip]) -> Option<DynamicImage> {
+        let area = displayed_area(image.width(), image.height(), transform, clips)?;
+        crop_to_displayed(image, transform, clips, &area)
+    }
+
+    // 左上が赤、それ以外が青の2x2画像
+    fn marker_image() -> DynamicImage {
+        let mut image = RgbImage::from_pixel(2, 2, Rgb([0, 0, 255]));
+        image.put_pixel(0, 0, Rgb([255, 0, 0]));
+        DynamicImage::ImageRgb8(image)
+    }
+
+    // hayroと同じく、画像のピクセル座標を上下反転して配置する
+    fn placement(ctm: Affine) -> Affine {
+        ctm * Affine::new([0.5, 0.0, 0.0, -0.5, 0.0, 1.0])
+    }
+
+    #[test]
+    fn upright_placement_keeps_pixels() {
+        let output = crop(&marker_image(), placement(Affine::scale(20.0)), &[])
+            .unwrap()
+            .to_rgb8();
+        assert_eq!(output.dimensions(), (2, 2));
+        assert_eq!(output.get_pixel(0, 0), &Rgb([255, 0, 0]));
+    }
+
+    #[test]
+    fn mirrored_placement_is_flipped() {
+        let ctm = Affine::new([-20.0, 0.0, 0.0, 20.0, 20.0, 0.0]);
+        let output = crop(&marker_image(), placement(ctm), &[])
+            .unwrap()
+            .to_rgb8();
+        assert_eq!(output.get_pixel(1, 0), &Rgb([255, 0, 0]));
+        assert_eq!(output.get_pixel(0, 0), &Rgb([0, 0, 255]));
+    }
+
+    #[test]
+    fn rotated_placement_is_rotated() {
+        // 反時計回りに90度
+        let ctm = Affine::new([0.0, 20.0, -20.0, 0.0, 20.0, 0.0]);
+        let output = crop(&marker_image(), placement(ctm), &[])
+            .unwrap()
+            .to_rgb8();
+        assert_eq!(output.dimensions(), (2, 2));
+        assert_eq!(output.get_pixel(0, 1), &Rgb([255, 0, 0]));
+    }
+
+    #[test]
+    fn rect_clip_crops_at_native_resolution() {
+        let image = DynamicImage::ImageRgb8(RgbImage::from_pixel(100, 50, Rgb([9, 9, 9])));
+        let transform = Affine::scale(2.0) * Affine::new([1.0, 0.0, 0.0, -1.0, 0.0, 50.0]);
+        let clip = Clip::from_rect(Rect::new(0.0, 0.0, 100.0, 40.0));
+        let output = crop(&image, transform, &[clip]).unwrap();
+        assert_eq!((output.width(), output.height()), (50, 20));
+        assert!(output.as_rgb8().is_some());
+    }
+
+    #[test]
+    fn shaped_clip_adds_transparency() {
+        let image = DynamicImage::ImageRgb8(RgbImage::from_pixel(20, 20, Rgb([9, 9, 9])));
+        let transform = Affine::new([1.0, 0.0, 0.0, -1.0, 0.0, 20.0]);
+        let clip = Clip {
+            path: Circle::new((10.0, 10.0), 8.0).to_path(0.1),
+            fill: hayro_interpret::FillRule::NonZero,
+            rect: None,
+        };
+        let output = crop(&image, transform, &[clip]).unwrap().to_rgba8();
+        assert_eq!(output.get_pixel(0, 0)[3], 0);
+        assert_eq!(output.get_pixel(8, 8)[3], 255);
+    }
+
+    #[test]
+    fn fully_clipped_image_is_hidden() {
+        let clip = Clip::from_rect(Rect::new(100.0, 100.0, 120.0, 120.0));
+        assert!(crop(&marker_image(), placement(Affine::scale(20.0)), &[clip]).is_none());
+    }
+
+    #[test]
+    fn sheared_placement_can_need_a_larger_output() {
+        // 100x1の画像を、ほぼ潰れるほどせん断して100x100の範囲に描く
+        let transform = Affine::new([1.0, 1.0, 0.0, 1e-6, 0.0, 0.0]);
+        let area = displayed_area(100, 1, transform, &[]).unwrap();
+        assert_eq!((area.width, area.height), (100_000, 100_000));
+    }
+
+    #[test]
+    fn degenerate_transform_has_no_resolution() {
+        assert_eq!(pixels_per_unit(Affine::scale_non_uniform(1.0, 0.0)), None);
+    }
+
+    #[test]
+    fn detects_axis_aligned_rect_paths() {
+        let rect = Rect::new(1.0, 2.0, 3.0, 4.0);
+        assert_eq!(path_as_rect(&rect.to_path(0.1)), Some(rect));
+
+        let mut triangle = BezPath::new();
+        triangle.move_to((0.0, 0.0));
+        triangle.line_to((1.0, 0.0));
+        triangle.line_to((0.0, 1.0));
+        triangle.close_path();
+        assert_eq!(path_as_rect(&triangle), None);
+    }
+}
@@ -0,0 +1,261 @@
+use hayro::vello_cpu::color::AlphaColor;
+use hayro::{RenderSettings, render};
+use hayro_interpret::InterpreterSettings;
+use image::{DynamicImage, RgbaImage};
+use thiserror::Error;
+
+use crate::limits::pixel_count;
+use crate::{
+    CancellationToken, ExtractError, ExtractImageWarning, ExtractImageWarningKind, ExtractLimits,
+    ExtractProgress, PageRange, catch_quietly, open_pdf, panic_message, select_pages_within_limit,
+};
+
+// PDFの座標系は1/72インチ単位
+const POINTS_PER_INCH: f32 = 72.0;
+
+#[derive(Debug, Clone)]
+pub struct RenderOptions {
+    pub dpi: f32,
+    // RGBA（[0, 0, 0, 0]で透明）
+    pub background: [u8; 4],
+    pub limits: ExtractLimits,
+    pub cancellation: CancellationToken,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            dpi: 150.0,
+            background: [255, 255, 255, 255],
+            limits: ExtractLimits::default(),
+            cancellation: CancellationToken::default(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct RenderedPage {
+    pub page_index: usize,
+    pub image: DynamicImage,
+}
+
+#[derive(Debug)]
+pub struct RenderPagesResult {
+    pub pages: Vec<RenderedPage>,
+    pub errors: Vec<ExtractImageWarning>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error(
+    "invalid background color \"{0}\": use white, black, transparent, #rgb, #rrggbb or #rrggbbaa"
+)]
+pub struct InvalidBackgroundColor(String);
+
+pub fn render_pages(
+    pdf_bytes: &[u8],
+    page_ranges: &[PageRange],
+    options: &RenderOptions,
+) -> Result<RenderPagesResult, ExtractError> {
+    render_pages_with_progress(pdf_bytes, page_ranges, options, |_| {})
+}
+
+pub fn render_pages_with_progress(
+    pdf_bytes: &[u8],
+    page_ranges: &[PageRange],
+    options: &RenderOptions,
+    mut on_progress: impl FnMut(ExtractProgress),
+) -> Result<RenderPagesResult, ExtractError> {
+    if !options.dpi.is_finite() || options.dpi <= 0.0 {
+        return Err(ExtractError::InvalidDpi(options.dpi));
+    }
+
+    let pdf = open_pdf(pdf_bytes)?;
+    let pages = pdf.pages();
+    let (selected_pages, selected_count) =
+        select_pages_within_limit(pages.len(), page_ranges, &options.limits)?;
+
+    let scale = options.dpi / POINTS_PER_INCH;
+    let [r, g, b, a] = options.background;
+    let render_settings = RenderSettings {
+        x_scale: scale,
+        y_scale: scale,
+        bg_color: AlphaColor::from_rgba8(r, g, b, a),
+        ..Default::default()
+    };
+    let interpreter_settings = InterpreterSettings::default();
+
+    let mut result = RenderPagesResult {
+        pages: Vec::new(),
+        errors: Vec::new(),
+    };
+    let mut decoded_bytes: u64 = 0;
+    let mut progress = ExtractProgress {
+        pages_done: 0,
+        total_pages: selected_count,
+        images_found: 0,
+    };
+    on_progress(progress);
+
+    for (i, page) in pages.iter().enumerate() {
+        if !selected_pages[i] {
+            continue;
+        }
+        if options.cancellation.is_cancelled() {
+            return Err(ExtractError::Cancelled);
+        }
+
+        let (width, height) = page.render_dimensions();
+        let (width, height) = (
+            pixel_length(width, options.dpi),
+            pixel_length(height, options.dpi),
+        );
+        let page_warning = |kind| ExtractImageWarning {
+            page_index: i,
+            image_index: None,
+            kind,
+        };
+
+        // hayroのピクセルマップは一辺がu16までしか扱えない
+        let too_large = ExtractImageWarningKind::PageTooLarge { width, height };
+        if width > u32::from(u16::MAX)
+            || height > u32::from(u16::MAX)
+            || options
+                .limits
+                .check_image_dimensions(width, height)
+                .is_err()
+        {
+            result.errors.push(page_warning(too_large));
+        } else {
+            let total = decoded_bytes.saturating_add(pixel_count(width, height) * 4);
+            if let Err(kind) = options.limits.check_decoded_bytes(total) {
+                result.errors.push(page_warning(kind));
+                break;
+            }
+
+            let rendered = catch_quietly(|| {
+                render(
+                    page,
+                    &interpreter_settings,
+                    &RenderSettings {
+                        width: Some(width as u16),
+                        height: Some(height as u16),
+                        ..render_settings
+                    },
+                )
+            });
+            match rendered {
+                Ok(pixmap) => {
+                    let rgba = pixmap
+                        .take_unpremultiplied()
+                        .into_iter()
+                        .flat_map(|pixel| [pixel.r, pixel.g, pixel.b, pixel.a])
+                        .collect();
+                    match RgbaImage::from_raw(width, height, rgba) {
+                        Some(image) => {
+                            decoded_bytes = total;
+                            result.pages.push(RenderedPage {
+                                page_index: i,
+                                image: DynamicImage::ImageRgba8(image),
+                            });
+                        }
+                        None => result.errors.push(page_warning(
+                            ExtractImageWarningKind::InvalidRgbaBufferShape,
+                        )),
+                    }
+                }
+                Err(payload) => {
+                    result
+                        .errors
+                        .push(page_warning(ExtractImageWarningKind::PageInterpretFailed {
+                            message: panic_message(payload.as_ref()),
+                        }))
+                }
+            }
+        }
+
+        progress.pages_done += 1;
+        progress.images_found = result.pages.len();
+        on_progress(progress);
+    }
+
+    if options.cancellation.is_cancelled() {
+        return Err(ExtractError::Cancelled);
+    }
+
+    Ok(result)
+}
+
+pub fn parse_background_color(input: &str) -> Result<[u8; 4], InvalidBackgroundColor> {
+    let invalid = || InvalidBackgroundColor(input.to_string());
+    let trimmed = input.trim();
+
+    match trimmed.to_ascii_lowercase().as_str() {
+        "white" => return Ok([255, 255, 255, 255]),
+        "black" => return Ok([0, 0, 0, 255]),
+        "transparent" => return Ok([0, 0, 0, 0]),
+        _ => {}
+    }
+
+    let hex = trimmed.strip_prefix('#').ok_or_else(invalid)?;
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(invalid());
+    }
+    let channel = |digits: &str| u8::from_str_radix(digits, 16).map_err(|_| invalid());
+    match hex.len() {
+        3 => {
+            let mut color = [255; 4];
+            for (slot, index) in color.iter_mut().zip(0..3) {
+                let value = channel(&hex[index..index + 1])?;
+                *slot = value * 17;
+            }
+            Ok(color)
+        }
+        6 | 8 => {
+            let mut color = [255; 4];
+            for (slot, index) in color.iter_mut().zip((0..hex.len()).step_by(2)) {
+                *slot = channel(&hex[index..index + 2])?;
+            }
+            Ok(color)
+        }
+        _ => Err(invalid()),
+    }
+}
+
+// 小数点以下は切り捨てるが、極端に小さいページでも1ピクセルは確保する
+fn pixel_length(points: f32, dpi: f32) -> u32 {
+    ((points * dpi / POINTS_PER_INCH).floor() as u32).max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_background_color, pixel_length};
+
+    #[test]
+    fn parses_named_background_colors() {
+        assert_eq!(parse_background_color("white"), Ok([255, 255, 255, 255]));
+        assert_eq!(parse_background_color("Transparent"), Ok([0, 0, 0, 0]));
+    }
+
+    #[test]
+    fn parses_hex_background_colors() {
+        assert_eq!(parse_background_color("#f80"), Ok([255, 136, 0, 255]));
+        assert_eq!(parse_background_color("#102030"), Ok([16, 32, 48, 255]));
+        assert_eq!(parse_background_color("#10203080"), Ok([16, 32, 48, 128]));
+    }
+
+    #[test]
+    fn rejects_invalid_background_colors() {
+        assert!(parse_background_color("red").is_err());
+        assert!(parse_background_color("#12345").is_err());
+        assert!(parse_background_color("#gg0000").is_err());
+        assert!(parse_background_color("#ééé").is_err());
+        assert!(parse_background_color("#+10203").is_err());
+    }
+
+    #[test]
+    fn letter_page_at_150_dpi() {
+        assert_eq!(pixel_length(612.0, 150.0), 1275);
+        assert_eq!(pixel_length(792.0, 150.0), 1650);
+        assert_eq!(pixel_length(0.1, 150.0), 1);
+    }
+}
@@ -0,0 +1,481 @@
+use std::collections::BTreeMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+use crate::{
+    ExtractError, ExtractImageWarning, ExtractImageWarningKind, ExtractOptions, ExtractedImage,
+    ObjectRef, PageRange, extract_images_with_options, open_pdf,
+};
+
+// 増分更新で追記された版。indexは古い順に0から
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Revision {
+    pub index: usize,
+    // ファイル先頭からこの版の%%EOFまでの長さ。先頭からこの長さを切り出すとこの版の文書になる
+    pub byte_length: usize,
+}
+
+#[derive(Debug)]
+pub struct RevisionImages {
+    pub revision: Revision,
+    // この版で描かれる画像の数
+    pub image_count: usize,
+    // 最初の版ではすべての画像、以降の版では1つ前の版から追加・変更された画像だけ
+    pub images: Vec<ExtractedImage>,
+    pub errors: Vec<ExtractImageWarning>,
+    // 1つ前の版との違い（最初の版では空）
+    pub changes: Vec<ImageChange>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageChange {
+    pub kind: ImageChangeKind,
+    // 画像XObjectの参照（インライン画像ならNone）
+    pub object: Option<ObjectRef>,
+    // 画像が描かれるページ（Removedなら前の版のページ）
+    pub pages: Vec<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageChangeKind {
+    Added,
+    Removed,
+    // 同じオブジェクト番号の画像の画素が変わった
+    Modified,
+}
+
+#[derive(Debug)]
+pub struct ExtractRevisionsResult {
+    pub revisions: Vec<RevisionImages>,
+}
+
+// 版が多い文書でも処理が終わるよう、新しいほうからこの数の版だけを調べる
+const MAX_REVISIONS: usize = 64;
+
+// 相互参照セクションごとの版を古い順に返す。増分更新されていなければファイル全体の1版だけ
+pub fn list_revisions(pdf_bytes: &[u8]) -> Vec<Revision> {
+    let mut ends = find_revision_ends(pdf_bytes);
+    // 線形化ファイルの先頭ページ用のセクションは版ではない
+    if ends.len() > 1 && is_linearized(pdf_bytes) {
+        ends.remove(0);
+    }
+    // 最後の%%EOFの後に続くごみは最新版に含める
+    match ends.last_mut() {
+        Some(last) => *last = pdf_bytes.len(),
+        None => ends.push(pdf_bytes.len()),
+    }
+    // 版ごとに先頭から開き直すので、開いて確かめる前に数を絞る
+    let skipped = ends.len().saturating_sub(MAX_REVISIONS);
+    ends.drain(..skipped);
+    // 壊れた途中の版は開けないので除く
+    let mut revisions = Vec::new();
+    for (i, &byte_length) in ends.iter().enumerate() {
+        if i + 1 < ends.len() && open_pdf(&pdf_bytes[..byte_length]).is_err() {
+            continue;
+        }
+        revisions.push(Revision {
+            index: revisions.len(),
+            byte_length,
+        });
+    }
+    revisions
+}
+
+// 版ごとに画像を取り出し、前の版と比べる。画像の数とデコード後のバイト数の上限は
+// すべての版でデコードした画像を合わせて数え、前の版から変わらない画像は画素を残さず
+// フィンガープリントで比べる
+pub fn extract_revisions(
+    pdf_bytes: &[u8],
+    page_ranges: &[PageRange],
+    options: &ExtractOptions,
+) -> Result<ExtractRevisionsResult, ExtractError> {
+    let mut revisions: Vec<RevisionImages> = Vec::new();
+    let mut previous: Option<BTreeMap<ImageKey, ImageSummary>> = None;
+    let mut image_count = 0usize;
+    let mut decoded_bytes = 0u64;
+    for revision in list_revisions(pdf_bytes) {
+        if options.cancellation.is_cancelled() {
+            return Err(ExtractError::Cancelled);
+        }
+        let mut revision_options = options.clone();
+        let limits = &mut revision_options.limits;
+        limits.max_images = limits
+            .max_images
+            .map(|limit| limit.saturating_sub(image_count));
+        limits.max_total_decoded_bytes = limits
+            .max_total_decoded_bytes
+            .map(|limit| limit.saturating_sub(decoded_bytes));
+        let result = extract_images_with_options(
+            &pdf_bytes[..revision.byte_length],
+            page_ranges,
+            &revision_options,
+        )?;
+        let fingerprints: Vec<u64> = result.images.iter().map(image_fingerprint).collect();
+        let current = summarize_images(&result.images, &fingerprints);
+        let total = result.images.len();
+        image_count += total;
+        decoded_bytes = result.images.iter().fold(decoded_bytes, |total, image| {
+            total.saturating_add(image.image.as_bytes().len() as u64)
+        });
+        let limit_reached = result.errors.iter().any(|warning| {
+            matches!(
+                warning.kind,
+                ExtractImageWarningKind::ImageLimitReached
+                    | ExtractImageWarningKind::DecodedBytesLimitReached
+            )
+        });
+        let (changes, images) = match &previous {
+            Some(previous) => {
+                let images: Vec<ExtractedImage> = result
+                    .images
+                    .into_iter()
+                    .zip(&fingerprints)
+                    .filter(|(image, fingerprint)| {
+                        previous
+                            .get(&image_key(image, **fingerprint))
+                            .is_none_or(|old| old.fingerprint != **fingerprint)
+                    })
+                    .map(|(image, _)| image)
+                    .collect();
+                // 上限で途中までしか取り出せなかった版と比べると、残りがすべて消えたように見える
+                let changes = if limit_reached {
+                    Vec::new()
+                } else {
+                    compare_images(previous, &current)
+                };
+                (changes, images)
+            }
+            None => (Vec::new(), result.images),
+        };
+        previous = Some(current);
+        revisions.push(RevisionImages {
+            revision,
+            image_count: total,
+            images,
+            errors: result.errors,
+            changes,
+        });
+        // 上限に達した後の版は比べられないので調べない
+        if limit_reached {
+            break;
+        }
+    }
+    Ok(ExtractRevisionsResult { revisions })
+}
+
+// インライン画像はオブジェクト番号がないので、ページと画素で同じものとみなす
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum ImageKey {
+    Object(ObjectRef),
+    Inline { page_index: usize, fingerprint: u64 },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ImageSummary {
+    fingerprint: u64,
+    pages: Vec<usize>,
+}
+
+fn image_key(image: &ExtractedImage, fingerprint: u64) -> ImageKey {
+    match image.object {
+        Some(object) => ImageKey::Object(object),
+        None => ImageKey::Inline {
+            page_index: image.page_index,
+            fingerprint,
+        },
+    }
+}
+
+fn summarize_images(
+    images: &[ExtractedImage],
+    fingerprints: &[u64],
+) -> BTreeMap<ImageKey, ImageSummary> {
+    let mut summaries: BTreeMap<ImageKey, ImageSummary> = BTreeMap::new();
+    for (image, &fingerprint) in images.iter().zip(fingerprints) {
+        let summary = summaries
+            .entry(image_key(image, fingerprint))
+            .or_insert_with(|| ImageSummary {
+                fingerprint,
+                pages: Vec::new(),
+            });
+        if !summary.pages.contains(&image.page_index) {
+            summary.pages.push(image.page_index);
+        }
+    }
+    summaries
+}
+
+fn image_fingerprint(image: &ExtractedImage) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    image.image.width().hash(&mut hasher);
+    image.image.height().hash(&mut hasher);
+    image.image.as_bytes().hash(&mut hasher);
+    hasher.finish()
+}
+
+fn compare_images(
+    previous: &BTreeMap<ImageKey, ImageSummary>,
+    current: &BTreeMap<ImageKey, ImageSummary>,
+) -> Vec<ImageChange> {
+    let object = |key: &ImageKey| match key {
+        ImageKey::Object(object) => Some(*object),
+        ImageKey::Inline { .. } => None,
+    };
+    let mut changes = Vec::new();
+    for (key, summary) in current {
+        let kind = match previous.get(key) {
+            None => ImageChangeKind::Added,
+            Some(old) if old.fingerprint != summary.fingerprint => ImageChangeKind::Modified,
+            Some(_) => continue,
+        };
+        changes.push(ImageChange {
+            kind,
+            object: object(key),
+            pages: summary.pages.clone(),
+        });
+    }
+    for (key, summary) in previous {
+        if !current.contains_key(key) {
+            changes.push(ImageChange {
+                kind: ImageChangeKind::Removed,
+                object: object(key),
+                pages: summary.pages.clone(),
+            });
+        }
+    }
+    changes
+}
+
+// %%EOFの直後の改行までを1つの版の終わりとする。添付ファイルなどストリームの中の%%EOFを
+// 拾わないよう、直前のstartxrefが相互参照表か相互参照ストリームを指すものだけを使う
+fn find_revision_ends(bytes: &[u8]) -> Vec<usize> {
+    const EOF_MARKER: &[u8] = b"%%EOF";
+    let mut ends = Vec::new();
+    let mut section_start = 0;
+    let mut position = 0;
+    while let Some(found) = find(&bytes[position..], EOF_MARKER) {
+        let marker = position + found;
+        position = marker + EOF_MARKER.len();
+        if !points_to_xref(bytes, &bytes[section_start..marker]) {
+            continue;
+        }
+        let mut end = position;
+        if bytes.get(end) == Some(&b'\r') {
+            end += 1;
+        }
+        if bytes.get(end) == Some(&b'\n') {
+            end += 1;
+        }
+        ends.push(end);
+        section_start = end;
+    }
+    ends
+}
+
+fn points_to_xref(bytes: &[u8], section: &[u8]) -> bool {
+    const STARTXREF: &[u8] = b"startxref";
+    let Some(found) = rfind(section, STARTXREF) else {
+        return false;
+    };
+    let offset = section[found + STARTXREF.len()..]
+        .iter()
+        .skip_while(|byte| byte.is_ascii_whitespace())
+        .take_while(|byte| byte.is_ascii_digit())
+        .try_fold(0usize, |offset, byte| {
+            offset
+                .checked_mul(10)?
+                .checked_add(usize::from(byte - b'0'))
+        });
+    let Some(target) = offset.and_then(|offset| bytes.get(offset..)) else {
+        return false;
+    };
+    let target = trim_leading_whitespace(target);
+    // 相互参照ストリームは"12 0 obj"で始まる
+    target.starts_with(b"xref") || target.first().is_some_and(u8::is_ascii_digit)
+}
+
+fn is_linearized(bytes: &[u8]) -> bool {
+    find(&bytes[..bytes.len().min(1024)], b"/Linearized").is_some()
+}
+
+fn trim_leading_whitespace(bytes: &[u8]) -> &[u8] {
+    let start = bytes
+        .iter()
+        .position(|byte| !byte.is_ascii_whitespace())
+        .unwrap_or(bytes.len());
+    &bytes[start..]
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+fn rfind(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .rposition(|window| window == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::{
+        ImageChange, ImageChangeKind, ImageKey, ImageSummary, compare_images, extract_revisions,
+        find_revision_ends, rfind,
+    };
+    use crate::tests::{pixel_image, single_page_pdf};
+    use crate::{ExtractImageWarningKind, ExtractLimits, ExtractOptions, ObjectRef};
+
+    fn object(number: i32) -> ImageKey {
+        ImageKey::Object(ObjectRef {
+            number,
+            generation: 0,
+        })
+    }
+
+    fn summary(fingerprint: u64, pages: &[usize]) -> ImageSummary {
+        ImageSummary {
+            fingerprint,
+            pages: pages.to_vec(),
+        }
+    }
+
+    #[test]
+    fn finds_the_end_of_each_xref_section() {
+        let first = b"%PDF-1.7\nxref\n0 1\ntrailer\n<<>>\nstartxref\n9\n%%EOF\n".to_vec();
+        let mut bytes = first.clone();
+        // ストリームの中の%%EOFは、startxrefが相互参照を指さないので版の終わりではない
+        bytes.extend_from_slice(b"5 0 obj\n<</Length 5>>stream\n%%EOF\nendstream\n");
+        let second_xref = bytes.len();
+        bytes.extend_from_slice(
+            format!("xref\n5 1\ntrailer\n<<>>\nstartxref\n{second_xref}\n%%EOF\r\n").as_bytes(),
+        );
+        assert_eq!(find_revision_ends(&bytes), vec![first.len(), bytes.len()]);
+    }
+
+    #[test]
+    fn ignores_markers_whose_startxref_points_elsewhere() {
+        assert_eq!(
+            find_revision_ends(b"%PDF-1.7\ntrailer\nstartxref\n0\n%%EOF\n"),
+            Vec::<usize>::new()
+        );
+        assert_eq!(
+            find_revision_ends(b"%PDF-1.7\n%%EOF\n"),
+            Vec::<usize>::new()
+        );
+    }
+
+    #[test]
+    fn reports_added_removed_and_modified_images() {
+        let previous = BTreeMap::from([
+            (object(5), summary(1, &[0])),
+            (object(6), summary(2, &[1])),
+            (object(7), summary(3, &[0])),
+        ]);
+        let current = BTreeMap::from([
+            (object(5), summary(1, &[0, 1])),
+            (object(6), summary(4, &[1])),
+            (object(9), summary(5, &[2])),
+        ]);
+        let object_ref = |number| {
+            Some(ObjectRef {
+                number,
+                generation: 0,
+            })
+        };
+        assert_eq!(
+            compare_images(&previous, &current),
+            vec![
+                ImageChange {
+                    kind: ImageChangeKind::Modified,
+                    object: object_ref(6),
+                    pages: vec![1],
+                },
+                ImageChange {
+                    kind: ImageChangeKind::Added,
+                    object: object_ref(9),
+                    pages: vec![2],
+                },
+                ImageChange {
+                    kind: ImageChangeKind::Removed,
+                    object: object_ref(7),
+                    pages: vec![0],
+                },
+            ]
+        );
+    }
+
+    // 6 0 Rの画像を差し替える増分更新を追記する
+    fn two_revisions() -> Vec<u8> {
+        let mut pdf = single_page_pdf(
+            "",
+            "<< /XObject << /Im1 5 0 R /Im2 6 0 R >> >>",
+            "q 10 0 0 10 0 0 cm /Im1 Do Q q 10 0 0 10 20 0 cm /Im2 Do Q",
+            &[pixel_image([255, 0, 0]), pixel_image([0, 255, 0])],
+        );
+        let previous_xref = {
+            let found = rfind(&pdf, b"startxref").unwrap() + b"startxref".len();
+            String::from_utf8_lossy(&pdf[found..])
+                .split_whitespace()
+                .next()
+                .unwrap()
+                .to_string()
+        };
+        let object = pdf.len();
+        pdf.extend(b"6 0 obj\n");
+        pdf.extend(pixel_image([0, 0, 255]));
+        pdf.extend(b"\nendobj\n");
+        let xref = pdf.len();
+        pdf.extend(
+            format!(
+                "xref\n6 1\n{object:010} 00000 n \ntrailer\n\
+                 << /Size 7 /Root 1 0 R /Prev {previous_xref} >>\nstartxref\n{xref}\n%%EOF\n"
+            )
+            .bytes(),
+        );
+        pdf
+    }
+
+    #[test]
+    fn keeps_pixels_only_for_changed_images() {
+        let result = extract_revisions(&two_revisions(), &[], &ExtractOptions::default()).unwrap();
+        let counts: Vec<_> = result
+            .revisions
+            .iter()
+            .map(|revision| (revision.image_count, revision.images.len()))
+            .collect();
+        assert_eq!(counts, [(2, 2), (2, 1)]);
+        let latest = &result.revisions[1];
+        assert_eq!(latest.images[0].object.map(|object| object.number), Some(6));
+        assert_eq!(latest.changes.len(), 1);
+        assert_eq!(latest.changes[0].kind, ImageChangeKind::Modified);
+    }
+
+    #[test]
+    fn shares_the_image_limit_across_revisions() {
+        let options = ExtractOptions {
+            limits: ExtractLimits {
+                max_images: Some(2),
+                ..ExtractLimits::default()
+            },
+            ..ExtractOptions::default()
+        };
+        let result = extract_revisions(&two_revisions(), &[], &options).unwrap();
+        assert_eq!(result.revisions.len(), 2);
+        assert!(result.revisions[1].images.is_empty());
+        assert!(
+            result.revisions[1]
+                .changes
+                .iter()
+                .all(|change| change.kind != ImageChangeKind::Removed)
+        );
+        assert!(
+            result.revisions[1]
+                .errors
+                .iter()
+                .any(|warning| warning.kind == ExtractImageWarningKind::ImageLimitReached)
+        );
+    }
+}
@@ -0,0 +1,407 @@
+use std::collections::HashMap;
+
+use image::DynamicImage;
+use image::imageops::replace;
+use kurbo::{Affine, Rect};
+
+use crate::placement::ImageBounds;
+use crate::{ExtractLimits, ExtractedImage};
+
+// 辺が接しているとみなす隙間・ずれ（ピクセル単位）
+const EDGE_TOLERANCE: f64 = 0.5;
+// 同じ解像度とみなす1ピクセルの大きさの相対誤差
+const SCALE_TOLERANCE: f64 = 0.01;
+// せん断・回転がないとみなす係数の許容誤差
+const AXIS_EPSILON: f64 = 1e-6;
+
+// 継ぎ合わせの候補になる画像の配置
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Fragment {
+    // ページ座標での配置
+    rect: Rect,
+    // 1ピクセルの幅と高さ（ポイント）
+    scale_x: f64,
+    scale_y: f64,
+}
+
+impl Fragment {
+    // 回転・反転のない配置だけを扱う（ページ座標はy軸が上向きなので、画像の下向きのy軸はdが負）
+    pub(crate) fn new(transform: Affine, width: u32, height: u32) -> Option<Self> {
+        let [a, b, c, d, _, _] = transform.as_coeffs();
+        if width == 0
+            || height == 0
+            || a <= 0.0
+            || d >= 0.0
+            || b.abs() > AXIS_EPSILON * a
+            || c.abs() > AXIS_EPSILON * -d
+        {
+            return None;
+        }
+        let (width, height) = (f64::from(width), f64::from(height));
+        let rect = transform.transform_rect_bbox(Rect::new(0.0, 0.0, width, height));
+        if !rect.is_finite() {
+            return None;
+        }
+        Some(Self {
+            rect,
+            scale_x: rect.width() / width,
+            scale_y: rect.height() / height,
+        })
+    }
+
+    // 辺が接しているとみなす隙間（ポイント）
+    fn tolerance(&self) -> f64 {
+        EDGE_TOLERANCE * self.scale_x.max(self.scale_y)
+    }
+
+    fn same_scale(&self, other: &Self) -> bool {
+        let close = |a: f64, b: f64| (a - b).abs() <= SCALE_TOLERANCE * a.max(b);
+        close(self.scale_x, other.scale_x) && close(self.scale_y, other.scale_y)
+    }
+
+    // 幅がそろった帯が上下に、または高さがそろったタイルが左右に接している
+    fn adjoins(&self, other: &Self) -> bool {
+        let (a, b) = (self.rect, other.rect);
+        let tolerance_x = EDGE_TOLERANCE * self.scale_x;
+        let tolerance_y = EDGE_TOLERANCE * self.scale_y;
+        let near = |p: f64, q: f64, tolerance: f64| (p - q).abs() <= tolerance;
+        let stacked = near(a.x0, b.x0, tolerance_x)
+            && near(a.x1, b.x1, tolerance_x)
+            && (near(a.y0, b.y1, tolerance_y) || near(b.y0, a.y1, tolerance_y));
+        let side_by_side = near(a.y0, b.y0, tolerance_y)
+            && near(a.y1, b.y1, tolerance_y)
+            && (near(a.x0, b.x1, tolerance_x) || near(b.x0, a.x1, tolerance_x));
+        stacked || side_by_side
+    }
+}
+
+// 1ページ分の画像のうち、接して並ぶ断片を1枚にまとめる。fragmentsはimagesと同じ順で、
+// 候補にならない画像はNone。まとめた画像は最初に描かれた断片の位置に置き、その情報を引き継ぐ。
+// decoded_bytesはこれまでにデコードした大きさで、まとめた画像もその上限に収まるものだけを作る
+pub(crate) fn stitch_fragments(
+    images: Vec<ExtractedImage>,
+    fragments: &[Option<Fragment>],
+    limits: &ExtractLimits,
+    decoded_bytes: u64,
+) -> Vec<ExtractedImage> {
+    let candidates: Vec<(usize, Fragment)> = (0..images.len())
+        .filter_map(|i| Some((i, fragments.get(i).copied().flatten()?)))
+        .collect();
+    // 接する断片は辺の座標がそろうので、左上の角（上下に並ぶ帯）と左下の角（左右に並ぶタイル）で
+    // 索引を作り、自分の左下・右下の角の近くにあるものとだけ比べる
+    let cell = candidates
+        .iter()
+        .map(|(_, fragment)| fragment.tolerance())
+        .fold(0.0, f64::max);
+    let mut top_left = CornerIndex::new(cell);
+    let mut bottom_left = CornerIndex::new(cell);
+    for &(i, fragment) in &candidates {
+        top_left.insert(fragment.rect.x0, fragment.rect.y1, i);
+        bottom_left.insert(fragment.rect.x0, fragment.rect.y0, i);
+    }
+    let mut groups = UnionFind::new(images.len());
+    for &(i, a) in &candidates {
+        let below = top_left.near(a.rect.x0, a.rect.y0);
+        let right = bottom_left.near(a.rect.x1, a.rect.y0);
+        for j in below.chain(right) {
+            let Some(b) = fragments[j] else {
+                continue;
+            };
+            if j != i && compatible(&images[i], &images[j]) && a.same_scale(&b) && a.adjoins(&b) {
+                groups.union(i, j);
+            }
+        }
+    }
+
+    let mut members: Vec<Vec<usize>> = vec![Vec::new(); images.len()];
+    for i in 0..images.len() {
+        members[groups.find(i)].push(i);
+    }
+    let mut stitched: Vec<Option<(DynamicImage, Rect)>> = vec![None; images.len()];
+    for group in members.iter().filter(|group| group.len() > 1) {
+        let parts = group
+            .iter()
+            .filter_map(|&i| Some((&images[i].image, fragments[i]?)))
+            .collect::<Vec<_>>();
+        stitched[group[0]] = assemble(&parts, limits, decoded_bytes);
+    }
+
+    let mut result = Vec::with_capacity(images.len());
+    let mut absorbed = vec![false; images.len()];
+    for (i, mut image) in images.into_iter().enumerate() {
+        if absorbed[i] {
+            continue;
+        }
+        if let Some((canvas, rect)) = stitched[i].take() {
+            let group = &members[groups.find(i)];
+            for &member in group {
+                absorbed[member] = true;
+            }
+            image.image = canvas;
+            image.bounds = ImageBounds {
+                x0: rect.x0 as f32,
+                y0: rect.y0 as f32,
+                x1: rect.x1 as f32,
+                y1: rect.y1 as f32,
+            };
+            image.fragment_count = group.len();
+        }
+        result.push(image);
+    }
+    result
+}
+
+// 同じ色の種類で、同じ場所（ページ本体か同じ注釈、同じレイヤ）に描かれた画像だけをまとめる
+fn compatible(a: &ExtractedImage, b: &ExtractedImage) -> bool {
+    a.image.color() == b.image.color()
+        && a.annotation == b.annotation
+        && a.layers == b.layers
+        && a.artifact == b.artifact
+}
+
+// 断片を並べた画像と、それが覆うページ上の矩形。隙間や重なりがあるか、
+// まとめた画像が上限を超えるならまとめない
+fn assemble(
+    parts: &[(&DynamicImage, Fragment)],
+    limits: &ExtractLimits,
+    decoded_bytes: u64,
+) -> Option<(DynamicImage, Rect)> {
+    let (first_image, first) = parts.first()?;
+    let union = parts.iter().fold(first.rect, |union, (_, fragment)| {
+        union.union(fragment.rect)
+    });
+
+    let mut placed = Vec::with_capacity(parts.len());
+    let (mut width, mut height) = (0u32, 0u32);
+    let mut covered: u64 = 0;
+    for (image, fragment) in parts {
+        // 画像の上端がページ座標の上側
+        let x = ((fragment.rect.x0 - union.x0) / first.scale_x).round() as u32;
+        let y = ((union.y1 - fragment.rect.y1) / first.scale_y).round() as u32;
+        width = width.max(x.checked_add(image.width())?);
+        height = height.max(y.checked_add(image.height())?);
+        covered += u64::from(image.width()) * u64::from(image.height());
+        placed.push((image, x, y));
+    }
+    if covered != u64::from(width) * u64::from(height) {
+        return None;
+    }
+    // 断片を捨てる前にキャンバスを確保するので、その分も上限に数える
+    let canvas_bytes = covered.saturating_mul(u64::from(first_image.color().bytes_per_pixel()));
+    if limits.check_image_dimensions(width, height).is_err()
+        || limits
+            .check_decoded_bytes(decoded_bytes.saturating_add(canvas_bytes))
+            .is_err()
+    {
+        return None;
+    }
+
+    let mut canvas = DynamicImage::new(width, height, first_image.color());
+    for (image, x, y) in placed {
+        replace(&mut canvas, *image, i64::from(x), i64::from(y));
+    }
+    Some((canvas, union))
+}
+
+// 角の座標を、接しているとみなす隙間の大きさの格子で区切って引けるようにする
+struct CornerIndex {
+    cell: f64,
+    corners: HashMap<(i64, i64), Vec<usize>>,
+}
+
+impl CornerIndex {
+    fn new(cell: f64) -> Self {
+        Self {
+            cell,
+            corners: HashMap::new(),
+        }
+    }
+
+    fn key(&self, x: f64, y: f64) -> (i64, i64) {
+        (
+            (x / self.cell).floor() as i64,
+            (y / self.cell).floor() as i64,
+        )
+    }
+
+    fn insert(&mut self, x: f64, y: f64, i: usize) {
+        let key = self.key(x, y);
+        self.corners.entry(key).or_default().push(i);
+    }
+
+    // 隙間の大きさ以内にある角は、同じか隣の格子に入っている
+    fn near(&self, x: f64, y: f64) -> impl Iterator<Item = usize> + '_ {
+        let (kx, ky) = self.key(x, y);
+        (-1..=1)
+            .flat_map(move |dx| {
+                (-1..=1).map(move |dy| (kx.saturating_add(dx), ky.saturating_add(dy)))
+            })
+            .filter_map(|key| self.corners.get(&key))
+            .flatten()
+            .copied()
+    }
+}
+
+struct UnionFind {
+    parents: Vec<usize>,
+}
+
+impl UnionFind {
+    fn new(len: usize) -> Self {
+        Self {
+            parents: (0..len).collect(),
+        }
+    }
+
+    fn find(&mut self, i: usize) -> usize {
+        let mut root = i;
+        while self.parents[root] != root {
+            root = self.parents[root];
+        }
+        let mut current = i;
+        while self.parents[current] != root {
+            let next = self.parents[current];
+            self.parents[current] = root;
+            current = next;
+        }
+        root
+    }
+
+    // 小さいほうの番号を代表にして、グループの先頭が最初に描かれた断片になるようにする
+    fn union(&mut self, a: usize, b: usize) {
+        let (a, b) = (self.find(a), self.find(b));
+        if a != b {
+            let (root, child) = (a.min(b), a.max(b));
+            self.parents[child] = root;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{DynamicImage, GenericImageView, Rgb, RgbImage};
+    use kurbo::Affine;
+
+    use super::{Fragment, assemble};
+    use crate::tests::{pixel_image, single_page_pdf};
+    use crate::{ExtractLimits, ExtractOptions, extract_images_with_options};
+
+    // ページ座標(x, y)に、1ピクセル1ポイントで置いた画像の配置
+    fn placed(x: f64, y: f64, width: u32, height: u32) -> Fragment {
+        let transform = Affine::new([1.0, 0.0, 0.0, -1.0, x, y + f64::from(height)]);
+        Fragment::new(transform, width, height).unwrap()
+    }
+
+    fn filled(width: u32, height: u32, value: u8) -> DynamicImage {
+        DynamicImage::ImageRgb8(RgbImage::from_pixel(width, height, Rgb([value; 3])))
+    }
+
+    #[test]
+    fn accepts_only_upright_placements() {
+        assert!(Fragment::new(Affine::new([2.0, 0.0, 0.0, -2.0, 0.0, 10.0]), 4, 4).is_some());
+        // 上下反転
+        assert!(Fragment::new(Affine::new([2.0, 0.0, 0.0, 2.0, 0.0, 0.0]), 4, 4).is_none());
+        // 90度回転
+        assert!(Fragment::new(Affine::new([0.0, 2.0, 2.0, 0.0, 0.0, 0.0]), 4, 4).is_none());
+    }
+
+    #[test]
+    fn detects_adjacent_strips_and_tiles() {
+        let top = placed(10.0, 50.0, 40, 10);
+        let below = placed(10.0, 40.0, 40, 10);
+        assert!(top.adjoins(&below));
+        assert!(below.adjoins(&top));
+        // 幅が違う
+        assert!(!top.adjoins(&placed(10.0, 40.0, 30, 10)));
+        // 離れている
+        assert!(!top.adjoins(&placed(10.0, 30.0, 40, 10)));
+        // 左右に並ぶタイル
+        assert!(top.adjoins(&placed(50.0, 50.0, 20, 10)));
+    }
+
+    #[test]
+    fn assembles_strips_from_top_to_bottom() {
+        let top = filled(3, 2, 10);
+        let bottom = filled(3, 1, 200);
+        // 描かれる順は下の帯が先
+        let (image, rect) = assemble(
+            &[
+                (&bottom, placed(0.0, 0.0, 3, 1)),
+                (&top, placed(0.0, 1.0, 3, 2)),
+            ],
+            &ExtractLimits::unlimited(),
+            0,
+        )
+        .unwrap();
+        assert_eq!(image.dimensions(), (3, 3));
+        assert_eq!(image.get_pixel(1, 0).0[0], 10);
+        assert_eq!(image.get_pixel(1, 2).0[0], 200);
+        assert_eq!((rect.x0, rect.y0, rect.x1, rect.y1), (0.0, 0.0, 3.0, 3.0));
+    }
+
+    #[test]
+    fn keeps_fragments_that_leave_a_gap() {
+        let tile = filled(2, 2, 0);
+        // L字に並んだ3枚は矩形を埋めない
+        assert!(
+            assemble(
+                &[
+                    (&tile, placed(0.0, 2.0, 2, 2)),
+                    (&tile, placed(2.0, 2.0, 2, 2)),
+                    (&tile, placed(0.0, 0.0, 2, 2)),
+                ],
+                &ExtractLimits::unlimited(),
+                0,
+            )
+            .is_none()
+        );
+    }
+
+    #[test]
+    fn keeps_fragments_when_the_canvas_exceeds_the_limits() {
+        let strip = filled(4, 1, 0);
+        let parts = [
+            (&strip, placed(0.0, 1.0, 4, 1)),
+            (&strip, placed(0.0, 0.0, 4, 1)),
+        ];
+        let pixels = ExtractLimits {
+            max_image_pixels: Some(4),
+            ..ExtractLimits::unlimited()
+        };
+        assert!(assemble(&parts, &pixels, 0).is_none());
+        // 2本の帯でデコード済みの24バイトに、キャンバスの24バイトが加わる
+        let bytes = ExtractLimits {
+            max_total_decoded_bytes: Some(47),
+            ..ExtractLimits::unlimited()
+        };
+        assert!(assemble(&parts, &bytes, 24).is_none());
+        assert!(assemble(&parts, &ExtractLimits::unlimited(), 24).is_some());
+    }
+
+    #[test]
+    fn groups_columns_and_rows_of_fragments() {
+        // 縦に3本の帯、離れた所に横に2枚のタイル、どれとも接しない1枚
+        let placements = [(0, 0), (0, 1), (0, 2), (10, 0), (11, 0), (20, 20)];
+        let content: String = placements
+            .iter()
+            .map(|(x, y)| format!("q 1 0 0 1 {x} {y} cm /Im Do Q "))
+            .collect();
+        let pdf = single_page_pdf(
+            "",
+            "<< /XObject << /Im 5 0 R >> >>",
+            &content,
+            &[pixel_image([0, 0, 0])],
+        );
+        let options = ExtractOptions {
+            stitch_fragments: true,
+            ..ExtractOptions::default()
+        };
+        let result = extract_images_with_options(&pdf, &[], &options).unwrap();
+        let sizes: Vec<_> = result
+            .images
+            .iter()
+            .map(|image| (image.image.dimensions(), image.fragment_count))
+            .collect();
+        assert_eq!(sizes, [((1, 3), 3), ((2, 1), 2), ((1, 1), 1)]);
+    }
+}
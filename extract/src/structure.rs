@@ -0,0 +1,257 @@
+use std::collections::{HashMap, HashSet};
+
+use hayro_syntax::object::dict::keys::{
+    ACTUAL_TEXT, ALT, K, P, PARENT_TREE, PG, ROLE_MAP, S, STRUCT_PARENT, STRUCT_TREE_ROOT, T, TYPE,
+};
+use hayro_syntax::object::{Dict, MaybeRef, Name, ObjRef, Object, String as PdfString};
+use hayro_syntax::xref::XRef;
+
+use crate::ObjectRef;
+use crate::number_tree::{NumberTreeEntry, number_tree_entries, resolve};
+use crate::text::decode_text_string;
+
+// /Pや/RoleMapが循環していても止まるように辿る回数を制限する
+const MAX_CHAIN_DEPTH: usize = 32;
+// 構造ツリーを辿る深さの上限
+const MAX_TREE_DEPTH: usize = 256;
+const FIGURE: &str = "Figure";
+
+// 画像を含む構造要素（タグ付きPDFのFigureなど）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StructureElement {
+    // 構造要素の参照（直接オブジェクトならNone）
+    pub object: Option<ObjectRef>,
+    // /RoleMapで標準の構造型に対応付けた役割（対応付けがなければ/Sのまま）
+    pub role: String,
+    // /Alt。要素自身になければ祖先の/Alt（子孫全体の代替テキストになる）
+    pub alt: Option<String>,
+    pub actual_text: Option<String>,
+    // /T
+    pub title: Option<String>,
+}
+
+// 構造ツリーの中のFigure要素
+pub(crate) struct FigureElement {
+    pub(crate) element: StructureElement,
+    // /Pgのページ
+    pub(crate) page: Option<ObjRef>,
+}
+
+// 内容と構造要素の対応（/StructTreeRoot /ParentTree）
+pub(crate) struct StructureTree<'a> {
+    xref: &'a XRef,
+    root: Option<Dict<'a>>,
+    parent_tree: HashMap<i32, NumberTreeEntry<'a>>,
+    role_map: HashMap<String, String>,
+}
+
+impl<'a> StructureTree<'a> {
+    pub(crate) fn new(xref: &'a XRef) -> Self {
+        let root = xref
+            .get::<Dict<'_>>(xref.root_id())
+            .and_then(|catalog| catalog.get::<Dict<'_>>(STRUCT_TREE_ROOT));
+        let parent_tree = root
+            .as_ref()
+            .and_then(|root| root.get::<Dict<'_>>(PARENT_TREE))
+            .map(|tree| {
+                number_tree_entries(xref, &tree)
+                    .into_iter()
+                    .map(|entry| (entry.key, entry))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let role_map = root
+            .as_ref()
+            .and_then(|root| root.get::<Dict<'_>>(ROLE_MAP))
+            .map(|role_map| {
+                role_map
+                    .entries()
+                    .filter_map(|(name, value)| {
+                        let MaybeRef::NotRef(value) = value else {
+                            return None;
+                        };
+                        Some((name_string(&name), name_string(&value.into_name()?)))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        Self {
+            xref,
+            root,
+            parent_tree,
+            role_map,
+        }
+    }
+
+    // /StructTreeRootがある（タグ付きPDF）か
+    pub(crate) fn is_tagged(&self) -> bool {
+        self.root.is_some()
+    }
+
+    // 構造ツリーを/Kの順に辿って、役割がFigureの要素を集める
+    pub(crate) fn figure_elements(&self) -> Vec<FigureElement> {
+        let mut figures = Vec::new();
+        let mut visited = HashSet::new();
+        if let Some(root) = &self.root {
+            self.collect_figures(root, None, &mut figures, &mut visited, 0);
+        }
+        figures
+    }
+
+    fn collect_figures(
+        &self,
+        node: &Dict<'a>,
+        page: Option<ObjRef>,
+        figures: &mut Vec<FigureElement>,
+        visited: &mut HashSet<ObjRef>,
+        depth: usize,
+    ) {
+        if depth > MAX_TREE_DEPTH {
+            return;
+        }
+        // /Kは要素1つ、要素の配列、MCID、MCRやOBJRの辞書のどれか
+        let kids: Vec<MaybeRef<Object<'a>>> = match node.get_raw::<Object<'a>>(K) {
+            Some(MaybeRef::NotRef(Object::Array(kids))) => kids.raw_iter().collect(),
+            Some(kid) => match resolve(self.xref, kid) {
+                Some(Object::Array(kids)) => kids.raw_iter().collect(),
+                Some(kid) => vec![MaybeRef::NotRef(kid)],
+                None => Vec::new(),
+            },
+            None => Vec::new(),
+        };
+        for kid in kids {
+            let object_ref = kid.as_obj_ref();
+            if let Some(object_ref) = object_ref
+                && !visited.insert(object_ref)
+            {
+                continue;
+            }
+            let Some(kid) = resolve(self.xref, kid).and_then(|kid| kid.into_dict()) else {
+                continue;
+            };
+            // MCRやOBJRは要素ではない
+            if !kid.contains_key(S) {
+                continue;
+            }
+            let kid_page = kid.get_ref(PG).or(page);
+            if let Some(element) = self.element(object_ref.map(ObjectRef::from), kid.clone())
+                && element.role == FIGURE
+            {
+                figures.push(FigureElement {
+                    element,
+                    page: kid_page,
+                });
+            }
+            self.collect_figures(&kid, kid_page, figures, visited, depth + 1);
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.parent_tree.is_empty()
+    }
+
+    // ページ（/StructParents）の内容ストリームでMCIDが付いたマーク付きコンテンツの構造要素
+    pub(crate) fn marked_content_element(
+        &self,
+        struct_parents: i32,
+        mcid: i32,
+    ) -> Option<StructureElement> {
+        let elements = self
+            .parent_tree
+            .get(&struct_parents)?
+            .value
+            .clone()
+            .into_array()?;
+        let value = elements.raw_iter().nth(usize::try_from(mcid).ok()?)?;
+        let object = value.as_obj_ref().map(ObjectRef::from);
+        self.element(object, resolve(self.xref, value)?.into_dict()?)
+    }
+
+    // /StructParentを持つオブジェクト（画像XObjectなど）を直接参照する構造要素
+    pub(crate) fn object_element(&self, object: &Dict<'_>) -> Option<StructureElement> {
+        let entry = self.parent_tree.get(&object.get::<i32>(STRUCT_PARENT)?)?;
+        self.element(
+            entry.object_ref.map(ObjectRef::from),
+            entry.value.clone().into_dict()?,
+        )
+    }
+
+    fn element(&self, object: Option<ObjectRef>, element: Dict<'_>) -> Option<StructureElement> {
+        let role = element
+            .get::<Name<'_>>(S)
+            .map(|role| standard_role(&self.role_map, &name_string(&role)))
+            .unwrap_or_default();
+        Some(StructureElement {
+            object,
+            role,
+            alt: inherited_alt(&element),
+            actual_text: text(&element, ACTUAL_TEXT),
+            title: text(&element, T),
+        })
+    }
+}
+
+fn inherited_alt(element: &Dict<'_>) -> Option<String> {
+    let mut current = Some(element.clone());
+    for _ in 0..MAX_CHAIN_DEPTH {
+        let element = current?;
+        if element.get::<Name<'_>>(TYPE).as_deref() == Some(STRUCT_TREE_ROOT) {
+            return None;
+        }
+        if let Some(alt) = text(&element, ALT) {
+            return Some(alt);
+        }
+        current = element.get::<Dict<'_>>(P);
+    }
+    None
+}
+
+// 独自の構造型は/RoleMapを辿って標準の構造型にする
+fn standard_role(role_map: &HashMap<String, String>, role: &str) -> String {
+    let mut current = role;
+    for _ in 0..MAX_CHAIN_DEPTH {
+        match role_map.get(current) {
+            Some(mapped) if mapped != current => current = mapped,
+            _ => break,
+        }
+    }
+    current.to_string()
+}
+
+fn text(dict: &Dict<'_>, key: &[u8]) -> Option<String> {
+    dict.get::<PdfString<'_>>(key)
+        .map(|value| decode_text_string(&value.get()))
+}
+
+fn name_string(name: &Name<'_>) -> String {
+    String::from_utf8_lossy(name).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::standard_role;
+
+    #[test]
+    fn follows_role_map_to_standard_roles() {
+        let role_map = HashMap::from([
+            ("Photo".to_string(), "Illustration".to_string()),
+            ("Illustration".to_string(), "Figure".to_string()),
+            ("Loop".to_string(), "Loop".to_string()),
+        ]);
+        assert_eq!(standard_role(&role_map, "Photo"), "Figure");
+        assert_eq!(standard_role(&role_map, "Figure"), "Figure");
+        assert_eq!(standard_role(&role_map, "Loop"), "Loop");
+    }
+
+    #[test]
+    fn stops_on_role_map_cycles() {
+        let role_map = HashMap::from([
+            ("A".to_string(), "B".to_string()),
+            ("B".to_string(), "A".to_string()),
+        ]);
+        let role = standard_role(&role_map, "A");
+        assert!(role == "A" || role == "B");
+    }
+}
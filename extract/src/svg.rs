@@ -0,0 +1,984 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::io::Cursor;
+use std::rc::Rc;
+
+use hayro_interpret::font::Glyph;
+use hayro_interpret::{
+    BlendMode, ClipPath, Context, Device, FillRule, GlyphDrawMode, Image, LumaData, Paint,
+    PathDrawMode, RectExt, SoftMask, StrokeProps, interpret,
+};
+use image::{DynamicImage, ImageBuffer, ImageFormat, Rgba};
+use kurbo::{Affine, BezPath, Cap, Join, PathEl, Rect, Shape};
+
+use crate::layers::{LayerVisibility, MarkedContentTracker, OptionalContent};
+use crate::limits::estimated_decoded_bytes;
+use crate::orientation::page_rotation_degrees;
+use crate::page_labels::read_page_labels;
+use crate::{
+    CancellationToken, ExtractError, ExtractImageWarning, ExtractImageWarningKind, ExtractLimits,
+    ImageBounds, PageRange, catch_quietly, open_pdf, page_only_settings, panic_message,
+    raster_to_dynamic_image, select_pages_within_limit,
+};
+
+// 図とみなす最小の幅と高さ（ポイント）。罫線や下線だけのまとまりを除く
+const MIN_FIGURE_SIZE: f64 = 36.0;
+// この距離（ポイント）より近い図形は同じ図にまとめる
+const FIGURE_GAP: f64 = 6.0;
+// 図の外側のこの距離（ポイント）以内にある文字は軸ラベルや凡例として図に含める
+const LABEL_MARGIN: f64 = 12.0;
+// ページのこの割合以上を覆う図形は背景や枠とみなして図の検出に使わない
+const BACKGROUND_COVERAGE: f64 = 0.8;
+// 線の太さのために図の周りに足す余白（ポイント）
+const FIGURE_PADDING: f64 = 2.0;
+// 図の検出で近い図形を探す格子の最小の大きさ（ポイント）と、1辺あたりの最大の数
+const FIGURE_CELL: f64 = 48.0;
+const MAX_FIGURE_CELLS: f64 = 64.0;
+
+#[derive(Debug, Clone, Default)]
+pub struct SvgOptions {
+    // ページ全体ではなく、ページ上の図（まとまった図形と画像、その周りのラベル）ごとに書き出す
+    pub figures: bool,
+    pub limits: ExtractLimits,
+    pub cancellation: CancellationToken,
+}
+
+// ページ全体、またはページ上の1つの図をSVGにしたもの
+#[derive(Debug, Clone)]
+pub struct SvgGraphic {
+    pub page_index: usize,
+    pub page_label: String,
+    // 書き出した範囲（ページ座標、/Rotateの適用前）
+    pub bounds: ImageBounds,
+    pub svg: String,
+    // SVGで表せないため省いた、この範囲にかかるパターンの塗り・線の数
+    pub skipped_paints: usize,
+    // ソフトマスクや描画モードを無視して書き出した、この範囲にかかる描画の数
+    pub ignored_effects: usize,
+}
+
+#[derive(Debug)]
+pub struct ExportSvgResult {
+    pub graphics: Vec<SvgGraphic>,
+    pub errors: Vec<ExtractImageWarning>,
+}
+
+// 内容ストリームの図形・文字・画像をベクターのまま記録し、SVGとして書き出す
+pub fn export_svg(
+    pdf_bytes: &[u8],
+    page_ranges: &[PageRange],
+    options: &SvgOptions,
+) -> Result<ExportSvgResult, ExtractError> {
+    let pdf = open_pdf(pdf_bytes)?;
+    let pages = pdf.pages();
+    let page_labels = read_page_labels(pdf.xref(), pages.len());
+    let (selected_pages, _) = select_pages_within_limit(pages.len(), page_ranges, &options.limits)?;
+    let optional_content = Rc::new(RefCell::new(OptionalContent::new(
+        pdf.xref(),
+        LayerVisibility::Default,
+    )));
+
+    let mut result = ExportSvgResult {
+        graphics: Vec::new(),
+        errors: Vec::new(),
+    };
+    let mut budget = ImageBudget::default();
+    for (i, page) in pages.iter().enumerate() {
+        if !selected_pages[i] {
+            continue;
+        }
+        if options.cancellation.is_cancelled() {
+            return Err(ExtractError::Cancelled);
+        }
+        let crop_box = page.intersected_crop_box().to_kurbo();
+        let mut capture =
+            VectorCapture::new(i, options.limits, budget, Rc::clone(&optional_content));
+        optional_content.borrow_mut().reset();
+
+        // ページ座標のまま記録する
+        let mut context =
+            Context::new(Affine::IDENTITY, crop_box, pdf.xref(), page_only_settings());
+        let interpreted = catch_quietly(|| {
+            let ops = MarkedContentTracker::new(
+                page.typed_operations(),
+                page.resources().clone(),
+                Rc::clone(&optional_content),
+            );
+            interpret(ops, page.resources(), &mut context, &mut capture);
+        });
+        if let Err(payload) = interpreted {
+            capture.warnings.push(ExtractImageWarning {
+                page_index: i,
+                image_index: None,
+                kind: ExtractImageWarningKind::PageInterpretFailed {
+                    message: panic_message(payload.as_ref()),
+                },
+            });
+        }
+        budget = capture.budget;
+        result.errors.append(&mut capture.warnings);
+
+        let regions = if options.figures {
+            detect_figures(&capture.items, crop_box)
+        } else {
+            vec![crop_box]
+        };
+        let rotation = page_rotation_degrees(page.rotation());
+        for region in regions {
+            result.graphics.push(SvgGraphic {
+                page_index: i,
+                page_label: page_labels.get(i).cloned().unwrap_or_default(),
+                bounds: ImageBounds {
+                    x0: region.x0 as f32,
+                    y0: region.y0 as f32,
+                    x1: region.x1 as f32,
+                    y1: region.y1 as f32,
+                },
+                svg: write_svg(&capture.items, region, rotation),
+                skipped_paints: capture
+                    .skipped_paints
+                    .iter()
+                    .filter(|bounds| is_in_region(**bounds, region))
+                    .count(),
+                ignored_effects: capture
+                    .ignored_effects
+                    .iter()
+                    .filter(|bounds| is_in_region(**bounds, region))
+                    .count(),
+            });
+        }
+    }
+    Ok(result)
+}
+
+// 描画命令を記録したもの。クリップと透明グループは入れ子の<g>として残す
+#[derive(Debug, Clone, PartialEq)]
+enum Item {
+    Open(String),
+    Close,
+    Draw {
+        kind: ItemKind,
+        // ページ座標での範囲（線の太さを含む）
+        bounds: Rect,
+        markup: String,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ItemKind {
+    Path,
+    Text,
+    Image,
+}
+
+// 埋め込む画像の数とデコード後の大きさ（ページをまたいで数える）
+#[derive(Debug, Clone, Copy, Default)]
+struct ImageBudget {
+    count: usize,
+    decoded_bytes: u64,
+    limit_reached: bool,
+}
+
+struct VectorCapture<'a> {
+    page_index: usize,
+    items: Vec<Item>,
+    clip_count: usize,
+    // 開いている<g>ごとの、ページ座標でのクリップの範囲（透明グループはNone）
+    clips: Vec<Option<Rect>>,
+    // 省いたパターンの塗り・線のページ座標での範囲
+    skipped_paints: Vec<Rect>,
+    // ソフトマスクや通常以外の描画モードが、いまの描画または開いている透明グループにかかっているか
+    soft_mask: bool,
+    blend_mode: bool,
+    group_effects: Vec<bool>,
+    // それらを無視して書き出した描画のページ座標での範囲
+    ignored_effects: Vec<Rect>,
+    // Type3グリフの手続きの中で描かれた図形は文字として扱う
+    text_depth: usize,
+    image_index: usize,
+    limits: ExtractLimits,
+    budget: ImageBudget,
+    warnings: Vec<ExtractImageWarning>,
+    optional_content: Rc<RefCell<OptionalContent<'a>>>,
+}
+
+impl<'a> VectorCapture<'a> {
+    fn new(
+        page_index: usize,
+        limits: ExtractLimits,
+        budget: ImageBudget,
+        optional_content: Rc<RefCell<OptionalContent<'a>>>,
+    ) -> Self {
+        Self {
+            page_index,
+            items: Vec::new(),
+            clip_count: 0,
+            clips: Vec::new(),
+            skipped_paints: Vec::new(),
+            soft_mask: false,
+            blend_mode: false,
+            group_effects: Vec::new(),
+            ignored_effects: Vec::new(),
+            text_depth: 0,
+            image_index: 0,
+            limits,
+            budget,
+            warnings: Vec::new(),
+            optional_content,
+        }
+    }
+
+    fn visible(&self) -> bool {
+        self.optional_content.borrow().current(None).0
+    }
+
+    fn push_shape(
+        &mut self,
+        kind: ItemKind,
+        path: &BezPath,
+        transform: Affine,
+        paint: &Paint<'a>,
+        stroke: Option<&StrokeProps>,
+        fill_rule: FillRule,
+    ) {
+        let mut bounds = transform.transform_rect_bbox(path.bounding_box());
+        if let Some(stroke) = stroke {
+            // 線の太さは変換の拡大率の分だけ広がる
+            let width = f64::from(stroke.line_width) * transform.determinant().abs().sqrt();
+            bounds = bounds.inflate(width / 2.0, width / 2.0);
+        }
+        let Paint::Color(color) = paint else {
+            if let Some(bounds) = self.clip_bounds(bounds) {
+                self.skipped_paints.push(bounds);
+            }
+            return;
+        };
+        let [r, g, b, a] = color.to_rgba().to_rgba8();
+        let color = format!("#{r:02x}{g:02x}{b:02x}");
+
+        let mut markup = format!(
+            r#"<path d="{}"{}"#,
+            path_data(path),
+            transform_attribute(transform)
+        );
+        match stroke {
+            Some(stroke) => {
+                let _ = write!(
+                    markup,
+                    r#" fill="none" stroke="{color}" stroke-width="{}""#,
+                    number(f64::from(stroke.line_width))
+                );
+                if a < 255 {
+                    let _ = write!(markup, r#" stroke-opacity="{}""#, opacity(a));
+                }
+                markup.push_str(match stroke.line_cap {
+                    Cap::Butt => "",
+                    Cap::Round => r#" stroke-linecap="round""#,
+                    Cap::Square => r#" stroke-linecap="square""#,
+                });
+                match stroke.line_join {
+                    Join::Miter => {
+                        let _ = write!(
+                            markup,
+                            r#" stroke-miterlimit="{}""#,
+                            number(f64::from(stroke.miter_limit))
+                        );
+                    }
+                    Join::Round => markup.push_str(r#" stroke-linejoin="round""#),
+                    Join::Bevel => markup.push_str(r#" stroke-linejoin="bevel""#),
+                }
+                if !stroke.dash_array.is_empty() {
+                    let dashes = stroke
+                        .dash_array
+                        .iter()
+                        .map(|dash| number(f64::from(*dash)))
+                        .collect::<Vec<_>>();
+                    let _ = write!(
+                        markup,
+                        r#" stroke-dasharray="{}" stroke-dashoffset="{}""#,
+                        dashes.join(" "),
+                        number(f64::from(stroke.dash_offset))
+                    );
+                }
+            }
+            None => {
+                let _ = write!(markup, r#" fill="{color}""#);
+                if a < 255 {
+                    let _ = write!(markup, r#" fill-opacity="{}""#, opacity(a));
+                }
+                if fill_rule == FillRule::EvenOdd {
+                    markup.push_str(r#" fill-rule="evenodd""#);
+                }
+            }
+        }
+        markup.push_str("/>");
+        self.push_draw(kind, bounds, markup);
+    }
+
+    // クリップの外に出る部分は範囲に含めず、すべて隠れるものはNone
+    fn clip_bounds(&self, bounds: Rect) -> Option<Rect> {
+        let mut bounds = bounds;
+        for clip in self.clips.iter().flatten() {
+            if !near(bounds, *clip, 0.0) {
+                return None;
+            }
+            bounds = bounds.intersect(*clip);
+        }
+        Some(bounds)
+    }
+
+    fn push_draw(&mut self, kind: ItemKind, bounds: Rect, markup: String) {
+        let Some(bounds) = self.clip_bounds(bounds) else {
+            return;
+        };
+        if self.soft_mask || self.blend_mode || self.group_effects.contains(&true) {
+            self.ignored_effects.push(bounds);
+        }
+        self.items.push(Item::Draw {
+            kind,
+            bounds,
+            markup,
+        });
+    }
+
+    fn push_image(&mut self, image: DynamicImage, transform: Affine, width: u32, height: u32) {
+        let decoded_bytes = self
+            .budget
+            .decoded_bytes
+            .saturating_add(image.as_bytes().len() as u64);
+        if let Err(kind) = self.limits.check_decoded_bytes(decoded_bytes) {
+            self.stop_at_limit(kind);
+            return;
+        }
+        let mut png = Vec::new();
+        if image
+            .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .is_err()
+        {
+            self.push_warning(ExtractImageWarningKind::ImageDecodeFailed {
+                object: None,
+                filter: None,
+            });
+            return;
+        }
+        self.budget.decoded_bytes = decoded_bytes;
+        self.budget.count += 1;
+
+        // 画像はピクセル座標（y軸が下向き）で置き、宣言サイズに引き伸ばす
+        let bounds =
+            transform.transform_rect_bbox(Rect::new(0.0, 0.0, f64::from(width), f64::from(height)));
+        let markup = format!(
+            r#"<image width="{width}" height="{height}" preserveAspectRatio="none"{} href="data:image/png;base64,{}"/>"#,
+            transform_attribute(transform),
+            base64(&png)
+        );
+        self.push_draw(ItemKind::Image, bounds, markup);
+    }
+
+    fn push_warning(&mut self, kind: ExtractImageWarningKind) {
+        self.warnings.push(ExtractImageWarning {
+            page_index: self.page_index,
+            image_index: Some(self.image_index),
+            kind,
+        });
+    }
+
+    // 上限に達したら警告を1回だけ出し、以降の画像は埋め込まない（図形は書き出し続ける）
+    fn stop_at_limit(&mut self, kind: ExtractImageWarningKind) {
+        if !self.budget.limit_reached {
+            self.push_warning(kind);
+        }
+        self.budget.limit_reached = true;
+    }
+}
+
+impl<'a> Device<'a> for VectorCapture<'a> {
+    // ソフトマスクと描画モードはSVGで表さず、かかった描画を数えるだけにする
+    fn set_soft_mask(&mut self, mask: Option<SoftMask<'a>>) {
+        self.soft_mask = mask.is_some();
+    }
+
+    fn set_blend_mode(&mut self, blend_mode: BlendMode) {
+        self.blend_mode = blend_mode != BlendMode::Normal;
+    }
+
+    fn draw_path(
+        &mut self,
+        path: &BezPath,
+        transform: Affine,
+        paint: &Paint<'a>,
+        draw_mode: &PathDrawMode,
+    ) {
+        if !self.visible() {
+            return;
+        }
+        let kind = if self.text_depth > 0 {
+            ItemKind::Text
+        } else {
+            ItemKind::Path
+        };
+        match draw_mode {
+            PathDrawMode::Fill(rule) => self.push_shape(kind, path, transform, paint, None, *rule),
+            PathDrawMode::Stroke(stroke) => self.push_shape(
+                kind,
+                path,
+                transform,
+                paint,
+                Some(stroke),
+                FillRule::NonZero,
+            ),
+        }
+    }
+
+    fn push_clip_path(&mut self, clip_path: &ClipPath) {
+        self.clip_count += 1;
+        let id = self.clip_count;
+        self.clips.push(Some(clip_path.path.bounding_box()));
+        let rule = match clip_path.fill {
+            FillRule::NonZero => "",
+            FillRule::EvenOdd => r#" clip-rule="evenodd""#,
+        };
+        self.items.push(Item::Open(format!(
+            r#"<clipPath id="clip{id}"><path d="{}"{rule}/></clipPath><g clip-path="url(#clip{id})">"#,
+            path_data(&clip_path.path)
+        )));
+    }
+
+    fn push_transparency_group(
+        &mut self,
+        opacity: f32,
+        mask: Option<SoftMask<'a>>,
+        blend_mode: BlendMode,
+    ) {
+        self.clips.push(None);
+        self.group_effects
+            .push(mask.is_some() || blend_mode != BlendMode::Normal);
+        self.items.push(Item::Open(if opacity < 1.0 {
+            format!(r#"<g opacity="{}">"#, number(f64::from(opacity)))
+        } else {
+            "<g>".to_string()
+        }));
+    }
+
+    fn draw_glyph(
+        &mut self,
+        glyph: &Glyph<'a>,
+        transform: Affine,
+        glyph_transform: Affine,
+        paint: &Paint<'a>,
+        draw_mode: &GlyphDrawMode,
+    ) {
+        if !self.visible() {
+            return;
+        }
+        match glyph {
+            // 文字はフォントに頼らず輪郭で書き出す
+            Glyph::Outline(outline) => {
+                let path = outline.outline();
+                let transform = transform * glyph_transform;
+                match draw_mode {
+                    GlyphDrawMode::Fill => self.push_shape(
+                        ItemKind::Text,
+                        &path,
+                        transform,
+                        paint,
+                        None,
+                        FillRule::NonZero,
+                    ),
+                    GlyphDrawMode::Stroke(stroke) => self.push_shape(
+                        ItemKind::Text,
+                        &path,
+                        transform,
+                        paint,
+                        Some(stroke),
+                        FillRule::NonZero,
+                    ),
+                    GlyphDrawMode::Invisible => {}
+                }
+            }
+            Glyph::Type3(glyph) => {
+                if matches!(draw_mode, GlyphDrawMode::Invisible) {
+                    return;
+                }
+                self.text_depth += 1;
+                glyph.interpret(self, transform, glyph_transform, paint);
+                self.text_depth -= 1;
+            }
+        }
+    }
+
+    fn draw_image(&mut self, image: Image<'a, '_>, transform: Affine) {
+        if !self.visible() || self.budget.limit_reached {
+            return;
+        }
+        let (width, height) = (image.width(), image.height());
+        if let Err(kind) = self.limits.check_image_count(self.budget.count) {
+            self.stop_at_limit(kind);
+            return;
+        }
+        if let Err(kind) = self.limits.check_image_dimensions(width, height) {
+            self.push_warning(kind);
+            self.image_index += 1;
+            return;
+        }
+        let estimated = self
+            .budget
+            .decoded_bytes
+            .saturating_add(estimated_decoded_bytes(width, height));
+        if let Err(kind) = self.limits.check_decoded_bytes(estimated) {
+            self.stop_at_limit(kind);
+            return;
+        }
+
+        let decoded = catch_quietly(|| {
+            let mut decoded = None;
+            match &image {
+                Image::Raster(raster) => raster.with_rgba(
+                    |rgb, alpha| decoded = Some(raster_to_dynamic_image(&rgb, alpha.as_ref())),
+                    None,
+                ),
+                Image::Stencil(stencil) => stencil.with_stencil(
+                    |mask, paint| decoded = Some(stencil_to_rgba(&mask, paint)),
+                    None,
+                ),
+            }
+            decoded
+        });
+        match decoded {
+            Ok(Some(Ok(decoded))) => self.push_image(decoded, transform, width, height),
+            Ok(Some(Err(kind))) => self.push_warning(kind),
+            Ok(None) => self.push_warning(ExtractImageWarningKind::ImageDecodeFailed {
+                object: None,
+                filter: None,
+            }),
+            Err(payload) => self.push_warning(ExtractImageWarningKind::ImageDecodePanicked {
+                message: panic_message(payload.as_ref()),
+            }),
+        }
+        self.image_index += 1;
+    }
+
+    fn pop_clip_path(&mut self) {
+        self.clips.pop();
+        self.items.push(Item::Close);
+    }
+
+    fn pop_transparency_group(&mut self) {
+        self.clips.pop();
+        self.group_effects.pop();
+        self.items.push(Item::Close);
+    }
+}
+
+// ステンシルマスクは塗りの色で、マスクの値を不透明度にする
+fn stencil_to_rgba(
+    mask: &LumaData,
+    paint: &Paint<'_>,
+) -> Result<DynamicImage, ExtractImageWarningKind> {
+    let [r, g, b, a] = match paint {
+        Paint::Color(color) => color.to_rgba().to_rgba8(),
+        Paint::Pattern(_) => [0, 0, 0, 255],
+    };
+    let data = mask
+        .data
+        .iter()
+        .flat_map(|&value| [r, g, b, ((u16::from(value) * u16::from(a)) / 255) as u8])
+        .collect();
+    let buffer: ImageBuffer<Rgba<u8>, Vec<u8>> =
+        ImageBuffer::from_raw(mask.width, mask.height, data)
+            .ok_or(ExtractImageWarningKind::InvalidAlphaBufferShape)?;
+    Ok(DynamicImage::ImageRgba8(buffer))
+}
+
+// 文字以外の図形と画像を近いものどうしでまとめ、十分な大きさのまとまりを図とする。
+// 図の周りの文字（軸ラベル、凡例）も含め、ページの上から順に返す
+fn detect_figures(items: &[Item], page: Rect) -> Vec<Rect> {
+    let page_area = page.area();
+    let mut clusters: Vec<Rect> = items
+        .iter()
+        .filter_map(|item| match item {
+            Item::Draw { kind, bounds, .. }
+                if *kind != ItemKind::Text
+                    && bounds.intersect(page).area() < BACKGROUND_COVERAGE * page_area =>
+            {
+                Some(bounds.intersect(page))
+            }
+            _ => None,
+        })
+        .collect();
+
+    // 近いまとまりどうしをつなぎ、広がったまとまりが新たに近づかなくなるまで繰り返す
+    loop {
+        let merged = merge_near_clusters(&clusters, page);
+        if merged.len() == clusters.len() {
+            break;
+        }
+        clusters = merged;
+    }
+
+    let mut figures: Vec<Rect> = clusters
+        .into_iter()
+        .filter(|cluster| cluster.width() >= MIN_FIGURE_SIZE && cluster.height() >= MIN_FIGURE_SIZE)
+        .map(|cluster| {
+            let labels = items.iter().filter_map(|item| match item {
+                Item::Draw {
+                    kind: ItemKind::Text,
+                    bounds,
+                    ..
+                } if near(cluster, *bounds, LABEL_MARGIN) => Some(*bounds),
+                _ => None,
+            });
+            labels
+                .fold(cluster, |figure, label| figure.union(label))
+                .inflate(FIGURE_PADDING, FIGURE_PADDING)
+                .intersect(page)
+        })
+        .collect();
+    figures.sort_by(|a, b| b.y1.total_cmp(&a.y1).then(a.x0.total_cmp(&b.x0)));
+    figures
+}
+
+// ページを格子に区切って各まとまりを重なる升目に登録し、同じ升目にあるものだけを比べる。
+// 互いに近いまとまりをすべてつないだ範囲を返す
+fn merge_near_clusters(clusters: &[Rect], page: Rect) -> Vec<Rect> {
+    let cell_width = FIGURE_CELL.max(page.width() / MAX_FIGURE_CELLS);
+    let cell_height = FIGURE_CELL.max(page.height() / MAX_FIGURE_CELLS);
+    let cells = |rect: Rect| {
+        let column = |x: f64| ((x - page.x0) / cell_width).floor().max(0.0) as usize;
+        let row = |y: f64| ((y - page.y0) / cell_height).floor().max(0.0) as usize;
+        let (x0, x1) = (column(rect.x0), column(rect.x1));
+        let (y0, y1) = (row(rect.y0), row(rect.y1));
+        (x0..=x1).flat_map(move |x| (y0..=y1).map(move |y| (x, y)))
+    };
+    let mut grid: HashMap<(usize, usize), Vec<usize>> = HashMap::new();
+    let mut parents: Vec<usize> = (0..clusters.len()).collect();
+    for (i, cluster) in clusters.iter().enumerate() {
+        // 左下へgapだけ広げた範囲どうしが重なれば近いので、その範囲で登録する
+        let inflated = Rect::new(
+            cluster.x0 - FIGURE_GAP,
+            cluster.y0 - FIGURE_GAP,
+            cluster.x1,
+            cluster.y1,
+        );
+        for cell in cells(inflated) {
+            let members = grid.entry(cell).or_default();
+            for &j in members.iter() {
+                let (a, b) = (find(&mut parents, i), find(&mut parents, j));
+                if a != b && near(*cluster, clusters[j], FIGURE_GAP) {
+                    parents[a.max(b)] = a.min(b);
+                }
+            }
+            members.push(i);
+        }
+    }
+
+    let mut merged: Vec<Option<Rect>> = vec![None; clusters.len()];
+    for (i, cluster) in clusters.iter().enumerate() {
+        let root = find(&mut parents, i);
+        merged[root] = Some(merged[root].map_or(*cluster, |rect| rect.union(*cluster)));
+    }
+    merged.into_iter().flatten().collect()
+}
+
+fn find(parents: &mut [usize], i: usize) -> usize {
+    let mut root = i;
+    while parents[root] != root {
+        root = parents[root];
+    }
+    parents[i] = root;
+    root
+}
+
+fn near(a: Rect, b: Rect, gap: f64) -> bool {
+    a.x0 - gap <= b.x1 && b.x0 <= a.x1 + gap && a.y0 - gap <= b.y1 && b.y0 <= a.y1 + gap
+}
+
+// regionの外にある描画は省き、ページ座標（y軸が上向き）からSVGの座標へ変換する
+fn write_svg(items: &[Item], region: Rect, rotation: u16) -> String {
+    let (width, height) = (region.width(), region.height());
+    let flip = Affine::new([1.0, 0.0, 0.0, -1.0, -region.x0, region.y1]);
+    // /Rotateは時計回り
+    let (rotate, svg_width, svg_height) = match rotation {
+        90 => (
+            Affine::new([0.0, 1.0, -1.0, 0.0, height, 0.0]),
+            height,
+            width,
+        ),
+        180 => (
+            Affine::new([-1.0, 0.0, 0.0, -1.0, width, height]),
+            width,
+            height,
+        ),
+        270 => (
+            Affine::new([0.0, -1.0, 1.0, 0.0, 0.0, width]),
+            height,
+            width,
+        ),
+        _ => (Affine::IDENTITY, width, height),
+    };
+
+    let mut svg = format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}pt" height="{h}pt" viewBox="0 0 {w} {h}"><g transform="{}">"#,
+        matrix(rotate * flip),
+        w = number(svg_width),
+        h = number(svg_height),
+    );
+    for item in items {
+        match item {
+            Item::Open(markup) => svg.push_str(markup),
+            Item::Close => svg.push_str("</g>"),
+            Item::Draw { bounds, markup, .. } => {
+                if is_in_region(*bounds, region) {
+                    svg.push_str(markup);
+                }
+            }
+        }
+    }
+    svg.push_str("</g></svg>\n");
+    svg
+}
+
+// 水平線・垂直線は面積がないので、範囲に入っているかを辺で調べる
+fn is_in_region(bounds: Rect, region: Rect) -> bool {
+    bounds.intersect(region).area() > 0.0
+        || ((bounds.width() == 0.0 || bounds.height() == 0.0) && near(bounds, region, 0.0))
+}
+
+fn path_data(path: &BezPath) -> String {
+    let mut data = String::new();
+    for element in path.elements() {
+        if !data.is_empty() {
+            data.push(' ');
+        }
+        let _ = match element {
+            PathEl::MoveTo(p) => write!(data, "M{} {}", number(p.x), number(p.y)),
+            PathEl::LineTo(p) => write!(data, "L{} {}", number(p.x), number(p.y)),
+            PathEl::QuadTo(p1, p2) => write!(
+                data,
+                "Q{} {} {} {}",
+                number(p1.x),
+                number(p1.y),
+                number(p2.x),
+                number(p2.y)
+            ),
+            PathEl::CurveTo(p1, p2, p3) => write!(
+                data,
+                "C{} {} {} {} {} {}",
+                number(p1.x),
+                number(p1.y),
+                number(p2.x),
+                number(p2.y),
+                number(p3.x),
+                number(p3.y)
+            ),
+            PathEl::ClosePath => write!(data, "Z"),
+        };
+    }
+    data
+}
+
+fn transform_attribute(transform: Affine) -> String {
+    if transform == Affine::IDENTITY {
+        String::new()
+    } else {
+        format!(r#" transform="{}""#, matrix(transform))
+    }
+}
+
+// 変換の係数は丸めると小さな単位の座標（グリフの輪郭など）が大きくずれるので、すべての桁を残す
+fn matrix(transform: Affine) -> String {
+    let coeffs = transform.as_coeffs().map(|value| {
+        if value == 0.0 {
+            "0".to_string()
+        } else {
+            value.to_string()
+        }
+    });
+    format!("matrix({})", coeffs.join(" "))
+}
+
+// 座標は1/1000単位までで十分なので、余分な桁と末尾の0を省く
+fn number(value: f64) -> String {
+    let text = format!("{value:.3}");
+    let text = text.trim_end_matches('0').trim_end_matches('.');
+    match text {
+        "-0" | "" => "0".to_string(),
+        _ => text.to_string(),
+    }
+}
+
+fn opacity(alpha: u8) -> String {
+    number(f64::from(alpha) / 255.0)
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let value = chunk.iter().enumerate().fold(0u32, |value, (i, &byte)| {
+            value | u32::from(byte) << (16 - 8 * i)
+        });
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(value >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use kurbo::{Affine, BezPath, Rect};
+
+    use super::{
+        Item, ItemKind, SvgOptions, base64, detect_figures, export_svg, matrix, number, path_data,
+        write_svg,
+    };
+    use crate::tests::single_page_pdf;
+
+    fn draw(kind: ItemKind, x0: f64, y0: f64, x1: f64, y1: f64) -> Item {
+        Item::Draw {
+            kind,
+            bounds: Rect::new(x0, y0, x1, y1),
+            markup: format!("<path id=\"{x0}-{y0}\"/>"),
+        }
+    }
+
+    #[test]
+    fn encodes_base64_with_padding() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+    }
+
+    #[test]
+    fn writes_compact_path_data() {
+        assert_eq!(number(1.0), "1");
+        assert_eq!(number(0.12345), "0.123");
+        assert_eq!(number(-0.0001), "0");
+        let mut path = BezPath::new();
+        path.move_to((0.0, 0.5));
+        path.line_to((10.0, 0.5));
+        path.close_path();
+        assert_eq!(path_data(&path), "M0 0.5 L10 0.5 Z");
+    }
+
+    #[test]
+    fn keeps_matrices_at_full_precision() {
+        assert_eq!(
+            matrix(Affine::new([0.0001234, 0.0, -0.0, -0.0001234, 72.5, 10.0])),
+            "matrix(0.0001234 0 0 -0.0001234 72.5 10)"
+        );
+    }
+
+    #[test]
+    fn merges_clusters_that_grow_close_to_others() {
+        let page = Rect::new(0.0, 0.0, 600.0, 800.0);
+        // 下の2つがつながって広がると、どちらとも離れている上の図形に近づく
+        let items = vec![
+            draw(ItemKind::Path, 200.0, 144.0, 240.0, 190.0),
+            draw(ItemKind::Path, 100.0, 100.0, 140.0, 140.0),
+            draw(ItemKind::Path, 144.0, 100.0, 300.0, 104.0),
+        ];
+        assert_eq!(
+            detect_figures(&items, page),
+            vec![Rect::new(98.0, 98.0, 302.0, 192.0)]
+        );
+    }
+
+    #[test]
+    fn counts_skipped_paints_per_figure() {
+        let pdf = single_page_pdf(
+            "",
+            "<< /Pattern << /P1 5 0 R >> >>",
+            "5 55 40 40 re S /Pattern cs /P1 scn 10 60 20 20 re f 0 g 55 5 40 40 re f",
+            &[
+                b"<< /PatternType 2 /Shading << /ShadingType 2 /ColorSpace /DeviceRGB \
+                /Coords [0 0 1 0] /Function << /FunctionType 2 /Domain [0 1] /C0 [1 0 0] \
+                /C1 [0 0 1] /N 1 >> >> >>"
+                    .to_vec(),
+            ],
+        );
+        let options = SvgOptions {
+            figures: true,
+            ..SvgOptions::default()
+        };
+        let result = export_svg(&pdf, &[], &options).unwrap();
+        let skipped: Vec<_> = result
+            .graphics
+            .iter()
+            .map(|graphic| graphic.skipped_paints)
+            .collect();
+        assert_eq!(skipped, [1, 0]);
+    }
+
+    #[test]
+    fn counts_drawing_with_ignored_blend_modes_and_soft_masks() {
+        let pdf = single_page_pdf(
+            "",
+            "<< /ExtGState << /Multiply << /BM /Multiply >> >> >>",
+            "0 g 10 10 20 20 re f q /Multiply gs 10 60 20 20 re f 60 60 20 20 re f Q \
+             60 10 20 20 re f",
+            &[],
+        );
+        let result = export_svg(&pdf, &[], &SvgOptions::default()).unwrap();
+        assert_eq!(result.graphics[0].ignored_effects, 2);
+        assert_eq!(result.graphics[0].skipped_paints, 0);
+    }
+
+    #[test]
+    fn groups_nearby_shapes_and_labels_into_figures() {
+        let page = Rect::new(0.0, 0.0, 600.0, 800.0);
+        let items = vec![
+            // 背景
+            draw(ItemKind::Path, 0.0, 0.0, 600.0, 800.0),
+            // 上の図: 枠と棒
+            draw(ItemKind::Path, 100.0, 500.0, 300.0, 700.0),
+            draw(ItemKind::Path, 120.0, 500.0, 140.0, 600.0),
+            // 軸ラベルと、図から離れた本文
+            draw(ItemKind::Text, 100.0, 490.0, 130.0, 498.0),
+            draw(ItemKind::Text, 100.0, 300.0, 500.0, 310.0),
+            // 下の図: 画像
+            draw(ItemKind::Image, 100.0, 100.0, 200.0, 200.0),
+            // 罫線だけ
+            draw(ItemKind::Path, 50.0, 400.0, 550.0, 400.0),
+        ];
+        assert_eq!(
+            detect_figures(&items, page),
+            vec![
+                Rect::new(98.0, 488.0, 302.0, 702.0),
+                Rect::new(98.0, 98.0, 202.0, 202.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn writes_only_items_inside_the_region() {
+        let items = vec![
+            Item::Open("<g>".to_string()),
+            draw(ItemKind::Path, 10.0, 10.0, 20.0, 20.0),
+            draw(ItemKind::Path, 100.0, 100.0, 120.0, 120.0),
+            Item::Close,
+        ];
+        assert_eq!(
+            write_svg(&items, Rect::new(0.0, 0.0, 50.0, 40.0), 0),
+            concat!(
+                r#"<svg xmlns="http://www.w3.org/2000/svg" width="50pt" height="40pt" viewBox="0 0 50 40">"#,
+                r#"<g transform="matrix(1 0 0 -1 0 40)"><g><path id="10-10"/></g></g></svg>"#,
+                "\n"
+            )
+        );
+        // 90度回転したページは幅と高さが入れ替わる
+        assert!(
+            write_svg(&items, Rect::new(0.0, 0.0, 50.0, 40.0), 90).starts_with(
+                r#"<svg xmlns="http://www.w3.org/2000/svg" width="40pt" height="50pt""#
+            )
+        );
+    }
+}
@@ -0,0 +1,59 @@
+// PDFDocEncodingでLatin-1と異なる0x80〜0x9Eの文字
+const PDF_DOC_HIGH: [char; 31] = [
+    '\u{2022}', '\u{2020}', '\u{2021}', '\u{2026}', '\u{2014}', '\u{2013}', '\u{0192}', '\u{2044}',
+    '\u{2039}', '\u{203A}', '\u{2212}', '\u{2030}', '\u{201E}', '\u{201C}', '\u{201D}', '\u{2018}',
+    '\u{2019}', '\u{201A}', '\u{2122}', '\u{FB01}', '\u{FB02}', '\u{0141}', '\u{0152}', '\u{0160}',
+    '\u{0178}', '\u{017D}', '\u{0131}', '\u{0142}', '\u{0153}', '\u{0161}', '\u{017E}',
+];
+
+// PDFのテキスト文字列（UTF-16BE、UTF-8、PDFDocEncoding）を文字列にする
+pub(crate) fn decode_text_string(bytes: &[u8]) -> String {
+    if let Some(utf16) = bytes.strip_prefix(&[0xFE, 0xFF]) {
+        let units: Vec<u16> = utf16
+            .chunks_exact(2)
+            .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+            .collect();
+        return String::from_utf16_lossy(&units);
+    }
+    if let Some(utf8) = bytes.strip_prefix(&[0xEF, 0xBB, 0xBF]) {
+        return String::from_utf8_lossy(utf8).into_owned();
+    }
+    bytes
+        .iter()
+        .map(|&byte| match byte {
+            0x80..=0x9E => PDF_DOC_HIGH[usize::from(byte - 0x80)],
+            0xA0 => '\u{20AC}',
+            _ => char::from(byte),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::decode_text_string;
+
+    #[test]
+    fn decodes_utf16_with_bom() {
+        assert_eq!(
+            decode_text_string(&[0xFE, 0xFF, 0x30, 0xEC, 0x30, 0xA4, 0x30, 0xE4]),
+            "レイヤ"
+        );
+    }
+
+    #[test]
+    fn decodes_utf8_with_bom() {
+        assert_eq!(
+            decode_text_string(&[0xEF, 0xBB, 0xBF, 0xE5, 0x9C, 0xB0, 0xE5, 0x9B, 0xB3]),
+            "地図"
+        );
+    }
+
+    #[test]
+    fn decodes_pdf_doc_encoding() {
+        assert_eq!(decode_text_string(b"Base map"), "Base map");
+        assert_eq!(
+            decode_text_string(&[0x80, 0x20, 0xE9, 0xA0]),
+            "\u{2022} \u{e9}\u{20AC}"
+        );
+    }
+}
@@ -0,0 +1,188 @@
+use hayro_syntax::object::Stream;
+use hayro_syntax::object::dict::keys::{HEIGHT, THUMB, WIDTH};
+use image::DynamicImage;
+
+use crate::image_objects::{ImageObject, check_supported, decode_image_object};
+use crate::limits::estimated_decoded_bytes;
+use crate::page_labels::read_page_labels;
+use crate::{
+    ExtractError, ExtractImageWarning, ExtractOptions, ObjectRef, PageRange, open_pdf,
+    select_pages_within_limit,
+};
+
+// ページ辞書の/Thumbに埋め込まれた縮小画像
+#[derive(Debug)]
+pub struct PageThumbnail {
+    pub page_index: usize,
+    pub page_label: String,
+    pub image: DynamicImage,
+    // /Thumbの参照（直接オブジェクトならNone）
+    pub object: Option<ObjectRef>,
+}
+
+#[derive(Debug)]
+pub struct ExtractThumbnailsResult {
+    pub thumbnails: Vec<PageThumbnail>,
+    pub errors: Vec<ExtractImageWarning>,
+}
+
+// ページの内容は解釈せず、/Thumbの画像だけをデコードする。
+// optionsのうちlimitsとcancellationだけを使う
+pub fn extract_thumbnails(
+    pdf_bytes: &[u8],
+    page_ranges: &[PageRange],
+    options: &ExtractOptions,
+) -> Result<ExtractThumbnailsResult, ExtractError> {
+    let pdf = open_pdf(pdf_bytes)?;
+    let pages = pdf.pages();
+    let page_labels = read_page_labels(pdf.xref(), pages.len());
+    let (selected_pages, _) = select_pages_within_limit(pages.len(), page_ranges, &options.limits)?;
+    let limits = &options.limits;
+
+    let mut result = ExtractThumbnailsResult {
+        thumbnails: Vec::new(),
+        errors: Vec::new(),
+    };
+    let mut decoded_bytes: u64 = 0;
+    for (i, page) in pages.iter().enumerate() {
+        if !selected_pages[i] {
+            continue;
+        }
+        if options.cancellation.is_cancelled() {
+            return Err(ExtractError::Cancelled);
+        }
+        let Some(stream) = page.raw().get::<Stream<'_>>(THUMB) else {
+            continue;
+        };
+        let thumbnail = ImageObject {
+            object_ref: page.raw().get_ref(THUMB).map(ObjectRef::from),
+            stream,
+        };
+        let warning = |kind| ExtractImageWarning {
+            page_index: i,
+            image_index: None,
+            kind,
+        };
+
+        if let Err(kind) = limits.check_image_count(result.thumbnails.len()) {
+            result.errors.push(warning(kind));
+            break;
+        }
+        if let Err(kind) = check_supported(&thumbnail) {
+            result.errors.push(warning(kind));
+            continue;
+        }
+        let dict = thumbnail.stream.dict();
+        let (width, height) = (
+            dict.get::<u32>(WIDTH).unwrap_or(0),
+            dict.get::<u32>(HEIGHT).unwrap_or(0),
+        );
+        if let Err(kind) = limits.check_image_dimensions(width, height) {
+            result.errors.push(warning(kind));
+            continue;
+        }
+        let estimated = decoded_bytes.saturating_add(estimated_decoded_bytes(width, height));
+        if let Err(kind) = limits.check_decoded_bytes(estimated) {
+            result.errors.push(warning(kind));
+            break;
+        }
+
+        let image = match decode_image_object(&thumbnail, page.resources(), pdf.xref()) {
+            Ok(image) => image,
+            Err(kind) => {
+                result.errors.push(warning(kind));
+                continue;
+            }
+        };
+        // デコーダが宣言と異なるサイズを返すことがあるので実サイズでも確認する
+        if let Err(kind) = limits.check_image_dimensions(image.width(), image.height()) {
+            result.errors.push(warning(kind));
+            continue;
+        }
+        let total = decoded_bytes.saturating_add(image.as_bytes().len() as u64);
+        if let Err(kind) = limits.check_decoded_bytes(total) {
+            result.errors.push(warning(kind));
+            break;
+        }
+        decoded_bytes = total;
+        result.thumbnails.push(PageThumbnail {
+            page_index: i,
+            page_label: page_labels.get(i).cloned().unwrap_or_default(),
+            image,
+            object: thumbnail.object_ref,
+        });
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::extract_thumbnails;
+    use crate::tests::{build_pdf, pixel_image, stream_object};
+    use crate::{ExtractImageWarningKind, ExtractLimits, ExtractOptions};
+
+    // 1ページ目と3ページ目だけに/Thumbがある3ページのPDF
+    fn pdf_with_thumbnails() -> Vec<u8> {
+        let page = |thumb: &str| {
+            format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 100 100] /Resources << >> \
+                 /Contents 6 0 R {thumb} >>"
+            )
+            .into_bytes()
+        };
+        build_pdf(&[
+            b"<< /Type /Catalog /Pages 2 0 R >>".to_vec(),
+            b"<< /Type /Pages /Kids [3 0 R 4 0 R 5 0 R] /Count 3 >>".to_vec(),
+            page("/Thumb 7 0 R"),
+            page(""),
+            page("/Thumb 8 0 R"),
+            stream_object("", b""),
+            pixel_image([255, 0, 0]),
+            pixel_image([0, 0, 255]),
+        ])
+    }
+
+    #[test]
+    fn extracts_thumbnails_of_pages_that_have_one() {
+        let result =
+            extract_thumbnails(&pdf_with_thumbnails(), &[], &ExtractOptions::default()).unwrap();
+
+        let thumbnails: Vec<_> = result
+            .thumbnails
+            .iter()
+            .map(|thumbnail| {
+                (
+                    thumbnail.page_index,
+                    thumbnail.object.map(|object| object.number),
+                    thumbnail.image.as_bytes().to_vec(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            thumbnails,
+            [(0, Some(7), vec![255, 0, 0]), (2, Some(8), vec![0, 0, 255])]
+        );
+        assert!(result.errors.is_empty());
+    }
+
+    #[test]
+    fn stops_at_the_image_limit() {
+        let options = ExtractOptions {
+            limits: ExtractLimits {
+                max_images: Some(1),
+                ..ExtractLimits::default()
+            },
+            ..ExtractOptions::default()
+        };
+        let result = extract_thumbnails(&pdf_with_thumbnails(), &[], &options).unwrap();
+
+        assert_eq!(result.thumbnails.len(), 1);
+        assert_eq!(result.errors.len(), 1);
+        assert_eq!(result.errors[0].page_index, 2);
+        assert_eq!(
+            result.errors[0].kind,
+            ExtractImageWarningKind::ImageLimitReached
+        );
+    }
+}
@@ -0,0 +1,428 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashSet};
+use std::hash::{Hash, Hasher};
+
+use hayro_interpret::CacheKey;
+
+use hayro_syntax::Pdf;
+use hayro_syntax::content::TypedIter;
+use hayro_syntax::content::ops::{
+    NonStrokeColorNamed, SetGraphicsState, StrokeColorNamed, TextFont, TypedInstruction, XObject,
+};
+use hayro_syntax::object::dict::keys::{
+    CHAR_PROCS, FORM, G, HEIGHT, IMAGE, MASK, RESOURCES, SMASK, SUBTYPE, THUMB, TYPE3, WIDTH,
+};
+use hayro_syntax::object::{Dict, Name, Object, ObjectIdentifier, Stream};
+use hayro_syntax::page::Resources;
+use image::DynamicImage;
+
+use crate::annotations::annotation_appearances;
+use crate::image_objects::{
+    ImageObject, ImageObjectIndex, check_supported, decode_image_object, object_ref_of,
+    soft_mask_size,
+};
+use crate::{ExtractImageWarningKind, ExtractOptions, ObjectRef};
+
+// フォームXObjectやパターンが入れ子になっていても止まるように深さを制限する
+const MAX_NESTING_DEPTH: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnusedImageKind {
+    // ページ（注釈の外観、フォームXObject、パターンを含む）のリソースにあるが、どこでも描かれない
+    Unpainted,
+    // どのページからもたどれない画像ストリーム（編集で残ったものなど）
+    Orphaned,
+}
+
+// 描かれていない画像XObject
+#[derive(Debug)]
+pub struct UnusedImage {
+    pub object: ObjectRef,
+    pub kind: UnusedImageKind,
+    // リソースに画像を持つページ（Orphanedなら空）
+    pub pages: Vec<usize>,
+    pub image: DynamicImage,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnusedImageWarning {
+    pub object: ObjectRef,
+    pub kind: ExtractImageWarningKind,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct UnusedImages {
+    pub(crate) images: Vec<UnusedImage>,
+    pub(crate) warnings: Vec<UnusedImageWarning>,
+}
+
+// ページの選択にかかわらず文書全体を調べる（ほかのページで描かれる画像は未使用ではない）。
+// image_countとdecoded_bytesは本文から取り出した分で、上限はそれと合わせて数える
+pub(crate) fn collect_unused_images(
+    pdf: &Pdf,
+    options: &ExtractOptions,
+    mut image_count: usize,
+    mut decoded_bytes: u64,
+) -> UnusedImages {
+    let mut result = UnusedImages::default();
+    let Some(first_page) = pdf.pages().first() else {
+        return result;
+    };
+
+    let mut scanner = PaintScanner::default();
+    // 画像ストリームと、リソースにその画像を持つページ
+    let mut listed: BTreeMap<ObjectRef, (Stream<'_>, Vec<usize>)> = BTreeMap::new();
+    // 描かれる画像の一部として使われるストリーム（ソフトマスク、マスク、サムネイル）
+    let mut attached: HashSet<ObjectIdentifier> = HashSet::new();
+    for (i, page) in pdf.pages().iter().enumerate() {
+        let mut index = ImageObjectIndex::default();
+        index.add_resources(page.resources());
+        if let Some(content) = page.page_stream() {
+            scanner.scan(content, page.resources(), 0);
+        }
+        for appearance in annotation_appearances(page) {
+            let resources = Resources::from_parent(
+                appearance
+                    .stream
+                    .dict()
+                    .get::<Dict<'_>>(RESOURCES)
+                    .unwrap_or_default(),
+                page.resources().clone(),
+            );
+            index.add_resources(&resources);
+            if let Ok(content) = appearance.stream.decoded() {
+                scanner.scan(&content, &resources, 0);
+            }
+        }
+        for image in index.images() {
+            let Some(id) = image.stream.dict().obj_id() else {
+                continue;
+            };
+            let (_, pages) = listed
+                .entry(object_ref_of(id))
+                .or_insert_with(|| (image.stream.clone(), Vec::new()));
+            if pages.last() != Some(&i) {
+                pages.push(i);
+            }
+        }
+        if let Some(thumbnail) = page.raw().get_ref(THUMB) {
+            attached.insert(thumbnail.into());
+        }
+    }
+
+    let mut orphaned: Vec<(ObjectRef, Stream<'_>)> = Vec::new();
+    for object in pdf.objects() {
+        let Object::Stream(stream) = object else {
+            continue;
+        };
+        let dict = stream.dict();
+        if dict.get::<Name<'_>>(SUBTYPE).as_deref() != Some(IMAGE) {
+            continue;
+        }
+        for key in [SMASK, MASK] {
+            if let Some(mask) = dict.get_ref(key) {
+                attached.insert(mask.into());
+            }
+        }
+        if let Some(id) = dict.obj_id()
+            && !listed.contains_key(&object_ref_of(id))
+        {
+            orphaned.push((object_ref_of(id), stream.clone()));
+        }
+    }
+    orphaned.sort_by_key(|(object, _)| *object);
+
+    let unpainted = listed
+        .into_iter()
+        .map(|(object, (stream, pages))| (object, stream, pages, UnusedImageKind::Unpainted));
+    let orphaned = orphaned
+        .into_iter()
+        .map(|(object, stream)| (object, stream, Vec::new(), UnusedImageKind::Orphaned));
+    for (object, stream, pages, kind) in unpainted.chain(orphaned) {
+        let Some(id) = stream.dict().obj_id() else {
+            continue;
+        };
+        if scanner.painted.contains(&id) || attached.contains(&id) {
+            continue;
+        }
+        if options.cancellation.is_cancelled() {
+            break;
+        }
+        let warning = |kind| UnusedImageWarning { object, kind };
+        let image = ImageObject {
+            object_ref: Some(object),
+            stream,
+        };
+
+        let limits = &options.limits;
+        if let Err(kind) = limits.check_image_count(image_count) {
+            result.warnings.push(warning(kind));
+            break;
+        }
+        if let Err(kind) = check_supported(&image) {
+            result.warnings.push(warning(kind));
+            continue;
+        }
+        let dict = image.stream.dict();
+        let (width, height) = (
+            dict.get::<u32>(WIDTH).unwrap_or(0),
+            dict.get::<u32>(HEIGHT).unwrap_or(0),
+        );
+        let estimated = match limits.check_image_with_mask(width, height, soft_mask_size(&image)) {
+            Ok(estimated) => decoded_bytes.saturating_add(estimated),
+            Err(kind) => {
+                result.warnings.push(warning(kind));
+                continue;
+            }
+        };
+        if let Err(kind) = limits.check_decoded_bytes(estimated) {
+            result.warnings.push(warning(kind));
+            break;
+        }
+
+        // 画像XObjectの色空間は名前付きリソースを参照しないので、どのページのリソースでもよい
+        match decode_image_object(&image, first_page.resources(), pdf.xref()) {
+            Ok(decoded) => {
+                image_count += 1;
+                decoded_bytes = decoded_bytes.saturating_add(decoded.as_bytes().len() as u64);
+                result.images.push(UnusedImage {
+                    object,
+                    kind,
+                    pages,
+                    image: decoded,
+                });
+            }
+            Err(kind) => result.warnings.push(warning(kind)),
+        }
+    }
+    result
+}
+
+// 内容ストリームを解釈せずに演算子だけを読み、Doで描かれる画像XObjectを集める。
+// フォームXObject、パターン、Type3フォントのグリフ、ソフトマスクの中もたどる
+#[derive(Default)]
+struct PaintScanner {
+    painted: HashSet<ObjectIdentifier>,
+    // 自分の/Resourcesにない名前は呼び出し元のリソースで引くので、呼び出し元ごとに1回たどる
+    visited: HashSet<(ObjectIdentifier, u64)>,
+}
+
+impl PaintScanner {
+    fn scan<'a>(&mut self, content: &[u8], resources: &Resources<'a>, depth: usize) {
+        if depth > MAX_NESTING_DEPTH {
+            return;
+        }
+        for instruction in TypedIter::new(content) {
+            match instruction {
+                TypedInstruction::XObject(XObject(name)) => {
+                    let Some(stream) = resources.get_x_object(name) else {
+                        continue;
+                    };
+                    match stream.dict().get::<Name<'_>>(SUBTYPE).as_deref() {
+                        Some(IMAGE) => {
+                            if let Some(id) = stream.dict().obj_id() {
+                                self.painted.insert(id);
+                            }
+                        }
+                        Some(FORM) => self.scan_stream(&stream, resources, depth),
+                        _ => {}
+                    }
+                }
+                TypedInstruction::NonStrokeColorNamed(NonStrokeColorNamed(_, Some(name)))
+                | TypedInstruction::StrokeColorNamed(StrokeColorNamed(_, Some(name))) => {
+                    // シェーディングパターンは辞書なので画像を含まない
+                    if let Some(pattern) = resources
+                        .get_pattern(name)
+                        .and_then(|pattern| pattern.into_stream())
+                    {
+                        self.scan_stream(&pattern, resources, depth);
+                    }
+                }
+                TypedInstruction::TextFont(TextFont(name, _)) => {
+                    if let Some(font) = resources.get_font(name)
+                        && font.get::<Name<'_>>(SUBTYPE).as_deref() == Some(TYPE3)
+                    {
+                        self.scan_type3_font(&font, resources, depth);
+                    }
+                }
+                TypedInstruction::SetGraphicsState(SetGraphicsState(name)) => {
+                    if let Some(group) = resources
+                        .get_ext_g_state(name)
+                        .and_then(|state| state.get::<Dict<'_>>(SMASK))
+                        .and_then(|mask| mask.get::<Stream<'_>>(G))
+                    {
+                        self.scan_stream(&group, resources, depth);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn scan_stream<'a>(&mut self, stream: &Stream<'a>, parent: &Resources<'a>, depth: usize) {
+        if let Some(id) = stream.dict().obj_id()
+            && !self.visited.insert((id, resources_key(parent)))
+        {
+            return;
+        }
+        let Ok(content) = stream.decoded() else {
+            return;
+        };
+        let resources = Resources::from_parent(
+            stream.dict().get::<Dict<'_>>(RESOURCES).unwrap_or_default(),
+            parent.clone(),
+        );
+        self.scan(&content, &resources, depth + 1);
+    }
+
+    // どのグリフが使われるかは文字列を読まないと分からないので、選ばれたフォントのグリフはすべて描かれるとみなす
+    fn scan_type3_font<'a>(&mut self, font: &Dict<'a>, parent: &Resources<'a>, depth: usize) {
+        if let Some(id) = font.obj_id()
+            && !self.visited.insert((id, resources_key(parent)))
+        {
+            return;
+        }
+        let font_resources = Resources::from_parent(
+            font.get::<Dict<'_>>(RESOURCES).unwrap_or_default(),
+            parent.clone(),
+        );
+        let char_procs = font.get::<Dict<'a>>(CHAR_PROCS).unwrap_or_default();
+        for glyph in char_procs.keys() {
+            if let Some(procedure) = char_procs.get::<Stream<'a>>(glyph) {
+                self.scan_stream(&procedure, &font_resources, depth);
+            }
+        }
+    }
+}
+
+// 名前を引く順に並んだリソース辞書。同じ辞書が後ろに繰り返されても結果は変わらないので、
+// 再帰するフォームでも同じキーになる
+fn resources_key(resources: &Resources<'_>) -> u64 {
+    let mut levels: Vec<[u128; 4]> = Vec::new();
+    let mut current = Some(resources);
+    while let Some(resources) = current {
+        let level = [
+            resources.x_objects.cache_key(),
+            resources.patterns.cache_key(),
+            resources.fonts.cache_key(),
+            resources.ext_g_states.cache_key(),
+        ];
+        if !levels.contains(&level) {
+            levels.push(level);
+        }
+        current = resources.parent();
+    }
+    let mut hasher = DefaultHasher::new();
+    levels.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::UnusedImageKind;
+    use crate::tests::{build_pdf, pixel_image, single_page_pdf, stream_object};
+    use crate::{ExtractImagesResult, ExtractOptions, extract_images_with_options};
+
+    fn extract_unused(pdf: &[u8]) -> ExtractImagesResult {
+        let options = ExtractOptions {
+            include_unused: true,
+            ..ExtractOptions::default()
+        };
+        extract_images_with_options(pdf, &[], &options).unwrap()
+    }
+
+    #[test]
+    fn reports_unpainted_and_orphaned_images() {
+        // /Im2はリソースにあるだけで描かれず、7 0 Rはどこからも参照されない
+        let pdf = single_page_pdf(
+            "",
+            "<< /XObject << /Im1 5 0 R /Im2 6 0 R >> >>",
+            "/Im1 Do",
+            &[
+                pixel_image([255, 0, 0]),
+                pixel_image([0, 255, 0]),
+                pixel_image([0, 0, 255]),
+            ],
+        );
+        let result = extract_unused(&pdf);
+
+        assert_eq!(result.images.len(), 1);
+        let unused: Vec<_> = result
+            .unused_images
+            .iter()
+            .map(|image| (image.object.number, image.kind, image.pages.clone()))
+            .collect();
+        assert_eq!(
+            unused,
+            [
+                (6, UnusedImageKind::Unpainted, vec![0]),
+                (7, UnusedImageKind::Orphaned, vec![]),
+            ]
+        );
+        assert_eq!(result.unused_images[0].image.as_bytes(), [0, 255, 0]);
+    }
+
+    #[test]
+    fn ignores_masks_and_thumbnails() {
+        let gray = |value: u8| {
+            stream_object(
+                "/Type /XObject /Subtype /Image /Width 1 /Height 1 /ColorSpace /DeviceGray \
+                 /BitsPerComponent 8",
+                &[value],
+            )
+        };
+        let pdf = build_pdf(&[
+            b"<< /Type /Catalog /Pages 2 0 R >>".to_vec(),
+            b"<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_vec(),
+            b"<< /Type /Page /Parent 2 0 R /MediaBox [0 0 100 100] \
+              /Resources << /XObject << /Im1 5 0 R /Im2 6 0 R >> >> /Contents 4 0 R /Thumb 9 0 R >>"
+                .to_vec(),
+            stream_object("", b"/Im1 Do /Im2 Do"),
+            stream_object(
+                "/Type /XObject /Subtype /Image /Width 1 /Height 1 /ColorSpace /DeviceRGB \
+                 /BitsPerComponent 8 /SMask 7 0 R",
+                &[255, 0, 0],
+            ),
+            stream_object(
+                "/Type /XObject /Subtype /Image /Width 1 /Height 1 /ColorSpace /DeviceRGB \
+                 /BitsPerComponent 8 /Mask 8 0 R",
+                &[0, 255, 0],
+            ),
+            gray(128),
+            stream_object(
+                "/Type /XObject /Subtype /Image /Width 1 /Height 1 /ImageMask true",
+                &[0],
+            ),
+            pixel_image([0, 0, 255]),
+        ]);
+        let result = extract_unused(&pdf);
+
+        assert_eq!(result.images.len(), 2);
+        assert!(result.unused_images.is_empty());
+        assert!(result.unused_errors.is_empty());
+    }
+
+    #[test]
+    fn scans_forms_again_under_other_resources() {
+        // Fm1には/Resourcesがないので、/Imはページでは6 0 R、Fm2の中では7 0 Rになる
+        let pdf = single_page_pdf(
+            "",
+            "<< /XObject << /Fm1 5 0 R /Fm2 8 0 R /Im 6 0 R >> >>",
+            "/Fm1 Do /Fm2 Do",
+            &[
+                stream_object("/Type /XObject /Subtype /Form /BBox [0 0 1 1]", b"/Im Do"),
+                pixel_image([255, 0, 0]),
+                pixel_image([0, 0, 255]),
+                stream_object(
+                    "/Type /XObject /Subtype /Form /BBox [0 0 1 1] \
+                     /Resources << /XObject << /Fm1 5 0 R /Im 7 0 R >> >>",
+                    b"/Fm1 Do",
+                ),
+            ],
+        );
+        let result = extract_unused(&pdf);
+
+        assert_eq!(result.images.len(), 2);
+        assert!(result.unused_images.is_empty());
+    }
+}
@@ -0,0 +1,330 @@
+use hayro_syntax::object::Stream;
+use thiserror::Error;
+
+use crate::limits::decode_stream_within;
+use crate::text::decode_text_string;
+
+// PNGのiTXtチャンクでXMPを示す名前
+const PNG_XMP_KEYWORD: &[u8] = b"XML:com.adobe.xmp";
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+
+// 画像XObjectの/Metadataに埋め込まれたXMP
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct XmpMetadata {
+    // XMPパケット全体（<?xpacket ?>を含むことがある）
+    pub raw: String,
+    // dc:title（x-defaultの言語、なければ最初のもの）
+    pub title: Option<String>,
+    // dc:creatorの順
+    pub creators: Vec<String>,
+    // dc:rights（x-defaultの言語、なければ最初のもの）
+    pub rights: Option<String>,
+    // dc:source
+    pub source: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum XmpEmbedError {
+    #[error("data is not a PNG file")]
+    InvalidPng,
+}
+
+// 画像のXMPパケットはこれより大きくならない（大きなものは読まない）
+const MAX_XMP_BYTES: u64 = 1024 * 1024;
+
+// 読めない/Metadataは画像の取り出しを妨げないよう無視する
+pub(crate) fn read_xmp(stream: &Stream<'_>) -> Option<XmpMetadata> {
+    let bytes = decode_stream_within(stream, MAX_XMP_BYTES).ok()?;
+    // XMPは通常UTF-8だが、BOM付きのUTF-16も許される
+    let raw = if bytes.starts_with(&[0xFE, 0xFF]) {
+        decode_text_string(&bytes)
+    } else if let Some(utf16) = bytes.strip_prefix(&[0xFF, 0xFE]) {
+        let units: Vec<u16> = utf16
+            .chunks_exact(2)
+            .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+            .collect();
+        String::from_utf16_lossy(&units)
+    } else {
+        String::from_utf8_lossy(bytes.strip_prefix(&[0xEF, 0xBB, 0xBF]).unwrap_or(&bytes))
+            .into_owned()
+    };
+    if raw.trim().is_empty() {
+        return None;
+    }
+    Some(parse_xmp(raw))
+}
+
+// XMLの名前空間は解決せず、慣例どおりの"dc:"接頭辞の要素と属性だけを読む
+pub(crate) fn parse_xmp(raw: String) -> XmpMetadata {
+    let title = language_alternative(&property_items(&raw, "dc:title"));
+    let creators = property_items(&raw, "dc:creator")
+        .into_iter()
+        .map(|item| item.text)
+        .collect();
+    let rights = language_alternative(&property_items(&raw, "dc:rights"));
+    let source = property_items(&raw, "dc:source")
+        .into_iter()
+        .next()
+        .map(|item| item.text);
+    XmpMetadata {
+        raw,
+        title,
+        creators,
+        rights,
+        source,
+    }
+}
+
+struct PropertyItem {
+    language: Option<String>,
+    text: String,
+}
+
+// <dc:x>text</dc:x>、<dc:x><rdf:Alt|Seq|Bag><rdf:li>…</rdf:li></…></dc:x>、dc:x="text" のどれか
+fn property_items(xml: &str, name: &str) -> Vec<PropertyItem> {
+    let mut items = Vec::new();
+    let open = format!("<{name}");
+    let close = format!("</{name}>");
+    let mut rest = xml;
+    while let Some(start) = rest.find(&open) {
+        let after = &rest[start + open.len()..];
+        rest = after;
+        if !after.starts_with(|c: char| c == '>' || c.is_whitespace()) {
+            continue;
+        }
+        let Some(tag_end) = after.find('>') else {
+            break;
+        };
+        if after[..tag_end].ends_with('/') {
+            continue;
+        }
+        let content = &after[tag_end + 1..];
+        let Some(content_end) = content.find(&close) else {
+            break;
+        };
+        let content = &content[..content_end];
+        rest = &after[tag_end + 1 + content_end..];
+        if content.contains("<rdf:li") {
+            items.extend(list_items(content));
+        } else if !content.contains('<') {
+            push_text(&mut items, None, content);
+        }
+    }
+    if items.is_empty()
+        && let Some(value) = attribute_value(xml, name)
+    {
+        push_text(&mut items, None, value);
+    }
+    items
+}
+
+fn list_items(content: &str) -> Vec<PropertyItem> {
+    let mut items = Vec::new();
+    let mut rest = content;
+    while let Some(start) = rest.find("<rdf:li") {
+        let after = &rest[start + "<rdf:li".len()..];
+        let Some(tag_end) = after.find('>') else {
+            break;
+        };
+        let tag = &after[..tag_end];
+        if tag.ends_with('/') {
+            rest = &after[tag_end + 1..];
+            continue;
+        }
+        let text = &after[tag_end + 1..];
+        let Some(text_end) = text.find("</rdf:li>") else {
+            break;
+        };
+        push_text(
+            &mut items,
+            attribute_value(tag, "xml:lang"),
+            &text[..text_end],
+        );
+        rest = &text[text_end..];
+    }
+    items
+}
+
+fn push_text(items: &mut Vec<PropertyItem>, language: Option<&str>, text: &str) {
+    let text = unescape(text.trim());
+    if !text.is_empty() {
+        items.push(PropertyItem {
+            language: language.map(str::to_string),
+            text,
+        });
+    }
+}
+
+fn language_alternative(items: &[PropertyItem]) -> Option<String> {
+    items
+        .iter()
+        .find(|item| item.language.as_deref() == Some("x-default"))
+        .or_else(|| items.first())
+        .map(|item| item.text.clone())
+}
+
+fn attribute_value<'a>(xml: &'a str, name: &str) -> Option<&'a str> {
+    let mut rest = xml;
+    while let Some(start) = rest.find(name) {
+        let before = rest[..start].chars().next_back();
+        let after = rest[start + name.len()..].trim_start();
+        rest = &rest[start + name.len()..];
+        if !before.is_some_and(char::is_whitespace) {
+            continue;
+        }
+        let Some(value) = after.strip_prefix('=') else {
+            continue;
+        };
+        let value = value.trim_start();
+        let Some(quote) = value.chars().next().filter(|c| *c == '"' || *c == '\'') else {
+            continue;
+        };
+        let value = &value[1..];
+        return value.find(quote).map(|end| &value[..end]);
+    }
+    None
+}
+
+fn unescape(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let Some(end) = rest.find(';') else {
+            break;
+        };
+        let entity = &rest[1..end];
+        let decoded = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => entity
+                .strip_prefix("#x")
+                .or_else(|| entity.strip_prefix("#X"))
+                .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                .or_else(|| entity.strip_prefix('#').and_then(|dec| dec.parse().ok()))
+                .and_then(char::from_u32),
+        };
+        match decoded {
+            Some(c) => {
+                result.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                result.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+// IHDRの直後にXMPのiTXtチャンク（無圧縮）を入れる
+pub fn embed_xmp_in_png(png: &[u8], xmp: &str) -> Result<Vec<u8>, XmpEmbedError> {
+    if !png.starts_with(&PNG_SIGNATURE) || png.get(12..16) != Some(b"IHDR".as_slice()) {
+        return Err(XmpEmbedError::InvalidPng);
+    }
+    let ihdr_length = u32::from_be_bytes([png[8], png[9], png[10], png[11]]) as usize;
+    let ihdr_end = PNG_SIGNATURE.len() + 12 + ihdr_length;
+    if png.len() < ihdr_end {
+        return Err(XmpEmbedError::InvalidPng);
+    }
+
+    // キーワード、圧縮フラグと圧縮方式、言語タグ、翻訳したキーワード、本文
+    let mut chunk_data = PNG_XMP_KEYWORD.to_vec();
+    chunk_data.extend_from_slice(&[0, 0, 0, 0, 0]);
+    chunk_data.extend_from_slice(xmp.as_bytes());
+
+    let mut output = Vec::with_capacity(png.len() + chunk_data.len() + 12);
+    output.extend_from_slice(&png[..ihdr_end]);
+    output.extend_from_slice(&(chunk_data.len() as u32).to_be_bytes());
+    let chunk_start = output.len();
+    output.extend_from_slice(b"iTXt");
+    output.extend_from_slice(&chunk_data);
+    let crc = crc32(&output[chunk_start..]);
+    output.extend_from_slice(&crc.to_be_bytes());
+    output.extend_from_slice(&png[ihdr_end..]);
+    Ok(output)
+}
+
+// PNGのチャンクのCRC（ISO 3309）
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFF_u32;
+    for &byte in bytes {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use image::{DynamicImage, ImageFormat};
+
+    use super::{XmpEmbedError, crc32, embed_xmp_in_png, parse_xmp};
+
+    const XMP: &str = r#"<?xpacket begin="" id="W5M0MpCehiHzreSzNTczkc9d"?>
+<x:xmpmeta xmlns:x="adobe:ns:meta/"><rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+<rdf:Description rdf:about="" xmlns:dc="http://purl.org/dc/elements/1.1/" dc:source="Archive &amp; Co.">
+<dc:title><rdf:Alt><rdf:li xml:lang="ja">港</rdf:li><rdf:li xml:lang="x-default">Harbor</rdf:li></rdf:Alt></dc:title>
+<dc:creator><rdf:Seq><rdf:li>Ann Lee</rdf:li><rdf:li>Bo &#x6797;</rdf:li></rdf:Seq></dc:creator>
+<dc:rights><rdf:Alt><rdf:li xml:lang="en">&#169; 2024 Ann Lee, CC BY 4.0</rdf:li></rdf:Alt></dc:rights>
+</rdf:Description></rdf:RDF></x:xmpmeta>
+<?xpacket end="w"?>"#;
+
+    #[test]
+    fn parses_dublin_core_fields() {
+        let metadata = parse_xmp(XMP.to_string());
+        assert_eq!(metadata.title.as_deref(), Some("Harbor"));
+        assert_eq!(metadata.creators, ["Ann Lee", "Bo 林"]);
+        assert_eq!(
+            metadata.rights.as_deref(),
+            Some("© 2024 Ann Lee, CC BY 4.0")
+        );
+        assert_eq!(metadata.source.as_deref(), Some("Archive & Co."));
+        assert_eq!(metadata.raw, XMP);
+
+        let simple = parse_xmp("<dc:source>scan 12</dc:source><dc:title/>".to_string());
+        assert_eq!(simple.source.as_deref(), Some("scan 12"));
+        assert_eq!(simple.title, None);
+        assert!(simple.creators.is_empty());
+    }
+
+    #[test]
+    fn embeds_itxt_chunk_after_ihdr() {
+        let mut png = Cursor::new(Vec::new());
+        DynamicImage::new_rgb8(2, 2)
+            .write_to(&mut png, ImageFormat::Png)
+            .unwrap();
+        let png = png.into_inner();
+        let embedded = embed_xmp_in_png(&png, XMP).unwrap();
+
+        assert_eq!(&embedded[37..41], b"iTXt");
+        assert!(embedded[41..].starts_with(b"XML:com.adobe.xmp\0\0\0\0\0<?xpacket"));
+        assert_eq!(embedded.len(), png.len() + XMP.len() + 22 + 12);
+        let decoded = image::load_from_memory(&embedded).unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (2, 2));
+
+        assert_eq!(
+            embed_xmp_in_png(b"GIF89a", XMP),
+            Err(XmpEmbedError::InvalidPng)
+        );
+    }
+
+    #[test]
+    fn computes_png_crc() {
+        assert_eq!(crc32(b"IEND"), 0xAE42_6082);
+    }
+}
@@ -1,38 +1,674 @@
 use std::io::Cursor;
 
-use extract::{self, ExtractImageWarningKind};
+use extract::{
+    self, AttachmentContent, AttachmentImage, AttachmentWarning, AttachmentWarningKind,
+    CancellationToken, ExtractError, ExtractImageWarning, ExtractImageWarningKind, ExtractLimits,
+    ExtractOptions, ExtractProgress, ExtractedImage, ImageChange, ImageChangeKind, ImageSource,
+    LayerVisibility, PageSelection, RenderOptions, StructureElement, SvgGraphic, SvgOptions,
+    UnusedImage, UnusedImageKind, UnusedImageWarning, XmpMetadata,
+};
 use image::{DynamicImage, ImageFormat};
-use js_sys::{Array, Error as JsError, Object, Reflect, Uint8Array};
+use js_sys::{Array, Error as JsError, Function, Object, Reflect, Uint8Array};
 use wasm_bindgen::prelude::*;
 
 #[wasm_bindgen(js_name = extractImages)]
-pub fn extract_images(pdf_bytes: &[u8], ranges: Option<Array>) -> Result<JsValue, JsValue> {
-    let page_ranges = parse_page_ranges(ranges)?;
-    let result = extract::extract_images(pdf_bytes, &page_ranges)
-        .map_err(|err| js_error(&err.to_string()))?;
+pub fn extract_images(
+    pdf_bytes: &[u8],
+    ranges: JsValue,
+    extract_options: Option<Object>,
+    on_progress: Option<Function>,
+    should_abort: Option<Function>,
+) -> Result<JsValue, JsValue> {
+    let page_ranges = parse_page_ranges(pdf_bytes, &ranges)?;
+    let embed_xmp = match &extract_options {
+        Some(value) => parse_optional_bool(value, "embedXmp")?.unwrap_or(false),
+        None => false,
+    };
+    let options = parse_extract_options(extract_options)?;
+
+    let mut callbacks = ProgressCallbacks::new(on_progress, should_abort);
+    let result =
+        extract::extract_images_with_progress(pdf_bytes, &page_ranges, &options, |progress| {
+            callbacks.report(&progress, &options.cancellation)
+        });
+    let result = callbacks.finish(result)?;
 
     let images = Array::new();
-    for item in result.images {
-        let image_obj = Object::new();
-        let png_bytes = encode_png(&item.image).map_err(|err| js_error(&err))?;
-        let png_array = Uint8Array::new_with_length(png_bytes.len() as u32);
-        png_array.copy_from(&png_bytes);
+    for item in &result.images {
+        let image_obj = extracted_image_item(item, embed_xmp)?;
+        images.push(&image_obj);
+    }
+
+    let attachments = Array::new();
+    for attachment in &result.attachments {
+        let attachment_obj = attachment_item(attachment, embed_xmp)?;
+        attachments.push(&attachment_obj);
+    }
+
+    let unused_images = Array::new();
+    for unused in &result.unused_images {
+        let unused_obj = unused_image_item(unused)?;
+        unused_images.push(&unused_obj);
+    }
+
+    let result_obj = Object::new();
+    Reflect::set(&result_obj, &JsValue::from_str("images"), &images)?;
+    let errors = warning_items(result.errors)?;
+    Reflect::set(&result_obj, &JsValue::from_str("errors"), &errors)?;
+    Reflect::set(&result_obj, &JsValue::from_str("attachments"), &attachments)?;
+    let attachment_errors = attachment_warning_items(result.attachment_errors)?;
+    Reflect::set(
+        &result_obj,
+        &JsValue::from_str("attachmentErrors"),
+        &attachment_errors,
+    )?;
+    Reflect::set(
+        &result_obj,
+        &JsValue::from_str("unusedImages"),
+        &unused_images,
+    )?;
+    let unused_errors = unused_warning_items(result.unused_errors)?;
+    Reflect::set(
+        &result_obj,
+        &JsValue::from_str("unusedErrors"),
+        &unused_errors,
+    )?;
+
+    Ok(result_obj.into())
+}
+
+#[wasm_bindgen(js_name = documentInfo)]
+pub fn document_info(pdf_bytes: &[u8]) -> Result<JsValue, JsValue> {
+    let info = extract::document_info(pdf_bytes).map_err(|err| js_error(&err.to_string()))?;
 
+    let layers = Array::new();
+    for layer in info.layers {
+        let layer_obj = Object::new();
         Reflect::set(
-            &image_obj,
-            &JsValue::from_str("pageIndex"),
-            &JsValue::from_f64(item.page_index as f64),
+            &layer_obj,
+            &JsValue::from_str("name"),
+            &JsValue::from_str(&layer.name),
         )?;
         Reflect::set(
-            &image_obj,
-            &JsValue::from_str("pngBytes"),
-            &JsValue::from(png_array),
+            &layer_obj,
+            &JsValue::from_str("visibleByDefault"),
+            &JsValue::from_bool(layer.visible_by_default),
         )?;
-        images.push(&image_obj);
+        layers.push(&layer_obj);
+    }
+
+    let pages = Array::new();
+    for page in info.pages {
+        let page_obj = Object::new();
+        Reflect::set(
+            &page_obj,
+            &JsValue::from_str("label"),
+            &JsValue::from_str(&page.label),
+        )?;
+        Reflect::set(
+            &page_obj,
+            &JsValue::from_str("width"),
+            &JsValue::from_f64(f64::from(page.width)),
+        )?;
+        Reflect::set(
+            &page_obj,
+            &JsValue::from_str("height"),
+            &JsValue::from_f64(f64::from(page.height)),
+        )?;
+        Reflect::set(
+            &page_obj,
+            &JsValue::from_str("rotation"),
+            &JsValue::from_f64(f64::from(page.rotation)),
+        )?;
+        Reflect::set(
+            &page_obj,
+            &JsValue::from_str("imageCount"),
+            &page
+                .image_count
+                .map_or(JsValue::NULL, |count| JsValue::from_f64(count as f64)),
+        )?;
+        pages.push(&page_obj);
+    }
+
+    let result_obj = Object::new();
+    Reflect::set(
+        &result_obj,
+        &JsValue::from_str("version"),
+        &JsValue::from_str(&info.version),
+    )?;
+    Reflect::set(
+        &result_obj,
+        &JsValue::from_str("encrypted"),
+        &JsValue::from_bool(info.encrypted),
+    )?;
+    Reflect::set(
+        &result_obj,
+        &JsValue::from_str("producer"),
+        &info
+            .producer
+            .as_deref()
+            .map_or(JsValue::NULL, JsValue::from_str),
+    )?;
+    Reflect::set(
+        &result_obj,
+        &JsValue::from_str("creator"),
+        &info
+            .creator
+            .as_deref()
+            .map_or(JsValue::NULL, JsValue::from_str),
+    )?;
+    Reflect::set(&result_obj, &JsValue::from_str("pages"), &pages)?;
+    Reflect::set(&result_obj, &JsValue::from_str("layers"), &layers)?;
+    Ok(result_obj.into())
+}
+
+// 書式だけを検査する（ラベルや範囲外のページはPDFがないと判定できない）
+#[wasm_bindgen(js_name = validatePageSelection)]
+pub fn validate_page_selection(text: &str) -> Result<(), JsValue> {
+    parse_page_selection(text).map(|_| ())
+}
+
+#[wasm_bindgen(js_name = renderPages)]
+pub fn render_pages(
+    pdf_bytes: &[u8],
+    ranges: JsValue,
+    render_options: Option<Object>,
+    on_progress: Option<Function>,
+    should_abort: Option<Function>,
+) -> Result<JsValue, JsValue> {
+    let page_ranges = parse_page_ranges(pdf_bytes, &ranges)?;
+    let options = parse_render_options(render_options)?;
+
+    let mut callbacks = ProgressCallbacks::new(on_progress, should_abort);
+    let result =
+        extract::render_pages_with_progress(pdf_bytes, &page_ranges, &options, |progress| {
+            callbacks.report(&progress, &options.cancellation)
+        });
+    let result = callbacks.finish(result)?;
+
+    let pages = Array::new();
+    for page in result.pages {
+        let page_obj = image_item(page.page_index, &page.image, None)?;
+        pages.push(&page_obj);
+    }
+
+    let result_obj = Object::new();
+    Reflect::set(&result_obj, &JsValue::from_str("pages"), &pages)?;
+    let errors = warning_items(result.errors)?;
+    Reflect::set(&result_obj, &JsValue::from_str("errors"), &errors)?;
+
+    Ok(result_obj.into())
+}
+
+// ページは描画せず、埋め込みの/Thumbだけを返す
+#[wasm_bindgen(js_name = extractThumbnails)]
+pub fn extract_thumbnails(pdf_bytes: &[u8], ranges: JsValue) -> Result<JsValue, JsValue> {
+    let page_ranges = parse_page_ranges(pdf_bytes, &ranges)?;
+    let result = extract::extract_thumbnails(pdf_bytes, &page_ranges, &ExtractOptions::default())
+        .map_err(|err| js_error(&err.to_string()))?;
+
+    let thumbnails = Array::new();
+    for thumbnail in result.thumbnails {
+        let thumbnail_obj = image_item(thumbnail.page_index, &thumbnail.image, None)?;
+        Reflect::set(
+            &thumbnail_obj,
+            &JsValue::from_str("pageLabel"),
+            &JsValue::from_str(&thumbnail.page_label),
+        )?;
+        thumbnails.push(&thumbnail_obj);
     }
 
+    let result_obj = Object::new();
+    Reflect::set(&result_obj, &JsValue::from_str("thumbnails"), &thumbnails)?;
+    let errors = warning_items(result.errors)?;
+    Reflect::set(&result_obj, &JsValue::from_str("errors"), &errors)?;
+
+    Ok(result_obj.into())
+}
+
+// 古い版から順に、各版の画像と1つ前の版からの変化を返す
+#[wasm_bindgen(js_name = extractRevisions)]
+pub fn extract_revisions(
+    pdf_bytes: &[u8],
+    ranges: JsValue,
+    extract_options: Option<Object>,
+) -> Result<JsValue, JsValue> {
+    let page_ranges = parse_page_ranges(pdf_bytes, &ranges)?;
+    let embed_xmp = match &extract_options {
+        Some(value) => parse_optional_bool(value, "embedXmp")?.unwrap_or(false),
+        None => false,
+    };
+    let options = parse_extract_options(extract_options)?;
+    let result = extract::extract_revisions(pdf_bytes, &page_ranges, &options)
+        .map_err(|err| js_error(&err.to_string()))?;
+
+    let revisions = Array::new();
+    for revision in result.revisions {
+        let images = Array::new();
+        for item in &revision.images {
+            let image_obj = extracted_image_item(item, embed_xmp)?;
+            images.push(&image_obj);
+        }
+        let changes = Array::new();
+        for change in &revision.changes {
+            let change_obj = image_change_item(change)?;
+            changes.push(&change_obj);
+        }
+
+        let revision_obj = Object::new();
+        Reflect::set(
+            &revision_obj,
+            &JsValue::from_str("revision"),
+            &JsValue::from_f64(revision.revision.index as f64),
+        )?;
+        Reflect::set(
+            &revision_obj,
+            &JsValue::from_str("byteLength"),
+            &JsValue::from_f64(revision.revision.byte_length as f64),
+        )?;
+        Reflect::set(
+            &revision_obj,
+            &JsValue::from_str("imageCount"),
+            &JsValue::from_f64(revision.image_count as f64),
+        )?;
+        Reflect::set(&revision_obj, &JsValue::from_str("images"), &images)?;
+        let errors = warning_items(revision.errors)?;
+        Reflect::set(&revision_obj, &JsValue::from_str("errors"), &errors)?;
+        Reflect::set(&revision_obj, &JsValue::from_str("changes"), &changes)?;
+        revisions.push(&revision_obj);
+    }
+
+    let result_obj = Object::new();
+    Reflect::set(&result_obj, &JsValue::from_str("revisions"), &revisions)?;
+    Ok(result_obj.into())
+}
+
+// ページ全体、またはfiguresが真なら検出した図ごとのSVGを返す
+#[wasm_bindgen(js_name = exportSvg)]
+pub fn export_svg(
+    pdf_bytes: &[u8],
+    ranges: JsValue,
+    svg_options: Option<Object>,
+) -> Result<JsValue, JsValue> {
+    let page_ranges = parse_page_ranges(pdf_bytes, &ranges)?;
+    let mut options = SvgOptions::default();
+    if let Some(value) = &svg_options {
+        options.figures = parse_optional_bool(value, "figures")?.unwrap_or(false);
+        options.limits = parse_limits(value)?;
+    }
+    let result = extract::export_svg(pdf_bytes, &page_ranges, &options)
+        .map_err(|err| js_error(&err.to_string()))?;
+
+    let graphics = Array::new();
+    for graphic in result.graphics {
+        let graphic_obj = svg_graphic_item(&graphic)?;
+        graphics.push(&graphic_obj);
+    }
+
+    let result_obj = Object::new();
+    Reflect::set(&result_obj, &JsValue::from_str("graphics"), &graphics)?;
+    let errors = warning_items(result.errors)?;
+    Reflect::set(&result_obj, &JsValue::from_str("errors"), &errors)?;
+    Ok(result_obj.into())
+}
+
+fn svg_graphic_item(graphic: &SvgGraphic) -> Result<Object, JsValue> {
+    let graphic_obj = Object::new();
+    Reflect::set(
+        &graphic_obj,
+        &JsValue::from_str("pageIndex"),
+        &JsValue::from_f64(graphic.page_index as f64),
+    )?;
+    Reflect::set(
+        &graphic_obj,
+        &JsValue::from_str("pageLabel"),
+        &JsValue::from_str(&graphic.page_label),
+    )?;
+    let bounds = Object::new();
+    for (key, value) in [
+        ("x0", graphic.bounds.x0),
+        ("y0", graphic.bounds.y0),
+        ("x1", graphic.bounds.x1),
+        ("y1", graphic.bounds.y1),
+    ] {
+        Reflect::set(
+            &bounds,
+            &JsValue::from_str(key),
+            &JsValue::from_f64(f64::from(value)),
+        )?;
+    }
+    Reflect::set(&graphic_obj, &JsValue::from_str("bounds"), &bounds)?;
+    Reflect::set(
+        &graphic_obj,
+        &JsValue::from_str("svg"),
+        &JsValue::from_str(&graphic.svg),
+    )?;
+    Reflect::set(
+        &graphic_obj,
+        &JsValue::from_str("skippedPaints"),
+        &JsValue::from_f64(graphic.skipped_paints as f64),
+    )?;
+    Reflect::set(
+        &graphic_obj,
+        &JsValue::from_str("ignoredEffects"),
+        &JsValue::from_f64(graphic.ignored_effects as f64),
+    )?;
+    Ok(graphic_obj)
+}
+
+fn image_change_item(change: &ImageChange) -> Result<Object, JsValue> {
+    let change_obj = Object::new();
+    Reflect::set(
+        &change_obj,
+        &JsValue::from_str("kind"),
+        &JsValue::from_str(match change.kind {
+            ImageChangeKind::Added => "added",
+            ImageChangeKind::Removed => "removed",
+            ImageChangeKind::Modified => "modified",
+        }),
+    )?;
+    let object = change.object.map_or(JsValue::NULL, |object| {
+        JsValue::from_str(&object.to_string())
+    });
+    Reflect::set(&change_obj, &JsValue::from_str("object"), &object)?;
+    let pages = Array::new();
+    for page_index in &change.pages {
+        pages.push(&JsValue::from_f64(*page_index as f64));
+    }
+    Reflect::set(&change_obj, &JsValue::from_str("pageIndexes"), &pages)?;
+    Ok(change_obj)
+}
+
+// コールバックが例外を投げたら処理を中断して、その例外をそのまま返す
+struct ProgressCallbacks {
+    on_progress: Option<Function>,
+    should_abort: Option<Function>,
+    error: Option<JsValue>,
+}
+
+impl ProgressCallbacks {
+    fn new(on_progress: Option<Function>, should_abort: Option<Function>) -> Self {
+        Self {
+            on_progress,
+            should_abort,
+            error: None,
+        }
+    }
+
+    fn report(&mut self, progress: &ExtractProgress, cancellation: &CancellationToken) {
+        if self.error.is_some() {
+            return;
+        }
+        let outcome = notify_progress(self.on_progress.as_ref(), progress)
+            .and_then(|()| check_abort(self.should_abort.as_ref()));
+        match outcome {
+            Ok(false) => {}
+            Ok(true) => cancellation.cancel(),
+            Err(err) => {
+                self.error = Some(err);
+                cancellation.cancel();
+            }
+        }
+    }
+
+    fn finish<T>(self, result: Result<T, ExtractError>) -> Result<T, JsValue> {
+        if let Some(err) = self.error {
+            return Err(err);
+        }
+        result.map_err(|err| match err {
+            ExtractError::Cancelled => abort_error(),
+            err => js_error(&err.to_string()),
+        })
+    }
+}
+
+fn extracted_image_item(item: &ExtractedImage, embed_xmp: bool) -> Result<Object, JsValue> {
+    let xmp = embed_xmp
+        .then(|| item.xmp.as_ref().map(|xmp| xmp.raw.as_str()))
+        .flatten();
+    let image_obj = image_item(item.page_index, &item.image, xmp)?;
+    Reflect::set(
+        &image_obj,
+        &JsValue::from_str("pageLabel"),
+        &JsValue::from_str(&item.page_label),
+    )?;
+    Reflect::set(
+        &image_obj,
+        &JsValue::from_str("rotation"),
+        &JsValue::from_f64(f64::from(item.orientation.rotation)),
+    )?;
+    Reflect::set(
+        &image_obj,
+        &JsValue::from_str("mirrored"),
+        &JsValue::from_bool(item.orientation.mirrored),
+    )?;
+    let annotation = item
+        .annotation
+        .as_deref()
+        .map_or(JsValue::NULL, JsValue::from_str);
+    Reflect::set(&image_obj, &JsValue::from_str("annotation"), &annotation)?;
+    Reflect::set(
+        &image_obj,
+        &JsValue::from_str("fromPattern"),
+        &JsValue::from_bool(item.from_pattern),
+    )?;
+    Reflect::set(
+        &image_obj,
+        &JsValue::from_str("fromType3Glyph"),
+        &JsValue::from_bool(item.from_type3_glyph),
+    )?;
+    let glyph_unicode = item.glyph_unicode.map_or(JsValue::NULL, |unicode| {
+        JsValue::from_str(&unicode.to_string())
+    });
+    Reflect::set(
+        &image_obj,
+        &JsValue::from_str("glyphUnicode"),
+        &glyph_unicode,
+    )?;
+    let layers: Array = item
+        .layers
+        .iter()
+        .map(|name| JsValue::from_str(name))
+        .collect();
+    Reflect::set(&image_obj, &JsValue::from_str("layers"), &layers)?;
+    let source = match item.source {
+        ImageSource::XObject => "xobject",
+        ImageSource::Inline => "inline",
+    };
+    Reflect::set(
+        &image_obj,
+        &JsValue::from_str("source"),
+        &JsValue::from_str(source),
+    )?;
+    let structure = match &item.structure {
+        Some(structure) => structure_item(structure)?,
+        None => JsValue::NULL,
+    };
+    Reflect::set(&image_obj, &JsValue::from_str("structure"), &structure)?;
+    Reflect::set(
+        &image_obj,
+        &JsValue::from_str("caption"),
+        &optional_string(item.caption.as_deref()),
+    )?;
+    Reflect::set(
+        &image_obj,
+        &JsValue::from_str("textAbove"),
+        &optional_string(item.nearby_text.above.as_deref()),
+    )?;
+    Reflect::set(
+        &image_obj,
+        &JsValue::from_str("textBelow"),
+        &optional_string(item.nearby_text.below.as_deref()),
+    )?;
+    let xmp = match &item.xmp {
+        Some(xmp) => xmp_item(xmp)?,
+        None => JsValue::NULL,
+    };
+    Reflect::set(&image_obj, &JsValue::from_str("xmp"), &xmp)?;
+    Reflect::set(
+        &image_obj,
+        &JsValue::from_str("fragmentCount"),
+        &JsValue::from_f64(item.fragment_count as f64),
+    )?;
+    Ok(image_obj)
+}
+
+// 画像ファイルの添付はバイト列をそのまま、添付PDFの画像はextractImagesの画像と同じ形で返す
+fn attachment_item(attachment: &AttachmentImage, embed_xmp: bool) -> Result<Object, JsValue> {
+    let attachment_obj = match &attachment.content {
+        AttachmentContent::File { bytes, format } => {
+            let attachment_obj = Object::new();
+            Reflect::set(
+                &attachment_obj,
+                &JsValue::from_str("kind"),
+                &JsValue::from_str("file"),
+            )?;
+            Reflect::set(
+                &attachment_obj,
+                &JsValue::from_str("format"),
+                &JsValue::from_str(format.extension()),
+            )?;
+            Reflect::set(
+                &attachment_obj,
+                &JsValue::from_str("bytes"),
+                &JsValue::from(Uint8Array::from(bytes.as_slice())),
+            )?;
+            attachment_obj
+        }
+        AttachmentContent::Extracted(item) => {
+            let attachment_obj = extracted_image_item(item, embed_xmp)?;
+            Reflect::set(
+                &attachment_obj,
+                &JsValue::from_str("kind"),
+                &JsValue::from_str("image"),
+            )?;
+            attachment_obj
+        }
+    };
+    Reflect::set(
+        &attachment_obj,
+        &JsValue::from_str("sourcePath"),
+        &JsValue::from_str(&attachment.source_path),
+    )?;
+    Ok(attachment_obj)
+}
+
+fn attachment_warning_items(warnings: Vec<AttachmentWarning>) -> Result<Array, JsValue> {
     let errors = Array::new();
-    for warning in result.errors {
+    for warning in warnings {
+        let error_obj = Object::new();
+        let kind = match &warning.kind {
+            AttachmentWarningKind::StreamDecodeFailed => "stream_decode_failed",
+            AttachmentWarningKind::PdfExtractFailed { .. } => "pdf_extract_failed",
+            AttachmentWarningKind::TooDeep => "too_deep",
+            AttachmentWarningKind::LimitReached(kind) => warning_kind_code(kind),
+            AttachmentWarningKind::Image(warning) => warning_kind_code(&warning.kind),
+        };
+        Reflect::set(
+            &error_obj,
+            &JsValue::from_str("sourcePath"),
+            &JsValue::from_str(&warning.source_path),
+        )?;
+        Reflect::set(
+            &error_obj,
+            &JsValue::from_str("kind"),
+            &JsValue::from_str(kind),
+        )?;
+        Reflect::set(
+            &error_obj,
+            &JsValue::from_str("message"),
+            &JsValue::from_str(&warning.kind.to_string()),
+        )?;
+        errors.push(&error_obj);
+    }
+    Ok(errors)
+}
+
+// 描かれない画像はページに属さないので、pageIndexの代わりにリソースに持つページを返す
+fn unused_image_item(unused: &UnusedImage) -> Result<Object, JsValue> {
+    let png_bytes = encode_png(&unused.image).map_err(|err| js_error(&err))?;
+    let unused_obj = Object::new();
+    Reflect::set(
+        &unused_obj,
+        &JsValue::from_str("object"),
+        &JsValue::from_str(&unused.object.to_string()),
+    )?;
+    Reflect::set(
+        &unused_obj,
+        &JsValue::from_str("kind"),
+        &JsValue::from_str(match unused.kind {
+            UnusedImageKind::Unpainted => "unpainted",
+            UnusedImageKind::Orphaned => "orphaned",
+        }),
+    )?;
+    let pages = Array::new();
+    for page_index in &unused.pages {
+        pages.push(&JsValue::from_f64(*page_index as f64));
+    }
+    Reflect::set(&unused_obj, &JsValue::from_str("pageIndexes"), &pages)?;
+    Reflect::set(
+        &unused_obj,
+        &JsValue::from_str("pngBytes"),
+        &JsValue::from(Uint8Array::from(png_bytes.as_slice())),
+    )?;
+    Ok(unused_obj)
+}
+
+fn unused_warning_items(warnings: Vec<UnusedImageWarning>) -> Result<Array, JsValue> {
+    let errors = Array::new();
+    for warning in warnings {
+        let error_obj = Object::new();
+        Reflect::set(
+            &error_obj,
+            &JsValue::from_str("object"),
+            &JsValue::from_str(&warning.object.to_string()),
+        )?;
+        Reflect::set(
+            &error_obj,
+            &JsValue::from_str("kind"),
+            &JsValue::from_str(warning_kind_code(&warning.kind)),
+        )?;
+        Reflect::set(
+            &error_obj,
+            &JsValue::from_str("message"),
+            &JsValue::from_str(&warning.kind.to_string()),
+        )?;
+        errors.push(&error_obj);
+    }
+    Ok(errors)
+}
+
+// xmpはPNGのiTXtチャンクとして埋め込む
+fn image_item(
+    page_index: usize,
+    image: &DynamicImage,
+    xmp: Option<&str>,
+) -> Result<Object, JsValue> {
+    let image_obj = Object::new();
+    let mut png_bytes = encode_png(image).map_err(|err| js_error(&err))?;
+    if let Some(xmp) = xmp {
+        png_bytes =
+            extract::embed_xmp_in_png(&png_bytes, xmp).map_err(|err| js_error(&err.to_string()))?;
+    }
+    let png_array = Uint8Array::new_with_length(png_bytes.len() as u32);
+    png_array.copy_from(&png_bytes);
+
+    Reflect::set(
+        &image_obj,
+        &JsValue::from_str("pageIndex"),
+        &JsValue::from_f64(page_index as f64),
+    )?;
+    Reflect::set(
+        &image_obj,
+        &JsValue::from_str("pngBytes"),
+        &JsValue::from(png_array),
+    )?;
+    Ok(image_obj)
+}
+
+fn warning_items(warnings: Vec<ExtractImageWarning>) -> Result<Array, JsValue> {
+    let errors = Array::new();
+    for warning in warnings {
         let error_obj = Object::new();
         let kind = warning_kind_code(&warning.kind);
         let message = warning.kind.to_string();
@@ -42,6 +678,13 @@ pub fn extract_images(pdf_bytes: &[u8], ranges: Option<Array>) -> Result<JsValue
             &JsValue::from_str("pageIndex"),
             &JsValue::from_f64(warning.page_index as f64),
         )?;
+        Reflect::set(
+            &error_obj,
+            &JsValue::from_str("imageIndex"),
+            &warning
+                .image_index
+                .map_or(JsValue::NULL, |index| JsValue::from_f64(index as f64)),
+        )?;
         Reflect::set(
             &error_obj,
             &JsValue::from_str("kind"),
@@ -54,19 +697,281 @@ pub fn extract_images(pdf_bytes: &[u8], ranges: Option<Array>) -> Result<JsValue
         )?;
         errors.push(&error_obj);
     }
+    Ok(errors)
+}
 
-    let result_obj = Object::new();
-    Reflect::set(&result_obj, &JsValue::from_str("images"), &images)?;
-    Reflect::set(&result_obj, &JsValue::from_str("errors"), &errors)?;
+fn parse_extract_options(value: Option<Object>) -> Result<ExtractOptions, JsValue> {
+    let mut options = ExtractOptions::default();
+    let Some(value) = value else {
+        return Ok(options);
+    };
 
-    Ok(result_obj.into())
+    if let Some(as_displayed) = parse_optional_bool(&value, "asDisplayed")? {
+        options.as_displayed = as_displayed;
+    }
+    if let Some(normalize_orientation) = parse_optional_bool(&value, "normalizeOrientation")? {
+        options.normalize_orientation = normalize_orientation;
+    }
+    if let Some(exclude_annotations) = parse_optional_bool(&value, "excludeAnnotations")? {
+        options.exclude_annotations = exclude_annotations;
+    }
+    if let Some(include_type3_glyphs) = parse_optional_bool(&value, "includeType3Glyphs")? {
+        options.include_type3_glyphs = include_type3_glyphs;
+    }
+    if let Some(include_attachments) = parse_optional_bool(&value, "includeAttachments")? {
+        options.include_attachments = include_attachments;
+    }
+    if let Some(include_unused) = parse_optional_bool(&value, "includeUnused")? {
+        options.include_unused = include_unused;
+    }
+    if let Some(stitch_fragments) = parse_optional_bool(&value, "stitchFragments")? {
+        options.stitch_fragments = stitch_fragments;
+    }
+    if let Some(exclude_inline_images) = parse_optional_bool(&value, "excludeInlineImages")? {
+        options.exclude_inline_images = exclude_inline_images;
+    }
+    options.layers = parse_layer_visibility(&value)?;
+    options.limits = parse_limits(&value)?;
+
+    let caption_distance = Reflect::get(&value, &JsValue::from_str("captionDistance"))?;
+    if !caption_distance.is_undefined() {
+        let Some(caption_distance) = caption_distance.as_f64() else {
+            return Err(js_error("captionDistance must be a number"));
+        };
+        options.caption_distance = Some(caption_distance as f32);
+    }
+
+    Ok(options)
 }
 
-fn parse_page_ranges(ranges: Option<Array>) -> Result<Vec<extract::PageRange>, JsValue> {
-    let Some(ranges) = ranges else {
-        return Ok(vec![]);
+// "default"、"all"、またはレイヤ名の配列
+fn parse_layer_visibility(value: &Object) -> Result<LayerVisibility, JsValue> {
+    let item = Reflect::get(value, &JsValue::from_str("layers"))?;
+    if item.is_undefined() {
+        return Ok(LayerVisibility::Default);
+    }
+    if let Some(mode) = item.as_string() {
+        return match mode.as_str() {
+            "default" => Ok(LayerVisibility::Default),
+            "all" => Ok(LayerVisibility::All),
+            _ => Err(js_error(
+                "layers must be \"default\", \"all\" or an array of names",
+            )),
+        };
+    }
+    if !Array::is_array(&item) {
+        return Err(js_error(
+            "layers must be \"default\", \"all\" or an array of names",
+        ));
+    }
+    Array::from(&item)
+        .iter()
+        .map(|name| {
+            name.as_string()
+                .ok_or_else(|| js_error("layer names must be strings"))
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map(LayerVisibility::Only)
+}
+
+// 省いた項目は既定値のまま、nullは無制限
+fn parse_limits(value: &Object) -> Result<ExtractLimits, JsValue> {
+    let mut limits = ExtractLimits::default();
+    let item = Reflect::get(value, &JsValue::from_str("limits"))?;
+    if item.is_undefined() {
+        return Ok(limits);
+    }
+    if !item.is_object() {
+        return Err(js_error("limits must be an object"));
+    }
+    let item = Object::from(item);
+    if let Some(limit) = parse_optional_limit(&item, "maxImagePixels")? {
+        limits.max_image_pixels = limit;
+    }
+    if let Some(limit) = parse_optional_limit(&item, "maxTotalDecodedBytes")? {
+        limits.max_total_decoded_bytes = limit;
+    }
+    if let Some(limit) = parse_optional_limit(&item, "maxImages")? {
+        limits.max_images = limit.map(|limit| limit as usize);
+    }
+    if let Some(limit) = parse_optional_limit(&item, "maxPages")? {
+        limits.max_pages = limit.map(|limit| limit as usize);
+    }
+    Ok(limits)
+}
+
+fn parse_optional_limit(value: &Object, field: &str) -> Result<Option<Option<u64>>, JsValue> {
+    let item = Reflect::get(value, &JsValue::from_str(field))?;
+    if item.is_undefined() {
+        return Ok(None);
+    }
+    if item.is_null() {
+        return Ok(Some(None));
+    }
+    match item.as_f64() {
+        Some(limit) if limit >= 0.0 && limit.fract() == 0.0 => Ok(Some(Some(limit as u64))),
+        _ => Err(js_error(&format!(
+            "limits.{field} must be a non-negative integer or null"
+        ))),
+    }
+}
+
+fn parse_optional_bool(value: &Object, field: &str) -> Result<Option<bool>, JsValue> {
+    let item = Reflect::get(value, &JsValue::from_str(field))?;
+    if item.is_undefined() {
+        return Ok(None);
+    }
+    item.as_bool()
+        .map(Some)
+        .ok_or_else(|| js_error(&format!("{field} must be a boolean")))
+}
+
+fn parse_render_options(value: Option<Object>) -> Result<RenderOptions, JsValue> {
+    let mut options = RenderOptions::default();
+    let Some(value) = value else {
+        return Ok(options);
     };
 
+    let dpi = Reflect::get(&value, &JsValue::from_str("dpi"))?;
+    if !dpi.is_undefined() {
+        let Some(dpi) = dpi.as_f64() else {
+            return Err(js_error("dpi must be a number"));
+        };
+        options.dpi = dpi as f32;
+    }
+
+    let background = Reflect::get(&value, &JsValue::from_str("background"))?;
+    if !background.is_undefined() {
+        let Some(background) = background.as_string() else {
+            return Err(js_error("background must be a string"));
+        };
+        options.background = extract::parse_background_color(&background)
+            .map_err(|err| js_error(&err.to_string()))?;
+    }
+    options.limits = parse_limits(&value)?;
+
+    Ok(options)
+}
+
+fn notify_progress(
+    on_progress: Option<&Function>,
+    progress: &ExtractProgress,
+) -> Result<(), JsValue> {
+    let Some(on_progress) = on_progress else {
+        return Ok(());
+    };
+
+    let progress_obj = Object::new();
+    Reflect::set(
+        &progress_obj,
+        &JsValue::from_str("pagesDone"),
+        &JsValue::from_f64(progress.pages_done as f64),
+    )?;
+    Reflect::set(
+        &progress_obj,
+        &JsValue::from_str("totalPages"),
+        &JsValue::from_f64(progress.total_pages as f64),
+    )?;
+    Reflect::set(
+        &progress_obj,
+        &JsValue::from_str("imagesFound"),
+        &JsValue::from_f64(progress.images_found as f64),
+    )?;
+    on_progress.call1(&JsValue::NULL, &progress_obj)?;
+    Ok(())
+}
+
+fn check_abort(should_abort: Option<&Function>) -> Result<bool, JsValue> {
+    match should_abort {
+        Some(should_abort) => Ok(should_abort.call0(&JsValue::NULL)?.is_truthy()),
+        None => Ok(false),
+    }
+}
+
+fn abort_error() -> JsValue {
+    let error = JsError::new("extraction was cancelled");
+    error.set_name("AbortError");
+    error.into()
+}
+
+fn optional_string(value: Option<&str>) -> JsValue {
+    value.map_or(JsValue::NULL, JsValue::from_str)
+}
+
+fn structure_item(structure: &StructureElement) -> Result<JsValue, JsValue> {
+    let obj = Object::new();
+    Reflect::set(
+        &obj,
+        &JsValue::from_str("role"),
+        &JsValue::from_str(&structure.role),
+    )?;
+    Reflect::set(
+        &obj,
+        &JsValue::from_str("alt"),
+        &optional_string(structure.alt.as_deref()),
+    )?;
+    Reflect::set(
+        &obj,
+        &JsValue::from_str("actualText"),
+        &optional_string(structure.actual_text.as_deref()),
+    )?;
+    Reflect::set(
+        &obj,
+        &JsValue::from_str("title"),
+        &optional_string(structure.title.as_deref()),
+    )?;
+    Ok(obj.into())
+}
+
+fn xmp_item(xmp: &XmpMetadata) -> Result<JsValue, JsValue> {
+    let obj = Object::new();
+    Reflect::set(
+        &obj,
+        &JsValue::from_str("raw"),
+        &JsValue::from_str(&xmp.raw),
+    )?;
+    Reflect::set(
+        &obj,
+        &JsValue::from_str("title"),
+        &optional_string(xmp.title.as_deref()),
+    )?;
+    let creators: Array = xmp
+        .creators
+        .iter()
+        .map(|creator| JsValue::from_str(creator))
+        .collect();
+    Reflect::set(&obj, &JsValue::from_str("creators"), &creators)?;
+    Reflect::set(
+        &obj,
+        &JsValue::from_str("rights"),
+        &optional_string(xmp.rights.as_deref()),
+    )?;
+    Reflect::set(
+        &obj,
+        &JsValue::from_str("source"),
+        &optional_string(xmp.source.as_deref()),
+    )?;
+    Ok(obj.into())
+}
+
+// 範囲はCLIの--pagesと同じ書式の文字列か、{start, end}の配列（0始まり、両端を含む）
+fn parse_page_ranges(
+    pdf_bytes: &[u8],
+    ranges: &JsValue,
+) -> Result<Vec<extract::PageRange>, JsValue> {
+    if ranges.is_undefined() || ranges.is_null() {
+        return Ok(vec![]);
+    }
+    if let Some(text) = ranges.as_string() {
+        let selection = parse_page_selection(&text)?;
+        return extract::resolve_page_selection(pdf_bytes, &selection)
+            .map_err(|err| js_error(&err.to_string()));
+    }
+    if !Array::is_array(ranges) {
+        return Err(js_error("ranges must be a string or an array"));
+    }
+
+    let ranges = Array::from(ranges);
     let mut parsed = Vec::with_capacity(ranges.length() as usize);
     for (index, item) in ranges.iter().enumerate() {
         if !item.is_object() {
@@ -87,6 +992,11 @@ fn parse_page_ranges(ranges: Option<Array>) -> Result<Vec<extract::PageRange>, J
     Ok(parsed)
 }
 
+fn parse_page_selection(text: &str) -> Result<PageSelection, JsValue> {
+    text.parse::<PageSelection>()
+        .map_err(|err| js_error(&err.to_string()))
+}
+
 fn parse_non_negative_index(
     value: &JsValue,
     field: &str,
@@ -118,6 +1028,18 @@ fn warning_kind_code(kind: &ExtractImageWarningKind) -> &'static str {
         ExtractImageWarningKind::InvalidAlphaBufferShape => "invalid_alpha_buffer_shape",
         ExtractImageWarningKind::InvalidRgbBufferShape => "invalid_rgb_buffer_shape",
         ExtractImageWarningKind::InvalidRgbaBufferShape => "invalid_rgba_buffer_shape",
+        ExtractImageWarningKind::ImageTooLarge { .. } => "image_too_large",
+        ExtractImageWarningKind::PageTooLarge { .. } => "page_too_large",
+        ExtractImageWarningKind::ImageLimitReached => "image_limit_reached",
+        ExtractImageWarningKind::DecodedBytesLimitReached => "decoded_bytes_limit_reached",
+        ExtractImageWarningKind::PageLimitReached => "page_limit_reached",
+        ExtractImageWarningKind::PageInterpretFailed { .. } => "page_interpret_failed",
+        ExtractImageWarningKind::ImageClippedOut => "image_clipped_out",
+        ExtractImageWarningKind::ImageDecodePanicked { .. } => "image_decode_panicked",
+        ExtractImageWarningKind::UnsupportedFilter { .. } => "unsupported_filter",
+        ExtractImageWarningKind::UnsupportedColorSpace { .. } => "unsupported_color_space",
+        ExtractImageWarningKind::StreamDecodeFailed { .. } => "stream_decode_failed",
+        ExtractImageWarningKind::ImageDecodeFailed { .. } => "image_decode_failed",
     }
 }
 
@@ -137,4 +1059,25 @@ mod tests {
             "invalid_rgb_buffer_shape"
         );
     }
+
+    #[test]
+    fn decode_failure_codes_ignore_details() {
+        assert_eq!(
+            warning_kind_code(&ExtractImageWarningKind::ImageDecodeFailed {
+                object: None,
+                filter: Some("JBIG2Decode".to_string()),
+            }),
+            "image_decode_failed"
+        );
+        assert_eq!(
+            warning_kind_code(&ExtractImageWarningKind::UnsupportedFilter {
+                object: Some(extract::ObjectRef {
+                    number: 7,
+                    generation: 0,
+                }),
+                filter: "BrotliDecode".to_string(),
+            }),
+            "unsupported_filter"
+        );
+    }
 }
@@ -27,8 +27,8 @@ function App() {
       return;
     }
 
-    const range = rangeField.validateRange();
-    if (!range) {
+    const range = await rangeField.validateRange();
+    if (range === null) {
       return;
     }
 
@@ -58,9 +58,11 @@ function App() {
             runtimeError={extractor.runtimeError()}
             status={extractor.status()}
             elapsedSec={elapsedSec()}
+            progress={extractor.progress()}
             onFileChange={pdfField.onFileChange}
             onRangeInput={rangeField.setText}
             onSubmit={onExtract}
+            onCancel={extractor.cancel}
           />
         }
       >
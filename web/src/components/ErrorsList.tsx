@@ -6,6 +6,16 @@ interface ErrorsListProps {
   errors: ExtractedErrorItem[];
 }
 
+function warningLocation(error: ExtractedErrorItem): string {
+  if (error.pageIndex === null) {
+    return "Document level";
+  }
+  if (error.imageIndex === null) {
+    return `Page ${error.pageIndex + 1}`;
+  }
+  return `Page ${error.pageIndex + 1}, image ${error.imageIndex + 1}`;
+}
+
 export function ErrorsList(props: ErrorsListProps) {
   const DEFAULT_VISIBLE_WARNINGS = 6;
   const [expanded, setExpanded] = createSignal(false);
@@ -26,9 +36,7 @@ export function ErrorsList(props: ErrorsListProps) {
           {(error) => (
             <li class="rounded-md border border-border bg-muted-bg px-3 py-2">
               <p class="text-error-fg">
-                <span class="font-medium">
-                  {error.pageIndex !== null ? `Page ${error.pageIndex + 1}` : "Document level"}:
-                </span>{" "}
+                <span class="font-medium">{warningLocation(error)}:</span>{" "}
                 {error.message}
               </p>
               <p class="text-sm text-muted-fg">{error.kind}</p>
@@ -1,18 +1,27 @@
+import { Show } from "solid-js";
 import { Button } from "./ui/button";
 interface ExtractActionsProps {
   canSubmit: boolean;
   isRunning: boolean;
+  onCancel: () => void;
 }
 
 export function ExtractActions(props: ExtractActionsProps) {
   return (
-    <Button
-      type="submit"
-      class="h-10 min-w-28 px-5"
-      disabled={!props.canSubmit || props.isRunning}
-      aria-busy={props.isRunning}
-    >
-      {props.isRunning ? "Extracting..." : "Extract"}
-    </Button>
+    <div class="flex flex-wrap items-center gap-2">
+      <Button
+        type="submit"
+        class="h-10 min-w-28 px-5"
+        disabled={!props.canSubmit || props.isRunning}
+        aria-busy={props.isRunning}
+      >
+        {props.isRunning ? "Extracting..." : "Extract"}
+      </Button>
+      <Show when={props.isRunning}>
+        <Button intent="secondary" type="button" class="h-10 px-5" onClick={props.onCancel}>
+          Cancel
+        </Button>
+      </Show>
+    </div>
   );
 }
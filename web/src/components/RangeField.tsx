@@ -18,8 +18,10 @@ export function RangeField(props: RangeFieldProps) {
     >
       <TextField.Label class="block mb-1 sm:mb-0">Page Range</TextField.Label>
       <div>
-        <TextField.Input placeholder="1-3,5" disabled={props.disabled} class="mb-0.5" />
-        <TextField.Description>e.g. 1-3,5. Empty means all pages.</TextField.Description>
+        <TextField.Input placeholder="1-3,5,last" disabled={props.disabled} class="mb-0.5" />
+        <TextField.Description>
+          e.g. 1-3,5, 10-, last-2-last, odd, 1-20:2, !4, label:iv. Empty means all pages.
+        </TextField.Description>
         <Show when={props.error}>
           {(message) => <TextField.ErrorMessage>{message()}</TextField.ErrorMessage>}
         </Show>
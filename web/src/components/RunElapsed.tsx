@@ -1,24 +1,54 @@
 import type { RunStatus } from "@/extractor-view";
+import type { ExtractProgress } from "@/extract";
 import { Show } from "solid-js";
 
 interface RunElapsedProps {
   status: RunStatus;
   elapsedSec: number;
+  progress: ExtractProgress | null;
 }
 
 export function RunElapsed(props: RunElapsedProps) {
   const isRunning = () => props.status === "running";
+  const percent = () => {
+    const progress = props.progress;
+    if (!progress || progress.totalPages === 0) {
+      return 0;
+    }
+    return Math.min(100, Math.round((progress.pagesDone / progress.totalPages) * 100));
+  };
 
   return (
     <div class="min-h-6 sm:justify-self-end">
       <Show when={isRunning()}>
-        <p
-          class="pt-0.5 text-sm tabular-nums text-muted-fg sm:text-right"
-          role="status"
-          aria-live="polite"
-        >
-          Elapsed {props.elapsedSec}s
-        </p>
+        <div class="grid gap-1 pt-0.5 sm:justify-items-end">
+          <p
+            class="text-sm tabular-nums text-muted-fg sm:text-right"
+            role="status"
+            aria-live="polite"
+          >
+            <Show when={props.progress}>
+              {(progress) => (
+                <>
+                  Page {progress().pagesDone}/{progress().totalPages} · {progress().imagesFound}{" "}
+                  images ·{" "}
+                </>
+              )}
+            </Show>
+            Elapsed {props.elapsedSec}s
+          </p>
+          <Show when={props.progress}>
+            <div
+              class="h-1 w-full overflow-hidden rounded-full bg-muted-bg sm:w-48"
+              role="progressbar"
+              aria-valuemin={0}
+              aria-valuemax={100}
+              aria-valuenow={percent()}
+            >
+              <div class="h-full bg-accent-bg transition-all" style={{ width: `${percent()}%` }} />
+            </div>
+          </Show>
+        </div>
       </Show>
     </div>
   );
@@ -5,6 +5,7 @@ import { RuntimeErrorMessage } from "./RuntimeErrorMessage";
 import { IdleInputEmpty } from "./IdleInputEmpty";
 import { IdleInputSelected } from "./IdleInputSelected";
 import type { RunStatus } from "@/extractor-view";
+import type { ExtractProgress } from "@/extract";
 
 interface ScreenIdleProps {
   selectedFile: File | null;
@@ -14,9 +15,11 @@ interface ScreenIdleProps {
   runtimeError: string | null;
   status: RunStatus;
   elapsedSec: number;
+  progress: ExtractProgress | null;
   onFileChange: (file: File | null) => void;
   onRangeInput: (value: string) => void;
   onSubmit: (event: SubmitEvent) => void;
+  onCancel: () => void;
 }
 
 export function ScreenIdle(props: ScreenIdleProps) {
@@ -57,8 +60,16 @@ export function ScreenIdle(props: ScreenIdleProps) {
 
         <div class="grid gap-3">
           <div class="grid w-full gap-1 sm:grid-cols-[auto_1fr] sm:items-end sm:gap-3">
-            <ExtractActions canSubmit={canSubmit()} isRunning={isRunning()} />
-            <RunElapsed status={props.status} elapsedSec={props.elapsedSec} />
+            <ExtractActions
+              canSubmit={canSubmit()}
+              isRunning={isRunning()}
+              onCancel={props.onCancel}
+            />
+            <RunElapsed
+              status={props.status}
+              elapsedSec={props.elapsedSec}
+              progress={props.progress}
+            />
           </div>
           <RuntimeErrorMessage status={props.status} runtimeError={props.runtimeError} />
         </div>
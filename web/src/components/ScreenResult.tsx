@@ -22,6 +22,7 @@ interface ScreenResultProps {
 
 type DisplayWarning = {
   pageIndex: number | null;
+  imageIndex: number | null;
   kind: string;
   message: string;
 };
@@ -50,6 +51,7 @@ function normalizeWarningItem(raw: unknown): { item: DisplayWarning; repaired: b
     return {
       item: {
         pageIndex: null,
+        imageIndex: null,
         kind: "unknown",
         message: "Invalid warning payload",
       },
@@ -60,6 +62,10 @@ function normalizeWarningItem(raw: unknown): { item: DisplayWarning; repaired: b
   const item = raw as Partial<ExtractedErrorItem>;
   const normalizedPageIndex =
     Number.isInteger(item.pageIndex) && Number(item.pageIndex) >= 0 ? Number(item.pageIndex) : null;
+  const normalizedImageIndex =
+    Number.isInteger(item.imageIndex) && Number(item.imageIndex) >= 0
+      ? Number(item.imageIndex)
+      : null;
   const normalizedKind = typeof item.kind === "string" && item.kind.trim() ? item.kind : "unknown";
   const normalizedMessage =
     typeof item.message === "string" && item.message.trim() ? item.message : "Unknown warning";
@@ -72,6 +78,7 @@ function normalizeWarningItem(raw: unknown): { item: DisplayWarning; repaired: b
   return {
     item: {
       pageIndex: normalizedPageIndex,
+      imageIndex: normalizedImageIndex,
       kind: normalizedKind,
       message: normalizedMessage,
     },
@@ -1,6 +1,10 @@
 import { batch, createSignal } from "solid-js";
-import { extractImages, type ExtractedErrorItem, type ExtractedImageItem } from "./extract";
-import type { PageRange } from "./extract/page-range";
+import {
+  extractImages,
+  type ExtractedErrorItem,
+  type ExtractedImageItem,
+  type ExtractProgress,
+} from "./extract";
 
 export type RunStatus = "idle" | "running" | "success" | "error";
 
@@ -11,18 +15,41 @@ export function createExtractor() {
   const [errors, setErrors] = createSignal<ExtractedErrorItem[]>([]);
 
   const [runtimeError, setRuntimeError] = createSignal<string | null>(null);
+  const [progress, setProgress] = createSignal<ExtractProgress | null>(null);
+
+  let abortController: AbortController | null = null;
+
+  const run = async (file: File, pageSelection: string) => {
+    abortController?.abort();
+    const controller = new AbortController();
+    abortController = controller;
 
-  const run = async (file: File, pageRanges: PageRange[]) => {
     batch(() => {
       setStatus("running");
       setImages([]);
       setErrors([]);
       setRuntimeError(null);
+      setProgress(null);
     });
 
     try {
       const pdfBytes = new Uint8Array(await file.arrayBuffer());
-      const result = await extractImages(pdfBytes, pageRanges);
+      const result = await extractImages(pdfBytes, pageSelection, {
+        onProgress: (value) => {
+          if (!controller.signal.aborted) {
+            setProgress(value);
+          }
+        },
+        signal: controller.signal,
+      });
+
+      // 中断されたらエラー扱いにせず入力画面へ戻す
+      if (controller.signal.aborted) {
+        if (abortController === controller) {
+          setStatus("idle");
+        }
+        return;
+      }
 
       if (result.isErr()) {
         setRuntimeError(result.error);
@@ -38,11 +65,21 @@ export function createExtractor() {
       setImages([]);
       setErrors([]);
       setStatus("error");
+    } finally {
+      if (abortController === controller) {
+        abortController = null;
+        setProgress(null);
+      }
     }
   };
 
+  const cancel = () => {
+    abortController?.abort();
+  };
+
   const clear = () =>
     batch(() => {
+      abortController?.abort();
       setStatus("idle");
       setImages([]);
       setErrors([]);
@@ -54,7 +91,9 @@ export function createExtractor() {
     images,
     errors,
     runtimeError,
+    progress,
     run,
+    cancel,
     clear,
   };
 }
@@ -1,31 +1,58 @@
 import * as Comlink from "comlink";
-import type { PageRange } from "./page-range";
-import type { ExtractResult } from "./models";
+import type { ExtractProgress, ExtractResult } from "./models";
 import { ResultAsync } from "neverthrow";
 import { fromWorkerResult, type WorkerApi } from "./worker-interface";
 
+export const EXTRACTION_CANCELLED = "Extraction cancelled.";
+
+export type ExtractImagesOptions = {
+  onProgress?: (progress: ExtractProgress) => void;
+  signal?: AbortSignal;
+};
+
 export function extractImages(
   pdfBytes: Uint8Array,
-  pageRanges: PageRange[] = [],
+  pageSelection = "",
+  options: ExtractImagesOptions = {},
 ): ResultAsync<ExtractResult, string> {
   return ResultAsync.fromPromise(
     (async () => {
+      const { onProgress, signal } = options;
+      if (signal?.aborted) {
+        throw new Error(EXTRACTION_CANCELLED);
+      }
+
       const worker = new Worker(new URL("./worker.ts", import.meta.url), {
         type: "module",
       });
       const api = Comlink.wrap<WorkerApi>(worker);
 
+      // 実行中のWorkerはメッセージを受け取れないので、中断時はWorkerごと止める
+      let onAbort = () => {};
+      const aborted = new Promise<never>((_, reject) => {
+        onAbort = () => {
+          worker.terminate();
+          reject(new Error(EXTRACTION_CANCELLED));
+        };
+        signal?.addEventListener("abort", onAbort, { once: true });
+      });
+
       try {
-        const workerResult = await api.extractImages(
-          Comlink.transfer(pdfBytes, [pdfBytes.buffer]),
-          pageRanges,
-        );
+        const workerResult = await Promise.race([
+          api.extractImages(
+            Comlink.transfer(pdfBytes, [pdfBytes.buffer]),
+            pageSelection,
+            onProgress ? Comlink.proxy(onProgress) : undefined,
+          ),
+          aborted,
+        ]);
         const result = fromWorkerResult(workerResult);
         if (result.isErr()) {
           throw new Error(result.error);
         }
         return result.value;
       } finally {
+        signal?.removeEventListener("abort", onAbort);
         worker.terminate();
       }
     })(),
@@ -33,4 +60,9 @@ export function extractImages(
   );
 }
 
-export type { ExtractResult, ExtractedImageItem, ExtractedErrorItem } from "./models";
+export type {
+  ExtractResult,
+  ExtractProgress,
+  ExtractedImageItem,
+  ExtractedErrorItem,
+} from "./models";
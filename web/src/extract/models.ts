@@ -5,10 +5,17 @@ export type ExtractedImageItem = {
 
 export type ExtractedErrorItem = {
   pageIndex: number | null;
+  imageIndex: number | null;
   kind: string;
   message: string;
 };
 
+export type ExtractProgress = {
+  pagesDone: number;
+  totalPages: number;
+  imagesFound: number;
+};
+
 export type ExtractResult = {
   images: ExtractedImageItem[];
   errors: ExtractedErrorItem[];
@@ -16,6 +23,7 @@ export type ExtractResult = {
 
 export const UNKNOWN_ERROR_ITEM: ExtractedErrorItem = {
   pageIndex: null,
+  imageIndex: null,
   kind: "unknown",
   message: "unknown error",
 };
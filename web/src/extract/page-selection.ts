@@ -0,0 +1,26 @@
+import init, { validatePageSelection } from "extract-wasm";
+import wasmUrl from "extract-wasm/extract_wasm_bg.wasm?url";
+import { Result, ResultAsync } from "neverthrow";
+
+// 書式の検査だけなのでメインスレッドでwasmを読み込む（1回だけ）
+let initialized: Promise<unknown> | null = null;
+
+function initWasm(): ResultAsync<unknown, string> {
+  initialized ??= init({ module_or_path: wasmUrl });
+  return ResultAsync.fromPromise(initialized, () => "failed to initialize wasm module");
+}
+
+// CLIの--pagesと同じ書式。空文字はすべてのページ
+// ラベルやページ数に依存する判定は抽出時に行う
+export function checkPageSelection(input: string): ResultAsync<string, string> {
+  const text = input.trim();
+  if (text === "") {
+    return ResultAsync.fromSafePromise(Promise.resolve(text));
+  }
+  return initWasm().andThen(() =>
+    Result.fromThrowable(
+      () => validatePageSelection(text),
+      (e) => (e instanceof Error ? e.message : String(e)),
+    )().map(() => text),
+  );
+}
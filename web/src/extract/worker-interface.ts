@@ -1,9 +1,12 @@
-import type { ExtractResult } from "./models";
+import type { ExtractProgress, ExtractResult } from "./models";
 import { ok, err, type Result } from "neverthrow";
-import type { PageRange } from "./page-range";
 
 export type WorkerApi = {
-  extractImages: (pdfBytes: Uint8Array, pageRanges?: PageRange[]) => Promise<WorkerExtractResult>;
+  extractImages: (
+    pdfBytes: Uint8Array,
+    pageSelection?: string,
+    onProgress?: (progress: ExtractProgress) => void,
+  ) => Promise<WorkerExtractResult>;
 };
 
 // Workerで転送するときにResultのメソッドが剥がれてしまうので
@@ -2,11 +2,11 @@ import * as Comlink from "comlink";
 import init, { extractImages } from "extract-wasm";
 import wasmUrl from "extract-wasm/extract_wasm_bg.wasm?url";
 import { err, ok, Result, ResultAsync } from "neverthrow";
-import type { PageRange } from "./page-range";
 import {
   UNKNOWN_ERROR_ITEM,
   type ExtractedErrorItem,
   type ExtractedImageItem,
+  type ExtractProgress,
   type ExtractResult,
 } from "./models";
 import { toWorkerResult, type WorkerApi, type WorkerExtractResult } from "./worker-interface";
@@ -79,9 +79,11 @@ function parseExtractedErrorItem(value: unknown): ExtractedErrorItem {
     return { ...UNKNOWN_ERROR_ITEM };
   }
   const pageIndex = typeof errorItem.pageIndex === "number" ? errorItem.pageIndex : null;
+  const imageIndex = typeof errorItem.imageIndex === "number" ? errorItem.imageIndex : null;
 
   return {
     pageIndex,
+    imageIndex,
     kind: errorItem.kind,
     message: errorItem.message,
   };
@@ -94,24 +96,17 @@ function normalizeUnknownErrorMessage(value: unknown): string {
   return value;
 }
 
-type WasmRange = {
-  start: number;
-  end: number;
-};
-
-const extractImagesWithRanges = extractImages as unknown as (
+const extractImagesWithSelection = extractImages as unknown as (
   pdfBytes: Uint8Array,
-  ranges?: WasmRange[],
+  ranges?: string,
+  options?: Record<string, unknown>,
+  onProgress?: (progress: ExtractProgress) => void,
 ) => unknown;
 
-function toWasmRanges(pageRanges: PageRange[]): WasmRange[] | undefined {
-  if (pageRanges.length === 0) {
-    return undefined;
-  }
-  return pageRanges.map((pageRange) => ({
-    start: pageRange.start,
-    end: pageRange.end,
-  }));
+// 空文字はすべてのページ
+function toWasmSelection(pageSelection: string): string | undefined {
+  const text = pageSelection.trim();
+  return text === "" ? undefined : text;
 }
 
 function parseExtractedResult(value: Record<string, unknown>): ExtractResult {
@@ -131,15 +126,26 @@ function parseExtractedResult(value: Record<string, unknown>): ExtractResult {
 const api: WorkerApi = {
   extractImages: async (
     pdfBytes: Uint8Array,
-    pageRanges: PageRange[] = [],
+    pageSelection = "",
+    onProgress?: (progress: ExtractProgress) => void,
   ): Promise<WorkerExtractResult> => {
+    // wasmは同期的に走るので中断はメインスレッドからWorkerを止めて行う
+    const reportProgress = onProgress
+      ? (progress: ExtractProgress) => {
+          void onProgress({
+            pagesDone: progress.pagesDone,
+            totalPages: progress.totalPages,
+            imagesFound: progress.imagesFound,
+          });
+        }
+      : undefined;
     const result = await ResultAsync.fromPromise(
       init({ module_or_path: wasmUrl }),
       () => "failed to initialize wasm module",
     )
       .andThen(() =>
         Result.fromThrowable(
-          () => extractImagesWithRanges(pdfBytes, toWasmRanges(pageRanges)),
+          () => extractImagesWithSelection(pdfBytes, toWasmSelection(pageSelection), undefined, reportProgress),
           normalizeUnknownErrorMessage,
         )()
           .andThen(parseWasmResponseObject)
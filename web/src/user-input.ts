@@ -1,5 +1,4 @@
-import { ok, type Result } from "neverthrow";
-import { parsePageRanges, type PageRange } from "./extract/page-range";
+import { checkPageSelection } from "./extract/page-selection";
 import { batch, createSignal } from "solid-js";
 
 // TODO: 余裕があればきれいにする
@@ -45,14 +44,6 @@ export function createPdfField() {
   };
 }
 
-function parseRangeText(text: string): Result<PageRange[], string> {
-  const currentRangeText = text.trim();
-  if (currentRangeText === "") {
-    return ok([]);
-  }
-  return parsePageRanges(currentRangeText);
-}
-
 function isSupportedPdfFile(file: File): boolean {
   const mime = file.type.trim().toLowerCase();
   if (mime.includes("pdf")) {
@@ -68,8 +59,9 @@ export function createPageRangeField() {
   const [text, setText] = createSignal("");
   const [error, setError] = createSignal<string | null>(null);
 
-  const validateRange = () => {
-    const result = parseRangeText(text());
+  // 正しければ範囲の文字列（空文字はすべてのページ）、誤りならnull
+  const validateRange = async () => {
+    const result = await checkPageSelection(text());
     if (result.isErr()) {
       setError(result.error);
       return null;